@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct UserRole {
+    pub user_id: String,
+    pub role: String,
+    pub assigned_by: String,
+    pub assigned_at: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct AccessDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl AccessDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn role(&self, user_id: &str) -> Option<String> {
+        sqlx::query_scalar::<_, String>("SELECT role FROM user_role WHERE user_id = ?;")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap_or_else(|err| {
+                log::error!("AccessDatabase::role: {err}");
+                None
+            })
+    }
+
+    pub async fn roles(&self) -> Vec<UserRole> {
+        sqlx::query_as::<_, UserRole>(
+            "SELECT user_id, role, assigned_by, assigned_at FROM user_role ORDER BY role, user_id;",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("AccessDatabase::roles: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn assign_role(
+        &self,
+        user_id: &str,
+        role: &str,
+        assigned_by: &str,
+        assigned_at: &str,
+    ) -> bool {
+        sqlx::query(
+            r#"
+            INSERT INTO user_role (user_id, role, assigned_by, assigned_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (user_id) DO UPDATE
+                SET role = excluded.role,
+                    assigned_by = excluded.assigned_by,
+                    assigned_at = excluded.assigned_at;
+            "#,
+        )
+        .bind(user_id)
+        .bind(role)
+        .bind(assigned_by)
+        .bind(assigned_at)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .unwrap_or_else(|err| {
+            log::error!("AccessDatabase::assign_role: {err}");
+            false
+        })
+    }
+
+    pub async fn remove_role(&self, user_id: &str) -> bool {
+        sqlx::query("DELETE FROM user_role WHERE user_id = ?;")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or_else(|err| {
+                log::error!("AccessDatabase::remove_role: {err}");
+                false
+            })
+    }
+
+    /// Everyone sharing at least one team with `user_id`, themselves
+    /// included.
+    pub async fn teammates(&self, user_id: &str) -> Vec<String> {
+        sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT ?1
+            UNION
+            SELECT member.user_id
+            FROM team_member AS own
+            JOIN team_member AS member ON member.team = own.team
+            WHERE own.user_id = ?1;
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("AccessDatabase::teammates: {err}");
+            vec![user_id.to_string()]
+        })
+    }
+}
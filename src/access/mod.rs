@@ -0,0 +1,281 @@
+pub mod database;
+
+use std::str::FromStr;
+
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::Path,
+    http::{Request, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use strum_macros::{AsRefStr, Display, EnumString};
+
+use crate::{
+    audit::{self, AuditAction},
+    db::Db,
+    timekeeping::normalize_is_admin,
+    utils,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, AsRefStr, EnumString, Serialize)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE", ascii_case_insensitive)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Role {
+    Employee,
+    /// Sees the members of their own teams.
+    TeamLead,
+    /// Sees everyone, can export and maintain the directory.
+    Hr,
+    /// Sees everything, including the audit log, but changes nothing.
+    Auditor,
+    Admin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum Permission {
+    ViewOwn,
+    ViewTeam,
+    ViewAll,
+    ViewReports,
+    ViewAuditLog,
+    Export,
+    ClockForOthers,
+    ManageDirectory,
+    ManageProjects,
+    ManageConfig,
+}
+
+impl Role {
+    pub fn permissions(self) -> &'static [Permission] {
+        use Permission::*;
+
+        match self {
+            Role::Employee => &[ViewOwn],
+            Role::TeamLead => &[ViewOwn, ViewTeam],
+            Role::Hr => &[ViewOwn, ViewAll, ViewReports, Export, ManageDirectory],
+            Role::Auditor => &[ViewOwn, ViewAll, ViewReports, ViewAuditLog],
+            Role::Admin => &[
+                ViewOwn,
+                ViewTeam,
+                ViewAll,
+                ViewReports,
+                ViewAuditLog,
+                Export,
+                ClockForOthers,
+                ManageDirectory,
+                ManageProjects,
+                ManageConfig,
+            ],
+        }
+    }
+
+    pub fn can(self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+/// Whose rows a principal may read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Scope {
+    Own(String),
+    Users(Vec<String>),
+    All,
+}
+
+impl Scope {
+    pub fn allows(&self, user_id: &str) -> bool {
+        match self {
+            Scope::Own(own) => own == user_id,
+            Scope::Users(users) => users.iter().any(|user| user == user_id),
+            Scope::All => true,
+        }
+    }
+
+    /// The visible user ids as a JSON array for `json_each`, or `None` when
+    /// every row is visible.
+    pub fn visible_users(&self) -> Option<String> {
+        match self {
+            Scope::Own(own) => Some(serde_json::json!([own]).to_string()),
+            Scope::Users(users) => Some(serde_json::json!(users).to_string()),
+            Scope::All => None,
+        }
+    }
+}
+
+/// Identity as forwarded by the main web server.
+#[derive(Debug, Clone, Deserialize, Default)]
+struct Identity {
+    user_id: String,
+    #[serde(deserialize_with = "normalize_is_admin", default)]
+    is_admin: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Principal {
+    pub user_id: String,
+    pub role: Role,
+    /// Where the request came from, for the audit log.
+    pub client_ip: String,
+}
+
+impl Principal {
+    pub fn can(&self, permission: Permission) -> bool {
+        self.role.can(permission)
+    }
+
+    /// Only the principal's direct reports, or `None` when they manage
+    /// nobody.
+    pub async fn manager_scope(&self, db: &mut Db) -> Option<Scope> {
+        let reports = db.directory().direct_reports(&self.user_id).await;
+        (!reports.is_empty()).then_some(Scope::Users(reports))
+    }
+
+    pub async fn scope(&self, db: &mut Db) -> Scope {
+        if self.can(Permission::ViewAll) {
+            Scope::All
+        } else if self.can(Permission::ViewTeam) {
+            Scope::Users(db.access().teammates(&self.user_id).await)
+        } else {
+            Scope::Own(self.user_id.clone())
+        }
+    }
+}
+
+/// Resolves who is calling and checks they hold `permission`. Every route
+/// goes through here. The main server's `is_admin` flag still grants the
+/// admin role; everyone else gets the role assigned to them, or employee.
+///
+/// The request is only read up front so the returned future stays `Send`.
+pub fn authorize<'a>(
+    db: &'a mut Db,
+    request: &Request<Body>,
+    permission: Permission,
+) -> impl Future<Output = Result<Principal, (StatusCode, &'static str)>> + Send + 'a {
+    let identity = utils::extract_url_params::<Identity>(request);
+    let path = request.uri().path().to_string();
+    let client_ip = utils::client_ip(request);
+
+    async move {
+        let Ok(identity) = identity else {
+            log::error!("Failed to extract URL parameters");
+            return Err((StatusCode::BAD_REQUEST, "No parameters given."));
+        };
+
+        let role = if identity.is_admin {
+            Role::Admin
+        } else {
+            match db.access().role(&identity.user_id).await {
+                Some(role) => Role::from_str(&role).unwrap_or_else(|_| {
+                    log::error!("Unknown role {role} of {}", identity.user_id);
+                    Role::Employee
+                }),
+                None => Role::Employee,
+            }
+        };
+
+        if !role.can(permission) {
+            log::warn!(
+                "{} ({role}) was denied {permission} on {path}",
+                identity.user_id
+            );
+            return Err((StatusCode::FORBIDDEN, "You are not allowed to do that."));
+        }
+
+        Ok(Principal {
+            user_id: identity.user_id,
+            role,
+            client_ip,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NewRole {
+    user_id: String,
+    role: String,
+}
+
+pub async fn list_roles(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    if let Err(rejection) = authorize(&mut db, &request, Permission::ViewAll).await {
+        return rejection.into_response();
+    }
+
+    Json(db.access().roles().await).into_response()
+}
+
+pub async fn assign_role(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match authorize(&mut db, &request, Permission::ManageConfig).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let new = match utils::json_body::<NewRole>(request).await {
+        Ok(new) => new,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let Ok(role) = Role::from_str(&new.role) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Role must be EMPLOYEE, TEAM_LEAD, HR, AUDITOR or ADMIN.",
+        )
+            .into_response();
+    };
+    if new.user_id.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "A user is required.").into_response();
+    }
+
+    db.access()
+        .assign_role(
+            new.user_id.trim(),
+            role.as_ref(),
+            &principal.user_id,
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+        .await;
+    log::info!("{} made {} {role}", principal.user_id, new.user_id.trim());
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Update,
+        &format!("role {}", new.user_id.trim()),
+        &new,
+        1,
+    )
+    .await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+pub async fn remove_role(
+    Extension(mut db): Extension<Db>,
+    Path(user_id): Path<String>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match authorize(&mut db, &request, Permission::ManageConfig).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if db.access().remove_role(&user_id).await {
+        log::info!("{} removed the role of {user_id}", principal.user_id);
+        audit::record(
+            &mut db,
+            &principal,
+            AuditAction::Delete,
+            &format!("role {user_id}"),
+            &(),
+            1,
+        )
+        .await;
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "No role assigned.").into_response()
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use axum::{
+    Extension, Json,
+    body::Body,
+    http::{Request, StatusCode},
+    response::{Html, IntoResponse},
+};
+use chrono::{Datelike, Days, NaiveDate, NaiveTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use tera::{Context, Tera};
+
+use crate::{
+    access::{self, Permission, Principal},
+    attendance::{self, DailySummary},
+    audit::{self, AuditAction},
+    calendar,
+    config::Config,
+    db::Db,
+    utils::{self, DATE_FORMAT, parse_date},
+};
+
+const HTML_PATH: &str = "www/*.html";
+/// Days covered when no range is given, twelve weeks up to today.
+const DEFAULT_RANGE_DAYS: u64 = 84;
+const NETWORK_LIMIT: u64 = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Parameters {
+    from: Option<String>,
+    to: Option<String>,
+    team: Option<String>,
+}
+
+/// The days and users a chart covers.
+struct Range {
+    from: NaiveDate,
+    to: NaiveDate,
+    team: Option<String>,
+    /// Members of `team` as a JSON array, `None` for everyone.
+    users: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct WeeklyArrival {
+    week: String,
+    week_start: NaiveDate,
+    days: usize,
+    late_days: usize,
+    /// Mean first clock-in, local `HH:MM`.
+    average_arrival: String,
+}
+
+#[derive(Debug, Serialize)]
+struct TeamAttendance {
+    team: String,
+    members: usize,
+    /// Member working days, leave excluded.
+    expected_days: usize,
+    present_days: usize,
+    leave_days: usize,
+    /// `present_days` out of `expected_days`, in percent.
+    rate: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct WeeklyOvertime {
+    week: String,
+    week_start: NaiveDate,
+    users: usize,
+    overtime_minutes: i64,
+    overtime_hours: String,
+}
+
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Days::new(date.weekday().num_days_from_monday().into())
+}
+
+fn week_label(date: NaiveDate) -> String {
+    let week = date.iso_week();
+    format!("{}-W{:02}", week.year(), week.week())
+}
+
+async fn resolve(db: &mut Db, config: &Config, params: &Parameters) -> Result<Range, String> {
+    let today = Utc::now()
+        .with_timezone(&config.shift.offset())
+        .date_naive();
+    let to = match params.to.as_deref().filter(|to| !to.is_empty()) {
+        Some(to) => parse_date(to)?,
+        None => today,
+    };
+    let from = match params.from.as_deref().filter(|from| !from.is_empty()) {
+        Some(from) => parse_date(from)?,
+        None => to - Days::new(DEFAULT_RANGE_DAYS - 1),
+    };
+    if from > to {
+        return Err(format!("{from} is after {to}."));
+    }
+
+    let team = params.team.clone().filter(|team| !team.is_empty());
+    let users = match &team {
+        Some(team) => Some(db.directory().team_user_ids(team).await),
+        None => None,
+    };
+    Ok(Range {
+        from,
+        to,
+        team,
+        users,
+    })
+}
+
+/// UTC bounds of the range's local days.
+fn bounds(config: &Config, range: &Range) -> (String, String) {
+    let offset = config.shift.offset();
+    (
+        attendance::local_day_bounds(range.from, &offset).0,
+        attendance::local_day_bounds(range.to, &offset).1,
+    )
+}
+
+async fn summaries(db: &mut Db, config: &Config, range: &Range) -> Vec<DailySummary> {
+    let (start, end) = attendance::summary_bounds(config, range.from, range.to);
+    let mut logins = db.device_login().device_login_between(&start, &end).await;
+    if let Some(users) = &range.users {
+        let users: HashSet<String> = serde_json::from_str(users).unwrap_or_default();
+        logins.retain(|login| users.contains(&login.user_id));
+    }
+    attendance::daily_summaries(&logins, config)
+        .into_iter()
+        .filter(|summary| summary.date <= range.to)
+        .collect()
+}
+
+/// Authorizes a chart request and resolves its filters, or the response to
+/// send instead.
+async fn prepare(
+    db: &mut Db,
+    config: &Config,
+    request: Request<Body>,
+) -> Result<(Principal, Parameters, Range), axum::response::Response> {
+    let principal = access::authorize(db, &request, Permission::ViewReports)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let range = resolve(db, config, &params)
+        .await
+        .map_err(|err| (StatusCode::BAD_REQUEST, err).into_response())?;
+    Ok((principal, params, range))
+}
+
+/// Answers a chart request, recording it in the audit log.
+async fn respond<T: Serialize>(
+    db: &mut Db,
+    principal: &Principal,
+    chart: &str,
+    params: &Parameters,
+    rows: Vec<T>,
+) -> axum::response::Response {
+    audit::record(
+        db,
+        principal,
+        AuditAction::View,
+        &format!("analytics {chart}"),
+        params,
+        rows.len(),
+    )
+    .await;
+    Json(rows).into_response()
+}
+
+fn weekly_arrivals(summaries: &[DailySummary]) -> Vec<WeeklyArrival> {
+    let mut weeks: BTreeMap<NaiveDate, (usize, usize, u64)> = BTreeMap::new();
+    for summary in summaries {
+        let week = weeks.entry(week_start(summary.date)).or_default();
+        week.0 += 1;
+        week.1 += usize::from(summary.late);
+        week.2 += u64::from(summary.arrived_at.num_seconds_from_midnight());
+    }
+
+    weeks
+        .into_iter()
+        .map(|(start, (days, late_days, seconds))| {
+            let average = (seconds / days as u64) as u32;
+            WeeklyArrival {
+                week: week_label(start),
+                week_start: start,
+                days,
+                late_days,
+                average_arrival: NaiveTime::from_num_seconds_from_midnight_opt(average, 0)
+                    .unwrap_or_default()
+                    .format("%H:%M")
+                    .to_string(),
+            }
+        })
+        .collect()
+}
+
+fn weekly_overtime(summaries: &[DailySummary], overtime_after_hours: i64) -> Vec<WeeklyOvertime> {
+    let mut weeks: BTreeMap<NaiveDate, (HashSet<&str>, i64)> = BTreeMap::new();
+    for summary in summaries {
+        let week = weeks.entry(week_start(summary.date)).or_default();
+        let overtime = summary.worked_minutes - overtime_after_hours * 60;
+        if overtime > 0 {
+            week.0.insert(&summary.user_id);
+            week.1 += overtime;
+        }
+    }
+
+    weeks
+        .into_iter()
+        .map(|(start, (users, minutes))| WeeklyOvertime {
+            week: week_label(start),
+            week_start: start,
+            users: users.len(),
+            overtime_minutes: minutes,
+            overtime_hours: attendance::format_minutes(minutes),
+        })
+        .collect()
+}
+
+pub async fn handle_dashboard(
+    Extension(mut db): Extension<Db>,
+    Extension(config): Extension<Config>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    if let Err(rejection) = access::authorize(&mut db, &request, Permission::ViewReports).await {
+        return rejection.into_response();
+    }
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let range = match resolve(&mut db, &config, &params).await {
+        Ok(range) => range,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+
+    let tera = Tera::new(HTML_PATH).unwrap();
+    let mut context = Context::new();
+    context.insert("from", &range.from.format(DATE_FORMAT).to_string());
+    context.insert("to", &range.to.format(DATE_FORMAT).to_string());
+    context.insert("team", &range.team.unwrap_or_default());
+    context.insert("teams", &db.directory().teams().await);
+
+    let rendered = tera.render("analytics.html", &context).unwrap();
+    Html(rendered).into_response()
+}
+
+/// Average first clock-in per week.
+pub async fn handle_arrivals(
+    Extension(mut db): Extension<Db>,
+    Extension(config): Extension<Config>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let (principal, params, range) = match prepare(&mut db, &config, request).await {
+        Ok(prepared) => prepared,
+        Err(response) => return response,
+    };
+    let summaries = summaries(&mut db, &config, &range).await;
+    let rows = weekly_arrivals(&summaries);
+    respond(&mut db, &principal, "arrivals", &params, rows).await
+}
+
+/// Worked time beyond `overtime_after_hours` a day, per week.
+pub async fn handle_overtime(
+    Extension(mut db): Extension<Db>,
+    Extension(config): Extension<Config>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let (principal, params, range) = match prepare(&mut db, &config, request).await {
+        Ok(prepared) => prepared,
+        Err(response) => return response,
+    };
+    let summaries = summaries(&mut db, &config, &range).await;
+    let rows = weekly_overtime(&summaries, config.shift.overtime_after_hours);
+    respond(&mut db, &principal, "overtime", &params, rows).await
+}
+
+/// Share of member working days with attendance, per team. Weekends,
+/// holidays, leave and days still to come are not expected.
+pub async fn handle_attendance(
+    Extension(mut db): Extension<Db>,
+    Extension(config): Extension<Config>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let (principal, params, range) = match prepare(&mut db, &config, request).await {
+        Ok(prepared) => prepared,
+        Err(response) => return response,
+    };
+    let today = Utc::now()
+        .with_timezone(&config.shift.offset())
+        .date_naive();
+    let (from, to) = (
+        range.from.format(DATE_FORMAT).to_string(),
+        range.to.format(DATE_FORMAT).to_string(),
+    );
+
+    let holidays: HashSet<String> = db
+        .calendar()
+        .holidays(&from, &to)
+        .await
+        .into_iter()
+        .map(|holiday| holiday.date)
+        .collect();
+    let working_days: Vec<NaiveDate> = range
+        .from
+        .iter_days()
+        .take_while(|date| *date <= range.to.min(today))
+        .filter(|date| {
+            !calendar::is_weekend(*date)
+                && !holidays.contains(&date.format(DATE_FORMAT).to_string())
+        })
+        .collect();
+    let leaves = db.calendar().leaves(None, None, &from, &to).await;
+    let present: HashSet<(String, NaiveDate)> = summaries(&mut db, &config, &range)
+        .await
+        .into_iter()
+        .map(|summary| (summary.user_id, summary.date))
+        .collect();
+
+    let mut teams: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for member in db.directory().team_members().await {
+        if range.team.as_ref().is_none_or(|team| *team == member.team) {
+            teams.entry(member.team).or_default().push(member.user_id);
+        }
+    }
+    let on_leave = |user_id: &str, date: &NaiveDate| {
+        let date = date.format(DATE_FORMAT).to_string();
+        leaves.iter().any(|leave| {
+            leave.user_id == user_id && leave.start_date <= date && date <= leave.end_date
+        })
+    };
+
+    let rates: Vec<TeamAttendance> = teams
+        .into_iter()
+        .map(|(team, members)| {
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            for user_id in &members {
+                for date in &working_days {
+                    let key = if present.contains(&(user_id.clone(), *date)) {
+                        "present"
+                    } else if on_leave(user_id, date) {
+                        "leave"
+                    } else {
+                        "absent"
+                    };
+                    *counts.entry(key).or_default() += 1;
+                }
+            }
+            let present_days = counts.get("present").copied().unwrap_or(0);
+            let leave_days = counts.get("leave").copied().unwrap_or(0);
+            let expected_days = present_days + counts.get("absent").copied().unwrap_or(0);
+            TeamAttendance {
+                team,
+                members: members.len(),
+                expected_days,
+                present_days,
+                leave_days,
+                rate: if expected_days == 0 {
+                    0.0
+                } else {
+                    (present_days as f64 * 1000.0 / expected_days as f64).round() / 10.0
+                },
+            }
+        })
+        .collect();
+    respond(&mut db, &principal, "attendance", &params, rates).await
+}
+
+/// Clock-ins per location and ISP, busiest first.
+pub async fn handle_networks(
+    Extension(mut db): Extension<Db>,
+    Extension(config): Extension<Config>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let (principal, params, range) = match prepare(&mut db, &config, request).await {
+        Ok(prepared) => prepared,
+        Err(response) => return response,
+    };
+    let (start, end) = bounds(&config, &range);
+    let rows = db
+        .device_login()
+        .clock_ins_by_network(&start, &end, range.users.as_deref(), NETWORK_LIMIT)
+        .await;
+    respond(&mut db, &principal, "networks", &params, rows).await
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite};
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct LoginAnomaly {
+    pub id: i64,
+    pub login_id: i64,
+    pub user_id: String,
+    pub kind: String,
+    pub detail: String,
+    pub created_at: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct AnomalyDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl AnomalyDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn flag(
+        &self,
+        login_id: i64,
+        user_id: &str,
+        kind: &str,
+        detail: &str,
+        created_at: &str,
+    ) -> bool {
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO login_anomaly (login_id, user_id, kind, detail, created_at)
+            VALUES (?, ?, ?, ?, ?);
+            "#,
+        )
+        .bind(login_id)
+        .bind(user_id)
+        .bind(kind)
+        .bind(detail)
+        .bind(created_at)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .unwrap_or_else(|err| {
+            log::error!("AnomalyDatabase::flag: {err}");
+            false
+        })
+    }
+
+    /// Flags of the given logins, keyed by login id.
+    pub async fn for_logins(&self, login_ids: &[i64]) -> HashMap<i64, Vec<LoginAnomaly>> {
+        if login_ids.is_empty() {
+            return HashMap::new();
+        }
+
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "SELECT id, login_id, user_id, kind, detail, created_at FROM login_anomaly WHERE login_id IN (",
+        );
+        let mut ids = query.separated(", ");
+        for id in login_ids {
+            ids.push_bind(id);
+        }
+        query.push(") ORDER BY id ASC;");
+
+        let anomalies = query
+            .build_query_as::<LoginAnomaly>()
+            .fetch_all(&self.pool)
+            .await
+            .unwrap_or_else(|err| {
+                log::error!("AnomalyDatabase::for_logins: {err}");
+                Vec::new()
+            });
+
+        let mut by_login: HashMap<i64, Vec<LoginAnomaly>> = HashMap::new();
+        for anomaly in anomalies {
+            by_login.entry(anomaly.login_id).or_default().push(anomaly);
+        }
+        by_login
+    }
+
+    pub async fn anomalies(
+        &self,
+        kind: Option<&str>,
+        user_id: Option<&str>,
+        limit_per_page: u64,
+        page_number: u64,
+    ) -> Vec<LoginAnomaly> {
+        let limit = limit_per_page;
+        let offset = limit * (page_number - 1);
+
+        sqlx::query_as::<_, LoginAnomaly>(
+            r#"
+            SELECT id, login_id, user_id, kind, detail, created_at
+            FROM login_anomaly
+            WHERE (?1 IS NULL OR kind = ?1)
+              AND (?2 IS NULL OR user_id = ?2)
+            ORDER BY id DESC
+            LIMIT ?3 OFFSET ?4;
+            "#,
+        )
+        .bind(kind)
+        .bind(user_id)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("AnomalyDatabase::anomalies: {err}");
+            Vec::new()
+        })
+    }
+}
@@ -0,0 +1,250 @@
+pub mod database;
+
+#[cfg(test)]
+mod tests;
+
+use std::{str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{
+    Extension, Json,
+    body::Body,
+    http::{Request, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use strum_macros::{AsRefStr, Display, EnumString};
+
+use crate::{
+    access::{self, Permission},
+    audit::{self, AuditAction},
+    config::Config,
+    db::Db,
+    scan::{self, LoginScan},
+    scheduler::Job,
+    users::device_login::{DeviceLogin, LoginStatus},
+    utils,
+};
+
+const ANOMALIES_PER_PAGE: u64 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, AsRefStr, EnumString)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum AnomalyKind {
+    NewDevice,
+    NewCountry,
+    NewIsp,
+    ImpossibleTravel,
+    BuddyPunching,
+    UnregisteredDevice,
+    RevokedDevice,
+    ForeignDevice,
+}
+
+/// `location` is stored as `City, Region, Country`.
+fn country(location: &str) -> &str {
+    location.rsplit(',').next().unwrap_or_default().trim()
+}
+
+/// Looks at every `device_login` row once, in insertion order, and records
+/// what is suspicious about it.
+pub struct AnomalyJob {
+    config: Config,
+}
+
+pub fn jobs(config: &Config) -> Vec<Arc<dyn Job>> {
+    vec![Arc::new(AnomalyJob {
+        config: config.clone(),
+    })]
+}
+
+impl AnomalyJob {
+    async fn detect(&self, db: &mut Db, login: &DeviceLogin) -> Vec<(AnomalyKind, String)> {
+        let mut found = Vec::new();
+        if login.login_status == LoginStatus::AutoOut {
+            return found;
+        }
+        let at = login.created_at;
+
+        let (devices, locations, isps) = db
+            .device_login()
+            .known_footprint(&login.user_id, login.id)
+            .await;
+
+        // The first event of a user is the baseline, not an anomaly.
+        if !devices.is_empty() {
+            if !login.device_id.is_empty() && !devices.contains(&login.device_id) {
+                found.push((
+                    AnomalyKind::NewDevice,
+                    "first use of this device".to_string(),
+                ));
+            }
+
+            let country = country(&login.location);
+            if !country.is_empty()
+                && !locations
+                    .iter()
+                    .any(|location| self::country(location) == country)
+            {
+                found.push((
+                    AnomalyKind::NewCountry,
+                    "first event from this country".to_string(),
+                ));
+            }
+
+            if !login.isp.is_empty() && !isps.contains(&login.isp) {
+                found.push((AnomalyKind::NewIsp, "first event via this ISP".to_string()));
+            }
+        }
+
+        if let Some(previous) = db
+            .device_login()
+            .previous_login(&login.user_id, login.id)
+            .await
+        {
+            let previous_at = previous.created_at;
+            let from = country(&previous.location);
+            let to = country(&login.location);
+            let elapsed = at - previous_at;
+
+            if !from.is_empty()
+                && !to.is_empty()
+                && from != to
+                && elapsed < chrono::Duration::hours(self.config.anomaly.impossible_travel_hours)
+            {
+                found.push((
+                    AnomalyKind::ImpossibleTravel,
+                    format!("{from} to {to} in {} minutes", elapsed.num_minutes().max(0)),
+                ));
+            }
+        }
+
+        if login.login_status == LoginStatus::In && !login.device_id.is_empty() {
+            let window = chrono::Duration::minutes(self.config.anomaly.buddy_punch_minutes);
+            let others = db
+                .device_login()
+                .other_clock_ins_on_device(
+                    &login.device_id,
+                    &login.user_id,
+                    &(at - window).to_rfc3339_opts(SecondsFormat::Millis, true),
+                    &(at + window).to_rfc3339_opts(SecondsFormat::Millis, true),
+                )
+                .await;
+
+            if !others.is_empty() {
+                found.push((
+                    AnomalyKind::BuddyPunching,
+                    format!(
+                        "this device also clocked in {} within {} minutes",
+                        others.join(", "),
+                        self.config.anomaly.buddy_punch_minutes
+                    ),
+                ));
+            }
+        }
+
+        found
+    }
+}
+
+#[async_trait]
+impl Job for AnomalyJob {
+    fn name(&self) -> &'static str {
+        "detect_anomalies"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "30 * * * * *"
+    }
+
+    async fn run(&self, db: &mut Db) -> Result<String, String> {
+        let scanned = scan::run(self, db).await;
+        Ok(format!(
+            "{} events scanned, {} flagged",
+            scanned.visited, scanned.findings
+        ))
+    }
+}
+
+#[async_trait]
+impl LoginScan for AnomalyJob {
+    fn cursor(&self) -> &'static str {
+        self.name()
+    }
+
+    async fn visit(&self, db: &mut Db, login: &DeviceLogin) -> Option<u64> {
+        let mut flagged = 0;
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        for (kind, detail) in self.detect(db, login).await {
+            if db
+                .anomaly()
+                .flag(login.id, &login.user_id, kind.as_ref(), &detail, &now)
+                .await
+            {
+                log::warn!(
+                    "device_login {} of {} flagged {kind}: {detail}",
+                    login.id,
+                    login.user_id
+                );
+                flagged += 1;
+            }
+        }
+        Some(flagged)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Parameters {
+    page: Option<String>,
+    kind: Option<String>,
+    user: Option<String>,
+}
+
+pub async fn handle_anomalies(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewReports).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+
+    let kind = match params.kind.as_deref().filter(|kind| !kind.is_empty()) {
+        Some(kind) => match AnomalyKind::from_str(kind) {
+            Ok(kind) => Some(kind),
+            Err(_) => {
+                return (StatusCode::BAD_REQUEST, format!("Unknown kind {kind}.")).into_response();
+            }
+        },
+        None => None,
+    };
+    let page = params
+        .page
+        .as_deref()
+        .and_then(|page| page.parse::<u64>().ok())
+        .unwrap_or(1)
+        .max(1);
+
+    let anomalies = db
+        .anomaly()
+        .anomalies(
+            kind.as_ref().map(AsRef::as_ref),
+            params.user.as_deref().filter(|user| !user.is_empty()),
+            ANOMALIES_PER_PAGE,
+            page,
+        )
+        .await;
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::View,
+        "anomalies",
+        &params,
+        anomalies.len(),
+    )
+    .await;
+
+    Json(anomalies).into_response()
+}
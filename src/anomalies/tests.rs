@@ -0,0 +1,221 @@
+//! Replays short sequences of clock events and checks which of them are
+//! flagged as impossible travel or buddy punching.
+
+use chrono::{DateTime, Duration, Utc};
+
+use super::{AnomalyJob, AnomalyKind};
+use crate::{
+    config::Config,
+    db,
+    users::device_login::{LoginStatus, NewDeviceLogin, canonical_time},
+};
+
+/// One clock event, `minutes` after the start of the sequence.
+struct Event {
+    user_id: &'static str,
+    device_id: &'static str,
+    location: &'static str,
+    status: LoginStatus,
+    minutes: i64,
+}
+
+const fn event(
+    user_id: &'static str,
+    device_id: &'static str,
+    location: &'static str,
+    status: LoginStatus,
+    minutes: i64,
+) -> Event {
+    Event {
+        user_id,
+        device_id,
+        location,
+        status,
+        minutes,
+    }
+}
+
+const MANILA: &str = "Manila, Metro Manila, PH";
+const CEBU: &str = "Cebu City, Cebu, PH";
+const TOKYO: &str = "Tokyo, Tokyo, JP";
+
+/// A sequence and what its last event is flagged with, among the two kinds
+/// under test.
+struct Case {
+    name: &'static str,
+    events: &'static [Event],
+    flagged: &'static [(AnomalyKind, &'static str)],
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "another country within the travel window",
+        events: &[
+            event("ana", "phone-ana", MANILA, LoginStatus::In, 0),
+            event("ana", "phone-ana", TOKYO, LoginStatus::Out, 45),
+        ],
+        flagged: &[(AnomalyKind::ImpossibleTravel, "PH to JP in 45 minutes")],
+    },
+    Case {
+        name: "another country after the travel window",
+        events: &[
+            event("ana", "phone-ana", MANILA, LoginStatus::In, 0),
+            event("ana", "phone-ana", TOKYO, LoginStatus::Out, 180),
+        ],
+        flagged: &[],
+    },
+    Case {
+        name: "another city in the same country",
+        events: &[
+            event("ana", "phone-ana", MANILA, LoginStatus::In, 0),
+            event("ana", "phone-ana", CEBU, LoginStatus::Out, 5),
+        ],
+        flagged: &[],
+    },
+    Case {
+        name: "no location to travel from",
+        events: &[
+            event("ana", "phone-ana", "", LoginStatus::In, 0),
+            event("ana", "phone-ana", TOKYO, LoginStatus::Out, 5),
+        ],
+        flagged: &[],
+    },
+    Case {
+        name: "only the previous event counts",
+        events: &[
+            event("ana", "phone-ana", TOKYO, LoginStatus::In, 0),
+            event("ana", "phone-ana", MANILA, LoginStatus::Out, 300),
+            event("ana", "phone-ana", MANILA, LoginStatus::In, 320),
+        ],
+        flagged: &[],
+    },
+    Case {
+        name: "an automatic clock-out is never flagged",
+        events: &[
+            event("ana", "phone-ana", MANILA, LoginStatus::In, 0),
+            event("ana", "phone-ana", TOKYO, LoginStatus::AutoOut, 5),
+        ],
+        flagged: &[],
+    },
+    Case {
+        name: "another user clocked in on the device just before",
+        events: &[
+            event("ana", "kiosk", MANILA, LoginStatus::In, 0),
+            event("ben", "kiosk", MANILA, LoginStatus::In, 4),
+        ],
+        flagged: &[(
+            AnomalyKind::BuddyPunching,
+            "this device also clocked in ana within 10 minutes",
+        )],
+    },
+    Case {
+        name: "another user clocked in on the device just after",
+        events: &[
+            event("ana", "kiosk", MANILA, LoginStatus::In, 0),
+            event("cleo", "kiosk", MANILA, LoginStatus::In, 20),
+            event("ben", "kiosk", MANILA, LoginStatus::In, 15),
+        ],
+        flagged: &[(
+            AnomalyKind::BuddyPunching,
+            "this device also clocked in cleo within 10 minutes",
+        )],
+    },
+    Case {
+        name: "another user clocked in on the device long before",
+        events: &[
+            event("ana", "kiosk", MANILA, LoginStatus::In, 0),
+            event("ben", "kiosk", MANILA, LoginStatus::In, 11),
+        ],
+        flagged: &[],
+    },
+    Case {
+        name: "another user clocked out on the device",
+        events: &[
+            event("ana", "kiosk", MANILA, LoginStatus::Out, 0),
+            event("ben", "kiosk", MANILA, LoginStatus::In, 2),
+        ],
+        flagged: &[],
+    },
+    Case {
+        name: "only clock-ins are checked for buddies",
+        events: &[
+            event("ana", "kiosk", MANILA, LoginStatus::In, 0),
+            event("ben", "kiosk", MANILA, LoginStatus::Out, 2),
+        ],
+        flagged: &[],
+    },
+    Case {
+        name: "the same user clocking in again",
+        events: &[
+            event("ana", "kiosk", MANILA, LoginStatus::In, 0),
+            event("ana", "kiosk", MANILA, LoginStatus::In, 2),
+        ],
+        flagged: &[],
+    },
+    Case {
+        name: "another user on another device",
+        events: &[
+            event("ana", "kiosk", MANILA, LoginStatus::In, 0),
+            event("ben", "phone-ben", MANILA, LoginStatus::In, 2),
+        ],
+        flagged: &[],
+    },
+];
+
+fn login(event: &Event) -> NewDeviceLogin {
+    NewDeviceLogin {
+        user_id: event.user_id.to_string(),
+        session_id: format!("{}-session", event.user_id),
+        name: event.user_id.to_string(),
+        email: format!("{}@example.com", event.user_id),
+        device_id: event.device_id.to_string(),
+        login_provider: "google".to_string(),
+        login_status: event.status,
+        ip_address: "10.1.2.3".to_string(),
+        location: event.location.to_string(),
+        isp: "PLDT".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn travel_and_buddy_punching() {
+    let config = Config::default();
+    let job = AnomalyJob {
+        config: config.clone(),
+    };
+    let start: DateTime<Utc> = "2026-01-05T01:00:00Z".parse().unwrap();
+
+    for case in CASES {
+        let pool = db::init_db("sqlite::memory:").await;
+        let mut db = db::open(&pool, &config).await;
+        let mut last_id = 0;
+        for event in case.events {
+            let at = start + Duration::minutes(event.minutes);
+            last_id = db
+                .device_login()
+                .insert(&login(event), &canonical_time(&at))
+                .await
+                .unwrap();
+        }
+        let last = db.device_login().device_login_by_ids(&[last_id]).await;
+
+        let flagged: Vec<(AnomalyKind, String)> = job
+            .detect(&mut db, &last[0])
+            .await
+            .into_iter()
+            .filter(|(kind, _)| {
+                matches!(
+                    kind,
+                    AnomalyKind::ImpossibleTravel | AnomalyKind::BuddyPunching
+                )
+            })
+            .collect();
+        let expected: Vec<(AnomalyKind, String)> = case
+            .flagged
+            .iter()
+            .map(|(kind, detail)| (*kind, detail.to_string()))
+            .collect();
+        assert_eq!(flagged, expected, "{}", case.name);
+        pool.close().await;
+    }
+}
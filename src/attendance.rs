@@ -0,0 +1,229 @@
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Days, FixedOffset, NaiveDate, NaiveTime, SecondsFormat, TimeZone, Utc};
+use serde::Serialize;
+
+use crate::{
+    config::Config,
+    users::device_login::{DeviceLogin, LoginStatus},
+};
+
+const DISPLAY_FORMAT: &str = "%H:%M";
+
+/// One IN followed by its OUT, or an IN still waiting for one.
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub user_id: String,
+    pub name: String,
+    pub email: String,
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+    /// Ended by the system rather than by the user clocking out.
+    pub auto_closed: bool,
+    /// Minutes between BREAK_START and BREAK_END inside the session.
+    pub break_minutes: i64,
+    #[serde(skip)]
+    break_start: Option<DateTime<Utc>>,
+}
+
+impl Session {
+    /// Minutes between IN and OUT, breaks included.
+    pub fn elapsed_minutes(&self) -> i64 {
+        self.end
+            .map(|end| (end - self.start).num_minutes().max(0))
+            .unwrap_or(0)
+    }
+
+    fn end_break(&mut self, at: DateTime<Utc>) {
+        if let Some(start) = self.break_start.take() {
+            self.break_minutes += (at - start).num_minutes().max(0);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailySummary {
+    pub user_id: String,
+    pub name: String,
+    pub email: String,
+    pub date: NaiveDate,
+    pub first_in: Option<String>,
+    /// Local time of the first clock-in, for arithmetic on arrivals.
+    #[serde(skip)]
+    pub arrived_at: NaiveTime,
+    pub last_out: Option<String>,
+    /// Time on the clock less unpaid breaks.
+    pub worked_minutes: i64,
+    /// `worked_minutes` as `H:MM`, for templates.
+    pub worked_hours: String,
+    /// Recorded break time, paid or not.
+    pub break_minutes: i64,
+    /// `break_minutes` as `H:MM`, for templates.
+    pub break_hours: String,
+    /// No break was recorded on a long day so the meal break was deducted.
+    pub meal_break_deducted: bool,
+    pub open_session: bool,
+    pub auto_closed: bool,
+    pub late: bool,
+}
+
+/// UTC bounds of a local calendar day, in the RFC3339 form `created_at` is
+/// compared against.
+pub fn local_day_bounds(date: NaiveDate, offset: &FixedOffset) -> (String, String) {
+    let start = local_midnight(date, offset);
+    let end = start + Days::new(1) - chrono::Duration::milliseconds(1);
+
+    (utc_bound(start), utc_bound(end))
+}
+
+/// UTC bounds of the events behind the summaries of the local days `from`
+/// through `to`. Sessions count towards the day they started, so the end
+/// reaches past `to` by the longest a session may run, or a day when that is
+/// not limited, to take in overnight clock-outs. Summaries of the days after
+/// `to` are incomplete and left for the caller to drop.
+pub fn summary_bounds(config: &Config, from: NaiveDate, to: NaiveDate) -> (String, String) {
+    let offset = config.shift.offset();
+    let next_day = local_midnight(to + Days::new(1), &offset);
+    let end = match config.auto_close.max_session_hours {
+        Some(hours) => next_day + chrono::Duration::hours(hours),
+        None => next_day + Days::new(1),
+    } - chrono::Duration::milliseconds(1);
+
+    (utc_bound(local_midnight(from, &offset)), utc_bound(end))
+}
+
+fn local_midnight(date: NaiveDate, offset: &FixedOffset) -> DateTime<FixedOffset> {
+    offset
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0).expect("Invalid time"))
+        .single()
+        .expect("Fixed offsets are never ambiguous")
+}
+
+fn utc_bound(at: DateTime<FixedOffset>) -> String {
+    at.with_timezone(&Utc)
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Pairs IN and OUT events. `logins` must be ordered by user, then time.
+/// A repeated IN keeps the earlier one and an OUT without an IN is ignored.
+/// Breaks only count inside a session and one still running when the
+/// session ends is closed with it.
+pub fn sessions(logins: &[DeviceLogin]) -> Vec<Session> {
+    let mut sessions = Vec::new();
+    let mut open: Option<Session> = None;
+
+    for login in logins {
+        let at = login.created_at;
+
+        if let Some(session) = open.take_if(|session| session.user_id != login.user_id) {
+            sessions.push(session);
+        }
+
+        match login.login_status {
+            LoginStatus::In if open.is_none() => {
+                open = Some(Session {
+                    user_id: login.user_id.clone(),
+                    name: login.name.clone(),
+                    email: login.email.clone(),
+                    start: at,
+                    end: None,
+                    auto_closed: false,
+                    break_minutes: 0,
+                    break_start: None,
+                });
+            }
+            LoginStatus::BreakStart => {
+                if let Some(session) = open.as_mut() {
+                    session.break_start.get_or_insert(at);
+                }
+            }
+            LoginStatus::BreakEnd => {
+                if let Some(session) = open.as_mut() {
+                    session.end_break(at);
+                }
+            }
+            LoginStatus::Out | LoginStatus::AutoOut => {
+                if let Some(mut session) = open.take() {
+                    session.end_break(at);
+                    session.end = Some(at);
+                    session.auto_closed = login.login_status == LoginStatus::AutoOut;
+                    sessions.push(session);
+                }
+            }
+            _ => {}
+        }
+    }
+    sessions.extend(open);
+
+    sessions
+}
+
+/// Per user and local day totals. Sessions count towards the day they started.
+/// Breaks beyond the paid allowance are deducted, as is the meal break on
+/// long days without any.
+pub fn daily_summaries(logins: &[DeviceLogin], config: &Config) -> Vec<DailySummary> {
+    let shift = &config.shift;
+    let offset = shift.offset();
+    let mut days: BTreeMap<(String, NaiveDate), DailySummary> = BTreeMap::new();
+
+    for session in sessions(logins) {
+        let start = session.start.with_timezone(&offset);
+        let date = start.date_naive();
+        let due =
+            date.and_time(shift.start_time()) + chrono::Duration::minutes(shift.grace_minutes);
+
+        let summary = days
+            .entry((session.user_id.clone(), date))
+            .or_insert_with(|| DailySummary {
+                user_id: session.user_id.clone(),
+                name: session.name.clone(),
+                email: session.email.clone(),
+                date,
+                first_in: Some(start.format(DISPLAY_FORMAT).to_string()),
+                arrived_at: start.time(),
+                last_out: None,
+                worked_minutes: 0,
+                worked_hours: String::new(),
+                break_minutes: 0,
+                break_hours: String::new(),
+                meal_break_deducted: false,
+                open_session: false,
+                auto_closed: false,
+                late: start.naive_local() > due,
+            });
+
+        summary.worked_minutes += session.elapsed_minutes();
+        summary.break_minutes += session.break_minutes;
+        summary.auto_closed |= session.auto_closed;
+        match session.end {
+            Some(end) => {
+                summary.last_out = Some(
+                    end.with_timezone(&offset)
+                        .format(DISPLAY_FORMAT)
+                        .to_string(),
+                )
+            }
+            None => summary.open_session = true,
+        }
+    }
+
+    days.into_values()
+        .map(|mut summary| {
+            let (deducted, meal_break) = config
+                .breaks
+                .deduction(summary.worked_minutes, summary.break_minutes);
+            summary.worked_minutes -= deducted;
+            summary.meal_break_deducted = meal_break;
+            summary.worked_hours = format_minutes(summary.worked_minutes);
+            summary.break_hours = format_minutes(summary.break_minutes);
+            summary
+        })
+        .collect()
+}
+
+pub fn format_minutes(minutes: i64) -> String {
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
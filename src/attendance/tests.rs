@@ -0,0 +1,305 @@
+//! Pairs clock events into sessions and totals them per local day.
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use super::{daily_summaries, sessions, summary_bounds};
+use crate::{
+    config::{BreakConfig, Config},
+    users::device_login::{DeviceLogin, LoginStatus},
+};
+
+/// A clock event of `user_id` at a UTC `HH:MM` on 2026-01-05, or the next
+/// day past 24:00.
+fn at(user_id: &str, status: LoginStatus, time: &str) -> DeviceLogin {
+    let (hours, minutes) = time.split_once(':').unwrap();
+    let minutes = hours.parse::<i64>().unwrap() * 60 + minutes.parse::<i64>().unwrap();
+    let day: DateTime<Utc> = "2026-01-05T00:00:00Z".parse().unwrap();
+    DeviceLogin {
+        id: 0,
+        user_id: user_id.to_string(),
+        name: user_id.to_string(),
+        email: format!("{user_id}@example.com"),
+        device_id: "front-door".to_string(),
+        login_status: status,
+        ip_address: String::new(),
+        location: String::new(),
+        isp: String::new(),
+        created_at: day + chrono::Duration::minutes(minutes),
+    }
+}
+
+/// Shifts start at 09:00 local, UTC+8, with 15 minutes of grace.
+fn config() -> Config {
+    let mut config = Config::default();
+    config.shift.utc_offset_hours = 8;
+    config.breaks = BreakConfig {
+        paid_minutes: 15,
+        meal_break_after_hours: Some(6),
+        meal_break_minutes: 60,
+    };
+    config
+}
+
+/// A session's start, end, break minutes and whether it was closed
+/// automatically.
+type SessionTimes = (&'static str, Option<&'static str>, i64, bool);
+
+#[test]
+fn sessions_pair_clock_events() {
+    use LoginStatus::*;
+
+    let cases: [(&str, Vec<DeviceLogin>, Vec<SessionTimes>); 7] = [
+        (
+            "a break inside a session",
+            vec![
+                at("ana", In, "01:00"),
+                at("ana", BreakStart, "04:00"),
+                at("ana", BreakEnd, "04:45"),
+                at("ana", Out, "10:00"),
+            ],
+            vec![("01:00", Some("10:00"), 45, false)],
+        ),
+        (
+            "a break still open at OUT ends with the session",
+            vec![
+                at("ana", In, "01:00"),
+                at("ana", BreakStart, "04:00"),
+                at("ana", Out, "05:00"),
+            ],
+            vec![("01:00", Some("05:00"), 60, false)],
+        ),
+        (
+            "a repeated IN keeps the earlier one",
+            vec![
+                at("ana", In, "01:00"),
+                at("ana", In, "01:30"),
+                at("ana", Out, "09:00"),
+            ],
+            vec![("01:00", Some("09:00"), 0, false)],
+        ),
+        (
+            "an OUT without an IN is ignored",
+            vec![at("ana", Out, "01:00"), at("ana", In, "02:00")],
+            vec![("02:00", None, 0, false)],
+        ),
+        (
+            "breaks outside a session are ignored",
+            vec![
+                at("ana", BreakStart, "00:30"),
+                at("ana", BreakEnd, "00:50"),
+                at("ana", In, "01:00"),
+                at("ana", Out, "02:00"),
+            ],
+            vec![("01:00", Some("02:00"), 0, false)],
+        ),
+        (
+            "an AUTO_OUT closes the session",
+            vec![at("ana", In, "01:00"), at("ana", AutoOut, "13:00")],
+            vec![("01:00", Some("13:00"), 0, true)],
+        ),
+        (
+            "sessions do not run over into the next user",
+            vec![
+                at("ana", In, "01:00"),
+                at("ben", Out, "02:00"),
+                at("ben", In, "03:00"),
+            ],
+            vec![("01:00", None, 0, false), ("03:00", None, 0, false)],
+        ),
+    ];
+
+    for (name, logins, expected) in cases {
+        let time = |time: &str| at("", In, time).created_at;
+        let found: Vec<_> = sessions(&logins)
+            .into_iter()
+            .map(|session| {
+                (
+                    session.start,
+                    session.end,
+                    session.break_minutes,
+                    session.auto_closed,
+                )
+            })
+            .collect();
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(start, end, break_minutes, auto_closed)| {
+                (time(start), end.map(time), break_minutes, auto_closed)
+            })
+            .collect();
+        assert_eq!(found, expected, "{name}");
+    }
+}
+
+#[test]
+fn daily_summaries_deduct_breaks() {
+    use LoginStatus::*;
+
+    // Events, then the day's worked minutes, break minutes and whether the
+    // meal break was deducted.
+    let cases = [
+        (
+            "breaks within the paid allowance",
+            vec![
+                at("ana", In, "01:00"),
+                at("ana", BreakStart, "04:00"),
+                at("ana", BreakEnd, "04:15"),
+                at("ana", Out, "10:00"),
+            ],
+            (540, 15, false),
+        ),
+        (
+            "breaks past the paid allowance",
+            vec![
+                at("ana", In, "01:00"),
+                at("ana", BreakStart, "04:00"),
+                at("ana", BreakEnd, "04:45"),
+                at("ana", Out, "10:00"),
+            ],
+            (510, 45, false),
+        ),
+        (
+            "a long day without a break",
+            vec![at("ana", In, "01:00"), at("ana", Out, "09:00")],
+            (420, 0, true),
+        ),
+        (
+            "a short day without a break",
+            vec![at("ana", In, "01:00"), at("ana", Out, "07:00")],
+            (360, 0, false),
+        ),
+        (
+            "a break still open at OUT",
+            vec![
+                at("ana", In, "01:00"),
+                at("ana", BreakStart, "09:00"),
+                at("ana", Out, "10:00"),
+            ],
+            (495, 60, false),
+        ),
+        (
+            "two sessions on one day",
+            vec![
+                at("ana", In, "01:00"),
+                at("ana", Out, "04:00"),
+                at("ana", In, "05:00"),
+                at("ana", Out, "09:00"),
+            ],
+            (360, 0, true),
+        ),
+    ];
+
+    for (name, logins, expected) in cases {
+        let summaries = daily_summaries(&logins, &config());
+        assert_eq!(summaries.len(), 1, "{name}");
+        let summary = &summaries[0];
+        assert_eq!(
+            (
+                summary.worked_minutes,
+                summary.break_minutes,
+                summary.meal_break_deducted
+            ),
+            expected,
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn daily_summaries_count_sessions_towards_the_day_they_started() {
+    use LoginStatus::*;
+
+    let logins = [
+        at("ana", In, "01:20"),
+        at("ana", In, "01:40"),
+        at("ana", Out, "05:00"),
+        at("ben", In, "14:00"),
+        at("ben", Out, "27:00"),
+        at("cleo", In, "00:30"),
+        at("cleo", AutoOut, "12:30"),
+        at("dan", In, "01:00"),
+    ];
+    let summaries = daily_summaries(&logins, &config());
+    let date = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+    let found: Vec<_> = summaries
+        .iter()
+        .map(|summary| {
+            (
+                summary.user_id.as_str(),
+                summary.date,
+                summary.first_in.as_deref(),
+                summary.last_out.as_deref(),
+                summary.worked_minutes,
+                summary.late,
+                summary.open_session,
+                summary.auto_closed,
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            // Late, the repeated IN leaving the arrival as it was.
+            (
+                "ana",
+                date("2026-01-05"),
+                Some("09:20"),
+                Some("13:00"),
+                220,
+                true,
+                false,
+                false
+            ),
+            // Overnight, on the local day it started.
+            (
+                "ben",
+                date("2026-01-05"),
+                Some("22:00"),
+                Some("11:00"),
+                720,
+                true,
+                false,
+                false
+            ),
+            (
+                "cleo",
+                date("2026-01-05"),
+                Some("08:30"),
+                Some("20:30"),
+                660,
+                false,
+                false,
+                true
+            ),
+            // Still on the clock, so nothing worked yet.
+            (
+                "dan",
+                date("2026-01-05"),
+                Some("09:00"),
+                None,
+                0,
+                false,
+                true,
+                false
+            ),
+        ]
+    );
+}
+
+#[test]
+fn summary_bounds_reach_past_the_last_day() {
+    let mut config = config();
+    let day = NaiveDate::from_ymd_opt(2026, 1, 5).unwrap();
+    assert_eq!(
+        summary_bounds(&config, day, day),
+        (
+            "2026-01-04T16:00:00.000Z".to_string(),
+            "2026-01-06T15:59:59.999Z".to_string()
+        )
+    );
+    config.auto_close.max_session_hours = Some(12);
+    assert_eq!(
+        summary_bounds(&config, day, day).1,
+        "2026-01-06T03:59:59.999Z"
+    );
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor: String,
+    pub role: String,
+    pub action: String,
+    pub target: String,
+    /// The request's filter parameters or the submitted change, as JSON.
+    pub params: String,
+    pub row_count: i64,
+    pub client_ip: String,
+    pub created_at: String,
+}
+
+/// What to search the audit log for. Dates are `YYYY-MM-DD`, in UTC.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AuditFilter<'a> {
+    pub actor: Option<&'a str>,
+    pub action: Option<&'a str>,
+    pub target: Option<&'a str>,
+    pub start_date: Option<&'a str>,
+    pub end_date: Option<&'a str>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl AuditDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn insert(&self, entry: &AuditEntry) {
+        if let Err(err) = sqlx::query(
+            r#"
+            INSERT INTO audit_log (actor, role, action, target, params, row_count,
+                                   client_ip, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?);
+            "#,
+        )
+        .bind(&entry.actor)
+        .bind(&entry.role)
+        .bind(&entry.action)
+        .bind(&entry.target)
+        .bind(&entry.params)
+        .bind(entry.row_count)
+        .bind(&entry.client_ip)
+        .bind(&entry.created_at)
+        .execute(&self.pool)
+        .await
+        {
+            log::error!("AuditDatabase::insert: {err}");
+        }
+    }
+
+    pub async fn search(
+        &self,
+        filter: &AuditFilter<'_>,
+        limit_per_page: u64,
+        page_number: u64,
+    ) -> Vec<AuditEntry> {
+        let limit = limit_per_page;
+        let offset = limit * (page_number - 1);
+
+        sqlx::query_as::<_, AuditEntry>(
+            r#"
+            SELECT id, actor, role, action, target, params, row_count, client_ip, created_at
+            FROM audit_log
+            WHERE (?1 IS NULL OR actor = ?1)
+              AND (?2 IS NULL OR action = ?2)
+              AND (?3 IS NULL OR target LIKE '%' || ?3 || '%')
+              AND (?4 IS NULL OR substr(created_at, 1, 10) >= ?4)
+              AND (?5 IS NULL OR substr(created_at, 1, 10) <= ?5)
+            ORDER BY id DESC
+            LIMIT ?6 OFFSET ?7;
+            "#,
+        )
+        .bind(filter.actor)
+        .bind(filter.action)
+        .bind(filter.target)
+        .bind(filter.start_date)
+        .bind(filter.end_date)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("AuditDatabase::search: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn count(&self, filter: &AuditFilter<'_>) -> i64 {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*)
+            FROM audit_log
+            WHERE (?1 IS NULL OR actor = ?1)
+              AND (?2 IS NULL OR action = ?2)
+              AND (?3 IS NULL OR target LIKE '%' || ?3 || '%')
+              AND (?4 IS NULL OR substr(created_at, 1, 10) >= ?4)
+              AND (?5 IS NULL OR substr(created_at, 1, 10) <= ?5);
+            "#,
+        )
+        .bind(filter.actor)
+        .bind(filter.action)
+        .bind(filter.target)
+        .bind(filter.start_date)
+        .bind(filter.end_date)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("AuditDatabase::count: {err}");
+            0
+        })
+    }
+}
@@ -0,0 +1,137 @@
+pub mod database;
+
+use axum::{
+    Extension,
+    body::Body,
+    http::Request,
+    response::{Html, IntoResponse},
+};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use strum_macros::{AsRefStr, Display, EnumString};
+use tera::{Context, Tera};
+
+use crate::{
+    access::{self, Permission, Principal},
+    db::Db,
+    utils,
+};
+use database::{AuditEntry, AuditFilter};
+
+const ENTRIES_PER_PAGE: u64 = 50;
+const HTML_PATH: &str = "www/*.html";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, AsRefStr, EnumString)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE", ascii_case_insensitive)]
+pub enum AuditAction {
+    /// Read clock events, unfiltered or paged.
+    View,
+    /// Read clock events narrowed down by name, team or dates.
+    Filter,
+    Export,
+    Create,
+    Update,
+    Delete,
+}
+
+/// Appends to the audit log. `params` is whatever narrowed down or made up
+/// the request, `row_count` how many rows it returned or touched.
+pub async fn record(
+    db: &mut Db,
+    principal: &Principal,
+    action: AuditAction,
+    target: &str,
+    params: &impl Serialize,
+    row_count: usize,
+) {
+    let params = serde_json::to_string(params).unwrap_or_else(|err| {
+        log::error!("Failed to serialize audit params: {err}");
+        String::new()
+    });
+
+    db.audit()
+        .insert(&AuditEntry {
+            id: 0,
+            actor: principal.user_id.clone(),
+            role: principal.role.to_string(),
+            action: action.to_string(),
+            target: target.to_string(),
+            params,
+            row_count: row_count as i64,
+            client_ip: principal.client_ip.clone(),
+            created_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        })
+        .await;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Parameters {
+    actor: Option<String>,
+    action: Option<String>,
+    target: Option<String>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    page: Option<String>,
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+pub async fn handle_audit_log(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewAuditLog).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let filter = AuditFilter {
+        actor: non_empty(&params.actor),
+        action: non_empty(&params.action),
+        target: non_empty(&params.target),
+        start_date: non_empty(&params.start_date),
+        end_date: non_empty(&params.end_date),
+    };
+
+    let total = db.audit().count(&filter).await;
+    let total_pages = (total as f64 / ENTRIES_PER_PAGE as f64).ceil() as u64;
+    let page = params
+        .page
+        .as_deref()
+        .and_then(|page| page.parse::<u64>().ok())
+        .unwrap_or(1)
+        .clamp(1, total_pages.max(1));
+    let entries = db.audit().search(&filter, ENTRIES_PER_PAGE, page).await;
+
+    record(
+        &mut db,
+        &principal,
+        AuditAction::View,
+        "audit_log",
+        &params,
+        entries.len(),
+    )
+    .await;
+
+    let tera = Tera::new(HTML_PATH).unwrap();
+    let mut context = Context::new();
+    context.insert("entries", &entries);
+    context.insert("actor", &filter.actor.unwrap_or_default());
+    context.insert("action", &filter.action.unwrap_or_default());
+    context.insert("target", &filter.target.unwrap_or_default());
+    context.insert("start_date", &filter.start_date.unwrap_or_default());
+    context.insert("end_date", &filter.end_date.unwrap_or_default());
+    context.insert("current_page", &page);
+    context.insert("total_pages", &total_pages);
+    context.insert("next_page", &(page + 1));
+    context.insert("prev_page", &(page.saturating_sub(1)));
+    context.insert("user_id", &principal.user_id);
+
+    let rendered = tera.render("audit.html", &context).unwrap();
+    Html(rendered).into_response()
+}
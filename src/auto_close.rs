@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+
+use crate::{config::Config, db::Db, scheduler::Job};
+
+/// Closes sessions people forgot to clock out of with a synthetic
+/// `AUTO_OUT`, so they stop counting as open and show up distinctly.
+pub struct AutoCloseJob {
+    config: Config,
+}
+
+pub fn jobs(config: &Config) -> Vec<Arc<dyn Job>> {
+    if !config.auto_close.is_enabled() {
+        log::info!("Automatic closing of sessions is disabled.");
+        return Vec::new();
+    }
+
+    vec![Arc::new(AutoCloseJob {
+        config: config.clone(),
+    })]
+}
+
+impl AutoCloseJob {
+    /// When a session opened at `clocked_in` is due to be closed. The shift
+    /// end only applies if the clock-in happened before it that day.
+    fn close_at(&self, clocked_in: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let policy = &self.config.auto_close;
+        let offset = self.config.shift.offset();
+
+        let by_duration = policy
+            .max_session_hours
+            .map(|hours| clocked_in + chrono::Duration::hours(hours));
+        let by_shift_end = policy.shift_end_time().and_then(|shift_end| {
+            let local = clocked_in.with_timezone(&offset);
+            offset
+                .from_local_datetime(&local.date_naive().and_time(shift_end))
+                .single()
+                .map(|end| end.with_timezone(&Utc))
+                .filter(|end| *end > clocked_in)
+        });
+
+        match (by_duration, by_shift_end) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+#[async_trait]
+impl Job for AutoCloseJob {
+    fn name(&self) -> &'static str {
+        "auto_close_sessions"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "0 */15 * * * *"
+    }
+
+    async fn run(&self, db: &mut Db) -> Result<String, String> {
+        let now = Utc::now();
+        let open = db
+            .device_login()
+            .open_sessions_before(None, &now.to_rfc3339_opts(SecondsFormat::Millis, true))
+            .await;
+
+        let mut closed = 0;
+        for session in open {
+            let Some(close_at) = self.close_at(session.clocked_in_at).filter(|at| *at <= now)
+            else {
+                continue;
+            };
+            let login = session.login;
+
+            let close_at = close_at.to_rfc3339_opts(SecondsFormat::Millis, true);
+            if db.device_login().insert_auto_out(login.id, &close_at).await {
+                log::info!(
+                    "Closed session of {} opened by device_login {} at {close_at}",
+                    login.user_id,
+                    login.id
+                );
+                closed += 1;
+            }
+        }
+
+        Ok(format!("{closed} sessions closed"))
+    }
+}
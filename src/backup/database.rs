@@ -0,0 +1,59 @@
+use sqlx::{Pool, Sqlite, SqlitePool};
+
+#[derive(Clone, Debug)]
+pub struct BackupDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl BackupDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    /// Writes a consistent copy of the live database to `path` without
+    /// blocking writers for longer than SQLite's own read transaction.
+    /// `VACUUM INTO` stands in for SQLite's online backup API, which sqlx
+    /// does not expose: it copies from a single read transaction too, and
+    /// leaves a compacted file that opens on its own.
+    pub async fn vacuum_into(&self, path: &str) -> Result<(), String> {
+        sqlx::query("VACUUM INTO ?;")
+            .bind(path)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|err| {
+                log::error!("BackupDatabase::vacuum_into: {err}");
+                err.to_string()
+            })
+    }
+}
+
+/// Opens a snapshot read-only and checks it. Returns the number of clock
+/// events it holds, unless `count_logins` is off as they live elsewhere.
+pub async fn check_snapshot(path: &str, count_logins: bool) -> Result<Option<i64>, String> {
+    let pool = SqlitePool::connect(&format!("sqlite://{path}?mode=ro"))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let checked = async {
+        let problems = sqlx::query_scalar::<_, String>("PRAGMA integrity_check;")
+            .fetch_all(&pool)
+            .await
+            .map_err(|err| err.to_string())?;
+        if problems != ["ok"] {
+            return Err(problems.join("; "));
+        }
+        if !count_logins {
+            return Ok(None);
+        }
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM device_login;")
+            .fetch_one(&pool)
+            .await
+            .map(Some)
+            .map_err(|err| err.to_string())
+    }
+    .await;
+
+    pool.close().await;
+    checked
+}
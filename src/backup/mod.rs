@@ -0,0 +1,171 @@
+pub mod database;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::fs;
+
+use crate::{
+    config::{BackupConfig, Config},
+    db::{self, Db},
+    scheduler::Job,
+    users::device_login,
+};
+
+const SNAPSHOT_PREFIX: &str = "enzowebserver-";
+const SNAPSHOT_SUFFIX: &str = ".db";
+const POSTGRES_NOT_COVERED: &str = "clock events are kept in PostgreSQL, which snapshots do not \
+                                    cover, back it up with pg_dump";
+
+/// Snapshots the live database into the backup directory and drops the
+/// oldest snapshots beyond the configured number.
+pub struct BackupJob {
+    config: Config,
+}
+
+pub fn jobs(config: &Config) -> Vec<Arc<dyn Job>> {
+    if config.backup.keep == 0 {
+        log::info!("Database backups are disabled.");
+        return Vec::new();
+    }
+
+    vec![Arc::new(BackupJob {
+        config: config.clone(),
+    })]
+}
+
+#[async_trait]
+impl Job for BackupJob {
+    fn name(&self) -> &'static str {
+        "backup_database"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "0 0 3 * * *"
+    }
+
+    async fn run(&self, db: &mut Db) -> Result<String, String> {
+        let snapshot = snapshot(db, &self.config.backup).await?;
+        let removed = rotate(&self.config.backup).await?;
+        let mut report = format!(
+            "wrote {}, removed {removed} old snapshots",
+            snapshot.display()
+        );
+        if let Some(note) = not_covered(&self.config) {
+            report.push_str(&format!(", {note}"));
+        }
+        Ok(report)
+    }
+}
+
+/// What a snapshot of the SQLite file leaves out with this configuration.
+pub fn not_covered(config: &Config) -> Option<&'static str> {
+    device_login::is_postgres(&config.storage.device_login_url).then_some(POSTGRES_NOT_COVERED)
+}
+
+/// Writes `enzowebserver-<UTC timestamp>.db` into the backup directory.
+pub async fn snapshot(db: &mut Db, config: &BackupConfig) -> Result<PathBuf, String> {
+    fs::create_dir_all(&config.dir)
+        .await
+        .map_err(|err| format!("Failed to create {}: {err}", config.dir))?;
+
+    let path = Path::new(&config.dir).join(format!(
+        "{SNAPSHOT_PREFIX}{}{SNAPSHOT_SUFFIX}",
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    ));
+    db.backup().vacuum_into(&path.to_string_lossy()).await?;
+    log::info!("Database snapshot written to {}", path.display());
+    Ok(path)
+}
+
+/// Snapshots in the backup directory, oldest first.
+pub async fn snapshots(config: &BackupConfig) -> Result<Vec<PathBuf>, String> {
+    let mut entries = match fs::read_dir(&config.dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("Failed to read {}: {err}", config.dir)),
+    };
+
+    let mut snapshots = Vec::new();
+    while let Some(entry) = entries.next_entry().await.map_err(|err| err.to_string())? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(SNAPSHOT_PREFIX) && name.ends_with(SNAPSHOT_SUFFIX) {
+            snapshots.push(entry.path());
+        }
+    }
+    // The timestamp in the name sorts chronologically.
+    snapshots.sort();
+    Ok(snapshots)
+}
+
+/// Removes all but the newest `keep` snapshots.
+pub async fn rotate(config: &BackupConfig) -> Result<usize, String> {
+    let snapshots = snapshots(config).await?;
+    let expired = snapshots.len().saturating_sub(config.keep);
+
+    for path in &snapshots[..expired] {
+        fs::remove_file(path)
+            .await
+            .map_err(|err| format!("Failed to remove {}: {err}", path.display()))?;
+        log::info!("Removed old snapshot {}", path.display());
+    }
+    Ok(expired)
+}
+
+/// Checks a snapshot or the live file. Its clock events are only counted
+/// while they are kept in SQLite.
+pub async fn verify(path: &str, config: &Config) -> Result<String, String> {
+    if !Path::new(path).is_file() {
+        return Err(format!("{path} does not exist."));
+    }
+    let note = not_covered(config);
+    match database::check_snapshot(path, note.is_none()).await? {
+        Some(logins) => Ok(format!("{path} is intact and holds {logins} clock events.")),
+        None => Ok(format!(
+            "{path} is intact, but {}.",
+            note.unwrap_or_default()
+        )),
+    }
+}
+
+/// Replaces the live database with a verified snapshot. The service must be
+/// stopped; the current database is kept next to it as `.pre-restore-*`.
+pub async fn restore(path: &str, config: &Config) -> Result<String, String> {
+    verify(path, config).await?;
+
+    let live = db::DB_FILE;
+    if Path::new(live).exists() {
+        let kept = format!("{live}.pre-restore-{}", Utc::now().format("%Y%m%dT%H%M%SZ"));
+        fs::copy(live, &kept)
+            .await
+            .map_err(|err| format!("Failed to keep {live} as {kept}: {err}"))?;
+        log::info!("Kept the current database as {kept}");
+    }
+
+    // Copy next to the live file first so the swap itself is a rename.
+    let staged = format!("{live}.restoring");
+    fs::copy(path, &staged)
+        .await
+        .map_err(|err| format!("Failed to copy {path}: {err}"))?;
+    for journal in ["-wal", "-shm", "-journal"] {
+        let journal = format!("{live}{journal}");
+        if Path::new(&journal).exists() {
+            fs::remove_file(&journal)
+                .await
+                .map_err(|err| format!("Failed to remove {journal}: {err}"))?;
+        }
+    }
+    fs::rename(&staged, live)
+        .await
+        .map_err(|err| format!("Failed to replace {live}: {err}"))?;
+
+    log::info!("Restored {live} from {path}");
+    match not_covered(config) {
+        Some(note) => Ok(format!("Restored {live} from {path}. Note that {note}.")),
+        None => Ok(format!("Restored {live} from {path}.")),
+    }
+}
@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct Holiday {
+    /// Local calendar day, `YYYY-MM-DD`.
+    pub date: String,
+    pub name: String,
+    pub created_at: String,
+}
+
+/// Leave a user is on from `start_date` to `end_date`, both inclusive.
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct Leave {
+    pub id: i64,
+    pub user_id: String,
+    pub kind: String,
+    pub start_date: String,
+    pub end_date: String,
+    pub note: String,
+    pub created_by: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewLeave {
+    pub user_id: String,
+    pub kind: String,
+    pub start_date: String,
+    pub end_date: String,
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct CalendarDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl CalendarDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    /// Holidays between two local days, inclusive.
+    pub async fn holidays(&self, from: &str, to: &str) -> Vec<Holiday> {
+        sqlx::query_as::<_, Holiday>(
+            r#"
+            SELECT date, name, created_at
+            FROM holiday
+            WHERE date BETWEEN ? AND ?
+            ORDER BY date ASC;
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("CalendarDatabase::holidays: {err}");
+            Vec::new()
+        })
+    }
+
+    /// `false` when the day already is a holiday.
+    pub async fn add_holiday(&self, date: &str, name: &str, created_at: &str) -> bool {
+        sqlx::query(
+            r#"
+            INSERT INTO holiday (date, name, created_at)
+            VALUES (?, ?, ?)
+            ON CONFLICT (date) DO NOTHING;
+            "#,
+        )
+        .bind(date)
+        .bind(name)
+        .bind(created_at)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .unwrap_or_else(|err| {
+            log::error!("CalendarDatabase::add_holiday: {err}");
+            false
+        })
+    }
+
+    pub async fn remove_holiday(&self, date: &str) -> bool {
+        sqlx::query("DELETE FROM holiday WHERE date = ?;")
+            .bind(date)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or_else(|err| {
+                log::error!("CalendarDatabase::remove_holiday: {err}");
+                false
+            })
+    }
+
+    /// Leave overlapping two local days, inclusive. `visible_users` is a
+    /// JSON array of user ids, `None` for everyone.
+    pub async fn leaves(
+        &self,
+        visible_users: Option<&str>,
+        user_id: Option<&str>,
+        from: &str,
+        to: &str,
+    ) -> Vec<Leave> {
+        sqlx::query_as::<_, Leave>(
+            r#"
+            SELECT id, user_id, kind, start_date, end_date, note, created_by, created_at
+            FROM user_leave
+            WHERE start_date <= ?4 AND end_date >= ?3
+              AND (?1 IS NULL OR user_id IN (SELECT value FROM json_each(?1)))
+              AND (?2 IS NULL OR user_id = ?2)
+            ORDER BY start_date ASC, user_id ASC;
+            "#,
+        )
+        .bind(visible_users)
+        .bind(user_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("CalendarDatabase::leaves: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn leave(&self, id: i64) -> Option<Leave> {
+        sqlx::query_as::<_, Leave>(
+            r#"
+            SELECT id, user_id, kind, start_date, end_date, note, created_by, created_at
+            FROM user_leave
+            WHERE id = ?;
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("CalendarDatabase::leave: {err}");
+            None
+        })
+    }
+
+    pub async fn add_leave(
+        &self,
+        new: &NewLeave,
+        created_by: &str,
+        created_at: &str,
+    ) -> Option<i64> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            INSERT INTO user_leave (user_id, kind, start_date, end_date, note, created_by, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            RETURNING id;
+            "#,
+        )
+        .bind(&new.user_id)
+        .bind(&new.kind)
+        .bind(&new.start_date)
+        .bind(&new.end_date)
+        .bind(&new.note)
+        .bind(created_by)
+        .bind(created_at)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("CalendarDatabase::add_leave: {err}");
+            None
+        })
+    }
+
+    pub async fn remove_leave(&self, id: i64) -> bool {
+        sqlx::query("DELETE FROM user_leave WHERE id = ?;")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or_else(|err| {
+                log::error!("CalendarDatabase::remove_leave: {err}");
+                false
+            })
+    }
+}
@@ -0,0 +1,536 @@
+pub mod database;
+
+use std::{collections::HashMap, str::FromStr};
+
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::Path,
+    http::{Request, StatusCode},
+    response::{Html, IntoResponse},
+};
+use chrono::{Datelike, Days, Months, NaiveDate, SecondsFormat, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use strum_macros::{AsRefStr, Display, EnumString};
+use tera::{Context, Tera};
+
+use crate::{
+    access::{self, Permission, Scope},
+    attendance::{self, DailySummary},
+    audit::{self, AuditAction},
+    config::Config,
+    db::Db,
+    timekeeping::{self, LoginView},
+    utils::{self, DATE_FORMAT, parse_date},
+};
+use database::{Leave, NewLeave};
+
+const HTML_PATH: &str = "www/*.html";
+const MONTH_FORMAT: &str = "%Y-%m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, AsRefStr, EnumString)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE", ascii_case_insensitive)]
+pub enum LeaveKind {
+    Annual,
+    Sick,
+    Unpaid,
+    Other,
+}
+
+/// How a day shows on the calendar. Attendance wins over a holiday or leave
+/// on the same day, so time worked anyway is never hidden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum DayStatus {
+    Present,
+    Late,
+    Holiday,
+    Leave,
+    /// A past working day without attendance, holiday or leave.
+    Absent,
+    Weekend,
+    Upcoming,
+}
+
+#[derive(Debug, Serialize)]
+struct CalendarDay {
+    date: NaiveDate,
+    day: u32,
+    status: DayStatus,
+    /// The holiday's name or the kind of leave.
+    label: String,
+    first_in: Option<String>,
+    last_out: Option<String>,
+    worked_hours: String,
+}
+
+/// Day counts of the month by status.
+#[derive(Debug, Default, Serialize)]
+struct MonthTotals {
+    present: usize,
+    late: usize,
+    absent: usize,
+    leave: usize,
+    holiday: usize,
+    worked_hours: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Parameters {
+    /// `YYYY-MM`, this month when missing.
+    month: Option<String>,
+    user: Option<String>,
+    /// A `YYYY-MM-DD` whose clock events are listed below the calendar.
+    day: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NewHoliday {
+    date: String,
+    name: String,
+}
+
+/// First day of the `YYYY-MM` month.
+fn parse_month(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{}-01", value.trim()), DATE_FORMAT)
+        .map_err(|_| format!("{value} is not a YYYY-MM month."))
+}
+
+/// The days of the month from Monday to Sunday, padded with `None` to
+/// whole weeks.
+fn weeks(days: Vec<CalendarDay>, first: NaiveDate) -> Vec<Vec<Option<CalendarDay>>> {
+    let padding = first.weekday().num_days_from_monday() as usize;
+    let mut cells: Vec<Option<CalendarDay>> = (0..padding).map(|_| None).collect();
+    cells.extend(days.into_iter().map(Some));
+    while !cells.len().is_multiple_of(7) {
+        cells.push(None);
+    }
+
+    let mut weeks = Vec::new();
+    let mut cells = cells.into_iter().peekable();
+    while cells.peek().is_some() {
+        weeks.push(cells.by_ref().take(7).collect());
+    }
+    weeks
+}
+
+/// Saturdays and Sundays are not working days.
+pub fn is_weekend(date: NaiveDate) -> bool {
+    matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+}
+
+fn day_status(
+    date: NaiveDate,
+    today: NaiveDate,
+    summary: Option<&DailySummary>,
+    holiday: Option<&str>,
+    leave: Option<&Leave>,
+) -> (DayStatus, String) {
+    let label = holiday
+        .map(str::to_string)
+        .or_else(|| leave.map(|leave| leave.kind.clone()))
+        .unwrap_or_default();
+    let status = match summary {
+        Some(summary) if summary.late => DayStatus::Late,
+        Some(_) => DayStatus::Present,
+        None if holiday.is_some() => DayStatus::Holiday,
+        None if leave.is_some() => DayStatus::Leave,
+        None if is_weekend(date) => DayStatus::Weekend,
+        None if date >= today => DayStatus::Upcoming,
+        None => DayStatus::Absent,
+    };
+    (status, label)
+}
+
+pub async fn handle_calendar(
+    Extension(mut db): Extension<Db>,
+    Extension(config): Extension<Config>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewOwn).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let scope = principal.scope(&mut db).await;
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+
+    let offset = config.shift.offset();
+    let today = Utc::now().with_timezone(&offset).date_naive();
+    let first = match params.month.as_deref().filter(|month| !month.is_empty()) {
+        Some(month) => match parse_month(month) {
+            Ok(first) => first,
+            Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+        },
+        None => today.with_day(1).expect("Every month has a first day"),
+    };
+    let last = first + Months::new(1) - Days::new(1);
+    let selected_day = match params.day.as_deref().filter(|day| !day.is_empty()) {
+        Some(day) => match parse_date(day) {
+            Ok(day) => Some(day),
+            Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+        },
+        None => None,
+    };
+
+    let user_id = params
+        .user
+        .clone()
+        .filter(|user| !user.is_empty())
+        .unwrap_or_else(|| principal.user_id.clone());
+    if !scope.allows(&user_id) {
+        return (
+            StatusCode::FORBIDDEN,
+            "You can only see the calendars of people you manage.",
+        )
+            .into_response();
+    }
+
+    let (start, _) = attendance::local_day_bounds(first, &offset);
+    let (_, end) = attendance::local_day_bounds(last, &offset);
+    let mut logins = db.device_login().device_login_between(&start, &end).await;
+    logins.retain(|login| login.user_id == user_id);
+    let summaries: HashMap<NaiveDate, DailySummary> = attendance::daily_summaries(&logins, &config)
+        .into_iter()
+        .map(|summary| (summary.date, summary))
+        .collect();
+
+    let (from, to) = (
+        first.format(DATE_FORMAT).to_string(),
+        last.format(DATE_FORMAT).to_string(),
+    );
+    let holidays: HashMap<String, String> = db
+        .calendar()
+        .holidays(&from, &to)
+        .await
+        .into_iter()
+        .map(|holiday| (holiday.date, holiday.name))
+        .collect();
+    let leaves = db.calendar().leaves(None, Some(&user_id), &from, &to).await;
+
+    let mut totals = MonthTotals::default();
+    let mut worked_minutes = 0;
+    let days: Vec<CalendarDay> = first
+        .iter_days()
+        .take_while(|date| *date <= last)
+        .map(|date| {
+            let key = date.format(DATE_FORMAT).to_string();
+            let summary = summaries.get(&date);
+            let leave = leaves
+                .iter()
+                .find(|leave| leave.start_date <= key && key <= leave.end_date);
+            let (status, label) = day_status(
+                date,
+                today,
+                summary,
+                holidays.get(&key).map(String::as_str),
+                leave,
+            );
+            match status {
+                DayStatus::Present => totals.present += 1,
+                DayStatus::Late => {
+                    totals.present += 1;
+                    totals.late += 1;
+                }
+                DayStatus::Absent => totals.absent += 1,
+                DayStatus::Leave => totals.leave += 1,
+                DayStatus::Holiday => totals.holiday += 1,
+                DayStatus::Weekend | DayStatus::Upcoming => {}
+            }
+            worked_minutes += summary.map_or(0, |summary| summary.worked_minutes);
+
+            CalendarDay {
+                date,
+                day: date.day(),
+                status,
+                label,
+                first_in: summary.and_then(|summary| summary.first_in.clone()),
+                last_out: summary.and_then(|summary| summary.last_out.clone()),
+                worked_hours: summary
+                    .map(|summary| summary.worked_hours.clone())
+                    .unwrap_or_default(),
+            }
+        })
+        .collect();
+    totals.worked_hours = attendance::format_minutes(worked_minutes);
+
+    let events: Vec<LoginView> = match selected_day {
+        Some(day) => {
+            let (start, end) = attendance::local_day_bounds(day, &offset);
+            let mut logins = db.device_login().device_login_between(&start, &end).await;
+            logins.retain(|login| login.user_id == user_id);
+            timekeeping::views(logins, &offset)
+        }
+        None => Vec::new(),
+    };
+
+    let sees_others = !matches!(scope, Scope::Own(_));
+    let users = match scope {
+        Scope::Own(_) => Vec::new(),
+        _ => db
+            .directory()
+            .users(None)
+            .await
+            .into_iter()
+            .filter(|user| scope.allows(&user.user_id))
+            .collect(),
+    };
+
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::View,
+        &format!("calendar {user_id}"),
+        &params,
+        events.len(),
+    )
+    .await;
+
+    let tera = Tera::new(HTML_PATH).unwrap();
+    let mut context = Context::new();
+    context.insert("weeks", &weeks(days, first));
+    context.insert("totals", &totals);
+    context.insert("month", &first.format(MONTH_FORMAT).to_string());
+    context.insert("month_label", &first.format("%B %Y").to_string());
+    context.insert(
+        "prev_month",
+        &(first - Months::new(1)).format(MONTH_FORMAT).to_string(),
+    );
+    context.insert(
+        "next_month",
+        &(first + Months::new(1)).format(MONTH_FORMAT).to_string(),
+    );
+    context.insert("selected_user", &user_id);
+    context.insert("users", &users);
+    context.insert("sees_others", &sees_others);
+    context.insert(
+        "selected_day",
+        &selected_day.map(|day| day.format(DATE_FORMAT).to_string()),
+    );
+    context.insert("events", &events);
+    context.insert("user_id", &principal.user_id);
+
+    let rendered = tera.render("calendar.html", &context).unwrap();
+    Html(rendered).into_response()
+}
+
+pub async fn list_holidays(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    if let Err(rejection) = access::authorize(&mut db, &request, Permission::ViewOwn).await {
+        return rejection.into_response();
+    }
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let from = params.from.unwrap_or_default();
+    let to = params
+        .to
+        .filter(|to| !to.is_empty())
+        .unwrap_or_else(|| "9999-12-31".to_string());
+
+    Json(db.calendar().holidays(&from, &to).await).into_response()
+}
+
+pub async fn create_holiday(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageDirectory).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let new = match utils::json_body::<NewHoliday>(request).await {
+        Ok(new) => new,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let date = match parse_date(&new.date) {
+        Ok(date) => date.format(DATE_FORMAT).to_string(),
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    if new.name.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "A holiday name is required.").into_response();
+    }
+
+    if !db
+        .calendar()
+        .add_holiday(
+            &date,
+            new.name.trim(),
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+        .await
+    {
+        return (StatusCode::CONFLICT, "The day already is a holiday.").into_response();
+    }
+    log::info!("{} added holiday {date}", principal.user_id);
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Create,
+        &format!("holiday {date}"),
+        &new,
+        1,
+    )
+    .await;
+    StatusCode::CREATED.into_response()
+}
+
+pub async fn delete_holiday(
+    Extension(mut db): Extension<Db>,
+    Path(date): Path<String>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageDirectory).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if db.calendar().remove_holiday(&date).await {
+        log::info!("{} removed holiday {date}", principal.user_id);
+        audit::record(
+            &mut db,
+            &principal,
+            AuditAction::Delete,
+            &format!("holiday {date}"),
+            &(),
+            1,
+        )
+        .await;
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "No such holiday.").into_response()
+    }
+}
+
+pub async fn list_leave(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewOwn).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let scope = principal.scope(&mut db).await;
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let from = params.from.clone().unwrap_or_default();
+    let to = params
+        .to
+        .clone()
+        .filter(|to| !to.is_empty())
+        .unwrap_or_else(|| "9999-12-31".to_string());
+
+    let leaves = db
+        .calendar()
+        .leaves(
+            scope.visible_users().as_deref(),
+            params.user.as_deref().filter(|user| !user.is_empty()),
+            &from,
+            &to,
+        )
+        .await;
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::View,
+        "leave",
+        &params,
+        leaves.len(),
+    )
+    .await;
+    Json(leaves).into_response()
+}
+
+pub async fn create_leave(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageDirectory).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let new = match utils::json_body::<NewLeave>(request).await {
+        Ok(new) => new,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let kind = match LeaveKind::from_str(&new.kind) {
+        Ok(kind) => kind,
+        Err(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Unknown leave kind {}.", new.kind),
+            )
+                .into_response();
+        }
+    };
+    let (start, end) = match (parse_date(&new.start_date), parse_date(&new.end_date)) {
+        (Ok(start), Ok(end)) if start <= end => (start, end),
+        (Ok(start), Ok(end)) => {
+            return (StatusCode::BAD_REQUEST, format!("{start} is after {end}.")).into_response();
+        }
+        (Err(err), _) | (_, Err(err)) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    if new.user_id.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "A user is required.").into_response();
+    }
+    let new = NewLeave {
+        user_id: new.user_id.trim().to_string(),
+        kind: kind.to_string(),
+        start_date: start.format(DATE_FORMAT).to_string(),
+        end_date: end.format(DATE_FORMAT).to_string(),
+        note: new.note.trim().to_string(),
+    };
+
+    let Some(id) = db
+        .calendar()
+        .add_leave(
+            &new,
+            &principal.user_id,
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+        .await
+    else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to record leave.").into_response();
+    };
+    log::info!(
+        "{} recorded {kind} leave for {} from {start} to {end}",
+        principal.user_id,
+        new.user_id
+    );
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Create,
+        &format!("leave {id}"),
+        &new,
+        1,
+    )
+    .await;
+    (StatusCode::CREATED, Json(db.calendar().leave(id).await)).into_response()
+}
+
+pub async fn delete_leave(
+    Extension(mut db): Extension<Db>,
+    Path(id): Path<i64>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageDirectory).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if db.calendar().remove_leave(id).await {
+        audit::record(
+            &mut db,
+            &principal,
+            AuditAction::Delete,
+            &format!("leave {id}"),
+            &(),
+            1,
+        )
+        .await;
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "No such leave.").into_response()
+    }
+}
@@ -0,0 +1,362 @@
+use std::{fs::File, io};
+
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    access::{Principal, Role},
+    attendance,
+    audit::{self, AuditAction},
+    backup,
+    config::Config,
+    db::Db,
+    directory,
+    import::{self, ImportFormat},
+    projects,
+    users::device_login::{LoginFilter, LoginSort},
+    utils::parse_date,
+};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Time keeping server and administration tasks")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the web server and scheduled jobs (the default).
+    Serve,
+    /// Create missing tables and indexes, rewrite clock events an older
+    /// version left with a local time or lower case status and set aside
+    /// those that cannot be read, then exit. Run it once after upgrading.
+    Migrate,
+    /// Add clock events from the export's CSV or a time clock terminal's
+    /// CSV or attlog. Events already present are skipped.
+    Import {
+        file: String,
+        #[arg(long, default_value_t = ImportFormat::Export)]
+        format: ImportFormat,
+        /// Name of the terminal or system the file comes from; external
+        /// ids are mapped per source. Defaults to the format.
+        #[arg(long)]
+        source: Option<String>,
+        /// CSV of `external_id,user_id` to add to the source's id map first.
+        #[arg(long)]
+        map: Option<String>,
+        /// File to write the unmapped and invalid lines to, as CSV.
+        #[arg(long)]
+        report: Option<String>,
+    },
+    /// Write clock events in a date range as CSV.
+    Export {
+        #[arg(long, value_parser = parse_date)]
+        from: NaiveDate,
+        #[arg(long, value_parser = parse_date)]
+        to: NaiveDate,
+        #[arg(long, default_value = "")]
+        name: String,
+        #[arg(long)]
+        team: Option<String>,
+        /// File to write to instead of stdout.
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+    /// Take a snapshot of the database now and rotate old ones.
+    Backup,
+    /// Check that a snapshot is intact.
+    Verify { snapshot: String },
+    /// Replace the database with a snapshot. Stop the server first.
+    Restore { snapshot: String },
+    /// The user directory.
+    Users {
+        #[command(subcommand)]
+        command: UsersCommand,
+    },
+    /// Attendance reports.
+    Report {
+        #[command(subcommand)]
+        command: ReportCommand,
+    },
+    /// Run an integrity check on the live database.
+    CheckDb,
+    /// Clock events set aside for an unreadable time, as CSV.
+    Quarantined,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UsersCommand {
+    /// Everyone in the directory, as CSV.
+    List {
+        #[arg(long)]
+        team: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReportCommand {
+    /// Worked time per user and local day, as CSV.
+    Daily {
+        #[arg(long, value_parser = parse_date)]
+        from: NaiveDate,
+        #[arg(long, value_parser = parse_date)]
+        to: NaiveDate,
+    },
+    /// Time booked per project, task and user, as CSV.
+    Projects {
+        #[arg(long, value_parser = parse_date)]
+        from: NaiveDate,
+        #[arg(long, value_parser = parse_date)]
+        to: NaiveDate,
+        /// Only this project's id.
+        #[arg(long)]
+        project: Option<i64>,
+    },
+}
+
+/// UTC bounds covering the local days `from` through `to`.
+fn range_bounds(
+    config: &Config,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<(String, String), String> {
+    if from > to {
+        return Err(format!("--from {from} is after --to {to}."));
+    }
+    let offset = config.shift.offset();
+    let (start, _) = attendance::local_day_bounds(from, &offset);
+    let (_, end) = attendance::local_day_bounds(to, &offset);
+    Ok((start, end))
+}
+
+/// Who ran the command, for the audit log.
+fn operator() -> Principal {
+    let user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+    Principal {
+        user_id: format!("cli:{user}"),
+        role: Role::Admin,
+        client_ip: "local".to_string(),
+    }
+}
+
+fn write_csv<T: Serialize>(
+    rows: impl IntoIterator<Item = T>,
+    output: Option<&str>,
+) -> Result<(), String> {
+    let writer: Box<dyn io::Write> = match output {
+        Some(path) => {
+            Box::new(File::create(path).map_err(|err| format!("Failed to create {path}: {err}"))?)
+        }
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut writer = csv::Writer::from_writer(writer);
+    for row in rows {
+        writer.serialize(row).map_err(|err| err.to_string())?;
+    }
+    writer.flush().map_err(|err| err.to_string())
+}
+
+/// Runs a command that works on the live database. `db::init_db` has
+/// already brought the schema up to date by the time this is called.
+pub async fn run(command: Command, db: &mut Db, config: &Config) -> Result<String, String> {
+    match command {
+        Command::Import {
+            file,
+            format,
+            source,
+            map,
+            report,
+        } => {
+            let source = source.unwrap_or_else(|| format.to_string());
+            import(
+                db,
+                config,
+                &file,
+                format,
+                &source,
+                map.as_deref(),
+                report.as_deref(),
+            )
+            .await
+        }
+        Command::Export {
+            from,
+            to,
+            name,
+            team,
+            output,
+        } => {
+            export(
+                db,
+                config,
+                (from, to),
+                &name,
+                team.as_deref(),
+                output.as_deref(),
+            )
+            .await
+        }
+        Command::Backup => backup_now(db, config).await,
+        Command::Users {
+            command: UsersCommand::List { team },
+        } => list_users(db, team.as_deref()).await,
+        Command::Report {
+            command: ReportCommand::Daily { from, to },
+        } => daily_report(db, config, from, to).await,
+        Command::Report {
+            command: ReportCommand::Projects { from, to, project },
+        } => project_report(db, from, to, project).await,
+        Command::Quarantined => {
+            write_csv(db.device_login().quarantined().await, None)?;
+            Ok(String::new())
+        }
+        Command::Serve
+        | Command::Migrate
+        | Command::Verify { .. }
+        | Command::Restore { .. }
+        | Command::CheckDb => {
+            unreachable!("{command:?} does not run on an open database")
+        }
+    }
+}
+
+async fn import(
+    db: &mut Db,
+    config: &Config,
+    file: &str,
+    format: ImportFormat,
+    source: &str,
+    map: Option<&str>,
+    report_path: Option<&str>,
+) -> Result<String, String> {
+    if let Some(map) = map {
+        let mapped = import::load_id_map(db, source, map).await?;
+        log::info!("Mapped {mapped} external ids of {source} from {map}");
+    }
+    let content =
+        std::fs::read_to_string(file).map_err(|err| format!("Failed to read {file}: {err}"))?;
+
+    let report = import::import(db, format, source, &content, &config.shift.offset()).await?;
+    match report_path {
+        Some(path) => write_csv(&report.rejected, Some(path))?,
+        None => {
+            for line in &report.rejected {
+                log::warn!("{file}:{}: {} {}", line.line, line.reason, line.detail);
+            }
+        }
+    }
+
+    audit::record(
+        db,
+        &operator(),
+        AuditAction::Create,
+        "device_login",
+        &json!({
+            "file": file,
+            "format": format.to_string(),
+            "source": source,
+            "duplicates": report.duplicates,
+            "rejected": report.rejected.len(),
+        }),
+        report.imported,
+    )
+    .await;
+    Ok(report.summary())
+}
+
+async fn export(
+    db: &mut Db,
+    config: &Config,
+    (from, to): (NaiveDate, NaiveDate),
+    name: &str,
+    team: Option<&str>,
+    output: Option<&str>,
+) -> Result<String, String> {
+    let (start, end) = range_bounds(config, from, to)?;
+    let (named_users, team_members) = directory::filter_users(db, name, team).await;
+    let filter = LoginFilter {
+        name,
+        named_users: named_users.as_deref(),
+        team_members: team_members.as_deref(),
+        visible_users: None,
+        start_rfc3339: &start,
+        end_rfc3339: &end,
+        search: "",
+    };
+
+    let total = db
+        .device_login()
+        .admin_filter_login_status_by_name_and_date_count(&filter)
+        .await;
+    let logins = db
+        .device_login()
+        .admin_filter_login_status_by_name_and_date(
+            total.max(1) as u64,
+            1,
+            &filter,
+            LoginSort::default(),
+        )
+        .await;
+    write_csv(&logins, output)?;
+
+    audit::record(
+        db,
+        &operator(),
+        AuditAction::Export,
+        "timekeeping",
+        &json!({ "from": from, "to": to, "name": name, "team": team, "output": output }),
+        logins.len(),
+    )
+    .await;
+    Ok(match output {
+        Some(path) => format!("Wrote {} rows to {path}.", logins.len()),
+        None => String::new(),
+    })
+}
+
+async fn list_users(db: &mut Db, team: Option<&str>) -> Result<String, String> {
+    write_csv(db.directory().users(team).await, None)?;
+    Ok(String::new())
+}
+
+async fn daily_report(
+    db: &mut Db,
+    config: &Config,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<String, String> {
+    let (start, end) = range_bounds(config, from, to)?;
+    let logins = db.device_login().device_login_between(&start, &end).await;
+    write_csv(attendance::daily_summaries(&logins, config), None)?;
+    Ok(String::new())
+}
+
+async fn project_report(
+    db: &mut Db,
+    from: NaiveDate,
+    to: NaiveDate,
+    project: Option<i64>,
+) -> Result<String, String> {
+    if from > to {
+        return Err(format!("--from {from} is after --to {to}."));
+    }
+    write_csv(projects::report(db, project, from, to).await, None)?;
+    Ok(String::new())
+}
+
+/// Takes a snapshot right away, outside the schedule, and rotates.
+async fn backup_now(db: &mut Db, config: &Config) -> Result<String, String> {
+    let snapshot = backup::snapshot(db, &config.backup).await?;
+    let removed = backup::rotate(&config.backup).await?;
+    let mut report = format!(
+        "Wrote {}, removed {removed} old snapshots.",
+        snapshot.display()
+    );
+    if let Some(note) = backup::not_covered(config) {
+        report.push_str(&format!(" Note that {note}."));
+    }
+    Ok(report)
+}
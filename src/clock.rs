@@ -0,0 +1,105 @@
+use axum::{
+    Extension, Json,
+    body::Body,
+    http::{Request, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{SecondsFormat, Utc};
+use serde_json::json;
+
+use crate::{
+    access::{self, Permission},
+    audit::{self, AuditAction},
+    config::Config,
+    db::Db,
+    policy,
+    users::device_login::{LoginStatus, NewDeviceLogin},
+    utils,
+};
+
+/// Records a clock event submitted by the main web server. Users may only
+/// clock for themselves, admins for anyone.
+pub async fn handle_clock(
+    Extension(mut db): Extension<Db>,
+    Extension(config): Extension<Config>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewOwn).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let login = match utils::json_body::<NewDeviceLogin>(request).await {
+        Ok(login) => login,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if principal.user_id != login.user_id && !principal.can(Permission::ClockForOthers) {
+        log::warn!("{} tried to clock for {}", principal.user_id, login.user_id);
+        return (StatusCode::FORBIDDEN, "You can only clock for yourself.").into_response();
+    }
+    match login.login_status {
+        LoginStatus::In | LoginStatus::Out | LoginStatus::BreakStart | LoginStatus::BreakEnd => {}
+        LoginStatus::Corrected if principal.can(Permission::ClockForOthers) => {}
+        LoginStatus::Corrected => {
+            return (StatusCode::FORBIDDEN, "Only admins can enter corrections.").into_response();
+        }
+        LoginStatus::AutoOut => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "AUTO_OUT is only recorded by the system.",
+            )
+                .into_response();
+        }
+    }
+
+    let verdict = policy::evaluate_for_user(
+        &mut db,
+        &login.user_id,
+        &login.ip_address,
+        &login.location,
+        &login.isp,
+    )
+    .await;
+    if !verdict.compliant && config.policy.reject_non_compliant {
+        log::warn!(
+            "Rejected clock {} of {}: {}",
+            login.login_status,
+            login.user_id,
+            verdict.reason
+        );
+        return (StatusCode::FORBIDDEN, Json(verdict)).into_response();
+    }
+
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    match db.device_login().insert(&login, &now).await {
+        Ok(id) => {
+            db.policy()
+                .record_compliance(id, &login.user_id, verdict.compliant, &verdict.reason, &now)
+                .await;
+            log::info!(
+                "Clock {} of {} recorded as {id}",
+                login.login_status,
+                login.user_id
+            );
+            // Clocks are the data itself; only clocking on someone's behalf is
+            // an admin action worth auditing.
+            if principal.user_id != login.user_id {
+                audit::record(
+                    &mut db,
+                    &principal,
+                    AuditAction::Create,
+                    &format!("device_login {id}"),
+                    &json!({ "user_id": login.user_id, "login_status": login.login_status }),
+                    1,
+                )
+                .await;
+            }
+            (
+                StatusCode::CREATED,
+                Json(json!({ "id": id, "compliance": verdict })),
+            )
+                .into_response()
+        }
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err).into_response(),
+    }
+}
@@ -0,0 +1,307 @@
+#[cfg(test)]
+mod tests;
+
+use std::{collections::HashMap, path::Path};
+
+use chrono::{DateTime, FixedOffset, NaiveTime, Utc};
+use serde::Deserialize;
+
+pub const DEFAULT_CONFIG_PATH: &str = "config.json";
+
+/// Runtime settings read from `config.json` in the working directory.
+/// Every field has a default so the file and any of its keys are optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub shift: ShiftConfig,
+    pub breaks: BreakConfig,
+    pub webhook: WebhookConfig,
+    pub smtp: SmtpConfig,
+    pub notification: NotificationConfig,
+    pub scheduler: SchedulerConfig,
+    pub auto_close: AutoCloseConfig,
+    pub anomaly: AnomalyConfig,
+    pub policy: PolicyConfig,
+    pub device: DeviceConfig,
+    pub retention: RetentionConfig,
+    pub backup: BackupConfig,
+    pub storage: StorageConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ShiftConfig {
+    /// Offset of the office's local time from UTC, in hours.
+    pub utc_offset_hours: i32,
+    /// Expected clock-in time, local, formatted as `HH:MM`.
+    pub start: String,
+    /// Minutes after `start` before a clock-in counts as late.
+    pub grace_minutes: i64,
+    /// Hours after a clock-in without a clock-out before it counts as missing.
+    pub missing_clock_out_hours: i64,
+    /// Sessions whose latest event is older than this are no longer reported
+    /// as missing a clock-out, so turning reports on does not send one for
+    /// every session ever left open.
+    pub missing_clock_out_lookback_hours: i64,
+    /// Worked hours per day beyond which time counts as overtime.
+    pub overtime_after_hours: i64,
+}
+
+/// How recorded breaks count against worked time.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BreakConfig {
+    /// Minutes of recorded break per day that are still paid.
+    pub paid_minutes: i64,
+    /// Days worked longer than this without any recorded break get
+    /// `meal_break_minutes` deducted. Unset disables the deduction.
+    pub meal_break_after_hours: Option<i64>,
+    pub meal_break_minutes: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub poll_interval_secs: u64,
+    pub request_timeout_secs: u64,
+    pub max_attempts: i64,
+    pub backoff_base_secs: i64,
+    pub backoff_max_secs: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SmtpConfig {
+    /// Email is disabled while this is empty.
+    pub host: String,
+    pub port: u16,
+    /// `none` for a local sink, `starttls` or `tls` for a real relay.
+    pub security: String,
+    pub username: String,
+    pub password: String,
+    pub from: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    pub admin_emails: Vec<String>,
+}
+
+/// Cron expressions (`sec min hour day month weekday`, local time) keyed by
+/// job name. Jobs missing here keep their default schedule, an empty
+/// expression disables the job.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    pub jobs: HashMap<String, String>,
+}
+
+/// When open sessions get a synthetic `AUTO_OUT`. Whichever limit is reached
+/// first applies; with neither set sessions are never closed automatically.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AutoCloseConfig {
+    pub max_session_hours: Option<i64>,
+    /// Local time, `HH:MM`, on the day of the clock-in.
+    pub shift_end: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AnomalyConfig {
+    /// A change of country faster than this is flagged as impossible travel.
+    pub impossible_travel_hours: i64,
+    /// Clock-ins by different users on one device this close together are
+    /// flagged as buddy punching.
+    pub buddy_punch_minutes: i64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    /// Refuse clock events submitted through the service that break a
+    /// network policy instead of only marking them.
+    pub reject_non_compliant: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DeviceConfig {
+    /// Register a device as active for whoever uses it first instead of
+    /// waiting for an admin to approve it.
+    pub trust_on_first_use: bool,
+}
+
+/// How long clock events keep personal data. Statuses and timestamps stay
+/// until the events are deleted; with neither limit set nothing is purged.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Days after which IP addresses are masked to their network and ISP
+    /// and location are dropped.
+    pub anonymize_after_days: Option<i64>,
+    /// Days after which events are deleted outright.
+    pub delete_after_days: Option<i64>,
+    /// Only report what a run would purge.
+    pub dry_run: bool,
+}
+
+/// Where scheduled snapshots of the database go and how many are kept.
+/// Keeping none disables them.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub dir: String,
+    pub keep: usize,
+}
+
+/// Where clock events are kept. Everything else stays in the SQLite file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// A `postgres://` URL, or empty for the SQLite file.
+    pub device_login_url: String,
+}
+
+impl Default for ShiftConfig {
+    fn default() -> Self {
+        Self {
+            utc_offset_hours: 8,
+            start: "09:00".to_string(),
+            grace_minutes: 15,
+            missing_clock_out_hours: 12,
+            missing_clock_out_lookback_hours: 48,
+            overtime_after_hours: 8,
+        }
+    }
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: 10,
+            request_timeout_secs: 10,
+            max_attempts: 8,
+            backoff_base_secs: 30,
+            backoff_max_secs: 3600,
+        }
+    }
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 25,
+            security: "none".to_string(),
+            username: String::new(),
+            password: String::new(),
+            from: "timekeeping@localhost".to_string(),
+        }
+    }
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            dir: "backups".to_string(),
+            keep: 7,
+        }
+    }
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            impossible_travel_hours: 2,
+            buddy_punch_minutes: 10,
+        }
+    }
+}
+
+impl ShiftConfig {
+    pub fn offset(&self) -> FixedOffset {
+        FixedOffset::east_opt(self.utc_offset_hours * 3600).unwrap_or_else(|| {
+            log::error!("Invalid utc_offset_hours {}", self.utc_offset_hours);
+            FixedOffset::east_opt(0).expect("Invalid offset")
+        })
+    }
+
+    /// Range the latest event of a session must fall in, as of `now`, for
+    /// it to be reported as missing a clock-out.
+    pub fn missing_clock_out_window(&self, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let cutoff = now - chrono::Duration::hours(self.missing_clock_out_hours);
+        let oldest = cutoff - chrono::Duration::hours(self.missing_clock_out_lookback_hours);
+        (oldest, cutoff)
+    }
+
+    pub fn start_time(&self) -> NaiveTime {
+        NaiveTime::parse_from_str(&self.start, "%H:%M").unwrap_or_else(|err| {
+            log::error!("Invalid shift start {}: {err}", self.start);
+            NaiveTime::from_hms_opt(9, 0, 0).expect("Invalid time")
+        })
+    }
+}
+
+impl AutoCloseConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.max_session_hours.is_some() || self.shift_end.is_some()
+    }
+
+    pub fn shift_end_time(&self) -> Option<NaiveTime> {
+        let shift_end = self.shift_end.as_ref()?;
+        NaiveTime::parse_from_str(shift_end, "%H:%M")
+            .inspect_err(|err| log::error!("Invalid shift end {shift_end}: {err}"))
+            .ok()
+    }
+}
+
+impl BreakConfig {
+    /// Minutes to deduct from `elapsed` minutes on the clock given `recorded`
+    /// minutes of break, and whether the meal break was applied.
+    pub fn deduction(&self, elapsed: i64, recorded: i64) -> (i64, bool) {
+        if recorded > 0 {
+            return ((recorded - self.paid_minutes).max(0), false);
+        }
+        match self.meal_break_after_hours {
+            Some(hours) if elapsed > hours * 60 => (self.meal_break_minutes.min(elapsed), true),
+            _ => (0, false),
+        }
+    }
+}
+
+impl RetentionConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.anonymize_after_days.is_some() || self.delete_after_days.is_some()
+    }
+}
+
+impl SchedulerConfig {
+    pub fn schedule_for(&self, job: &str, default: &str) -> Option<String> {
+        let expression = self
+            .jobs
+            .get(job)
+            .map(String::as_str)
+            .unwrap_or(default)
+            .trim();
+
+        (!expression.is_empty()).then(|| expression.to_string())
+    }
+}
+
+/// The configuration in `path`, or the defaults when there is none. A file
+/// that cannot be read or parsed is an error rather than silently ignored.
+pub fn load(path: &str) -> Result<Config, String> {
+    if !Path::new(path).exists() {
+        log::info!("No {path} found, using default configuration.");
+        return Ok(Config::default());
+    }
+
+    let config = std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| serde_json::from_str::<Config>(&contents).map_err(|e| e.to_string()))
+        .map_err(|err| format!("Failed to load {path}: {err}"))?;
+    log::info!("Loaded configuration from {path}");
+    Ok(config)
+}
@@ -0,0 +1,52 @@
+//! How recorded breaks and the meal break count against worked time.
+
+use super::BreakConfig;
+
+const BREAKS: BreakConfig = BreakConfig {
+    paid_minutes: 15,
+    meal_break_after_hours: Some(6),
+    meal_break_minutes: 60,
+};
+
+#[test]
+fn deduction() {
+    // (elapsed, recorded) and the expected (deducted, meal break applied).
+    let cases = [
+        // Recorded breaks beyond the paid allowance are deducted.
+        ((540, 45), (30, false)),
+        ((540, 15), (0, false)),
+        ((540, 10), (0, false)),
+        // Any recorded break replaces the meal break, however short.
+        ((600, 5), (0, false)),
+        // Without a break, only days past the threshold lose the meal break.
+        ((360, 0), (0, false)),
+        ((361, 0), (60, true)),
+        ((240, 0), (0, false)),
+    ];
+    for ((elapsed, recorded), expected) in cases {
+        assert_eq!(
+            BREAKS.deduction(elapsed, recorded),
+            expected,
+            "{elapsed} {recorded}"
+        );
+    }
+}
+
+#[test]
+fn meal_break_never_exceeds_the_day() {
+    let breaks = BreakConfig {
+        meal_break_after_hours: Some(0),
+        ..BREAKS
+    };
+    assert_eq!(breaks.deduction(30, 0), (30, true));
+}
+
+#[test]
+fn meal_break_can_be_disabled() {
+    let breaks = BreakConfig {
+        meal_break_after_hours: None,
+        ..BREAKS
+    };
+    assert_eq!(breaks.deduction(720, 0), (0, false));
+    assert_eq!(BreakConfig::default().deduction(720, 30), (30, false));
+}
@@ -1,6 +1,33 @@
+use std::sync::Arc;
+
 use sqlx::{Pool, Sqlite, SqlitePool};
 
-use crate::users::device_login::DeviceLoginDatabase;
+use crate::{
+    access::database::AccessDatabase,
+    anomalies::database::AnomalyDatabase,
+    audit::database::AuditDatabase,
+    backup::database::BackupDatabase,
+    calendar::database::CalendarDatabase,
+    config::Config,
+    devices::database::DeviceDatabase,
+    directory::database::DirectoryDatabase,
+    import::database::ImportDatabase,
+    notifications::database::NotificationDatabase,
+    policy::database::PolicyDatabase,
+    privacy::database::PrivacyDatabase,
+    projects::database::ProjectDatabase,
+    retention::database::RetentionDatabase,
+    scan::database::ScanDatabase,
+    scheduler::database::JobRunDatabase,
+    users::{
+        device_login::{self, DeviceLoginStore},
+        sqlite,
+    },
+    webhooks::database::WebhookDatabase,
+};
+
+/// The database file, relative to the working directory.
+pub const DB_FILE: &str = "enzowebserver.db";
 
 const INIT_DB: &str = r#"
 CREATE TABLE IF NOT EXISTS device_login (
@@ -14,45 +41,578 @@ CREATE TABLE IF NOT EXISTS device_login (
                     ip_address TEXT,
                     location TEXT,
                     isp TEXT,
-                    created_at TEXT
+                    created_at TEXT,
+                    anonymized_at TEXT
+                );
+CREATE TABLE IF NOT EXISTS device_login_quarantine (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    login_id INTEGER NOT NULL,
+                    user_id TEXT,
+                    session_id TEXT,
+                    name TEXT,
+                    email TEXT,
+                    device_id TEXT,
+                    login_provider TEXT,
+                    login_status TEXT,
+                    ip_address TEXT,
+                    location TEXT,
+                    isp TEXT,
+                    created_at TEXT,
+                    quarantined_at TEXT NOT NULL
+                );
+CREATE TRIGGER IF NOT EXISTS device_login_canonical_time AFTER INSERT ON device_login
+BEGIN
+    SELECT RAISE(ABORT, 'created_at is not a time')
+    WHERE strftime('%Y-%m-%dT%H:%M:%fZ', NEW.created_at) IS NULL;
+    UPDATE device_login SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', NEW.created_at)
+    WHERE rowid = NEW.rowid AND created_at IS NOT strftime('%Y-%m-%dT%H:%M:%fZ', NEW.created_at);
+END;
+CREATE TRIGGER IF NOT EXISTS device_login_canonical_status AFTER INSERT ON device_login
+BEGIN
+    SELECT RAISE(ABORT, 'login_status is not a known status')
+    WHERE NEW.login_status IS NULL
+       OR upper(trim(NEW.login_status)) NOT IN ('IN', 'OUT', 'BREAK_START', 'BREAK_END',
+                                                'AUTO_OUT', 'CORRECTED');
+    UPDATE device_login SET login_status = upper(trim(NEW.login_status))
+    WHERE rowid = NEW.rowid AND login_status IS NOT upper(trim(NEW.login_status));
+END;
+CREATE TABLE IF NOT EXISTS scan_cursor (
+                    name TEXT PRIMARY KEY,
+                    last_login_id INTEGER NOT NULL
+                );
+"#;
+
+const INIT_WEBHOOK_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS webhook_subscription (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    url TEXT NOT NULL,
+                    secret TEXT NOT NULL,
+                    events TEXT NOT NULL,
+                    active INTEGER NOT NULL DEFAULT 1,
+                    created_at TEXT NOT NULL
+                );
+CREATE TABLE IF NOT EXISTS webhook_delivery (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    subscription_id INTEGER NOT NULL REFERENCES webhook_subscription(id),
+                    event TEXT NOT NULL,
+                    dedupe_key TEXT NOT NULL,
+                    payload TEXT NOT NULL,
+                    status TEXT NOT NULL,
+                    attempts INTEGER NOT NULL DEFAULT 0,
+                    next_attempt_at TEXT NOT NULL,
+                    created_at TEXT NOT NULL,
+                    delivered_at TEXT,
+                    UNIQUE (subscription_id, event, dedupe_key)
+                );
+CREATE INDEX IF NOT EXISTS webhook_delivery_due
+                    ON webhook_delivery (status, next_attempt_at);
+CREATE TABLE IF NOT EXISTS webhook_attempt (
+                    delivery_id INTEGER NOT NULL REFERENCES webhook_delivery(id),
+                    attempt INTEGER NOT NULL,
+                    response_code INTEGER,
+                    error TEXT,
+                    attempted_at TEXT NOT NULL
+                );
+"#;
+
+const INIT_NOTIFICATION_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS notification_log (
+                    kind TEXT NOT NULL,
+                    dedupe_key TEXT NOT NULL,
+                    recipient TEXT NOT NULL,
+                    sent_at TEXT NOT NULL,
+                    PRIMARY KEY (kind, dedupe_key, recipient)
+                );
+"#;
+
+const INIT_JOB_RUN_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS job_run (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    job TEXT NOT NULL,
+                    status TEXT NOT NULL,
+                    message TEXT,
+                    started_at TEXT NOT NULL,
+                    finished_at TEXT
+                );
+"#;
+
+const INIT_ANOMALY_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS login_anomaly (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    login_id INTEGER NOT NULL,
+                    user_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    detail TEXT NOT NULL,
+                    created_at TEXT NOT NULL,
+                    UNIQUE (login_id, kind)
+                );
+CREATE INDEX IF NOT EXISTS login_anomaly_user ON login_anomaly (user_id);
+"#;
+
+const INIT_POLICY_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS network_policy (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    scope TEXT NOT NULL,
+                    scope_value TEXT NOT NULL,
+                    rule_type TEXT NOT NULL,
+                    value TEXT NOT NULL,
+                    created_at TEXT NOT NULL
+                );
+CREATE TABLE IF NOT EXISTS policy_group_member (
+                    group_name TEXT NOT NULL,
+                    user_id TEXT NOT NULL,
+                    PRIMARY KEY (group_name, user_id)
+                );
+CREATE TABLE IF NOT EXISTS login_compliance (
+                    login_id INTEGER PRIMARY KEY,
+                    user_id TEXT NOT NULL,
+                    compliant INTEGER NOT NULL,
+                    reason TEXT NOT NULL,
+                    evaluated_at TEXT NOT NULL
                 );
 "#;
 
+const INIT_DEVICE_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS device (
+                    device_id TEXT PRIMARY KEY,
+                    user_id TEXT NOT NULL,
+                    label TEXT NOT NULL DEFAULT '',
+                    first_seen TEXT NOT NULL,
+                    last_seen TEXT NOT NULL,
+                    status TEXT NOT NULL
+                );
+"#;
+
+const INIT_DIRECTORY_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS app_user (
+                    user_id TEXT PRIMARY KEY,
+                    name TEXT NOT NULL DEFAULT '',
+                    email TEXT NOT NULL DEFAULT '',
+                    department TEXT NOT NULL DEFAULT '',
+                    manager_id TEXT,
+                    employment_status TEXT NOT NULL DEFAULT 'ACTIVE',
+                    hire_date TEXT,
+                    termination_date TEXT,
+                    first_seen TEXT NOT NULL,
+                    last_seen TEXT NOT NULL
+                );
+CREATE TABLE IF NOT EXISTS team (
+                    name TEXT PRIMARY KEY,
+                    department TEXT NOT NULL DEFAULT '',
+                    created_at TEXT NOT NULL
+                );
+CREATE TABLE IF NOT EXISTS team_member (
+                    team TEXT NOT NULL REFERENCES team(name),
+                    user_id TEXT NOT NULL,
+                    PRIMARY KEY (team, user_id)
+                );
+CREATE INDEX IF NOT EXISTS team_member_user ON team_member (user_id);
+CREATE TABLE IF NOT EXISTS reporting_line (
+                    manager_id TEXT NOT NULL,
+                    report_id TEXT NOT NULL,
+                    created_at TEXT NOT NULL,
+                    PRIMARY KEY (manager_id, report_id)
+                );
+CREATE INDEX IF NOT EXISTS reporting_line_report ON reporting_line (report_id);
+"#;
+
+const INIT_ACCESS_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS user_role (
+                    user_id TEXT PRIMARY KEY,
+                    role TEXT NOT NULL,
+                    assigned_by TEXT NOT NULL,
+                    assigned_at TEXT NOT NULL
+                );
+"#;
+
+/// Rows are only ever inserted; the triggers refuse to change history.
+const INIT_AUDIT_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS audit_log (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    actor TEXT NOT NULL,
+                    role TEXT NOT NULL,
+                    action TEXT NOT NULL,
+                    target TEXT NOT NULL,
+                    params TEXT NOT NULL,
+                    row_count INTEGER NOT NULL,
+                    client_ip TEXT NOT NULL,
+                    created_at TEXT NOT NULL
+                );
+CREATE INDEX IF NOT EXISTS audit_log_actor ON audit_log (actor, created_at);
+CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
+CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'audit_log is append-only');
+END;
+"#;
+
+/// The index also backs the duplicate check of imports.
+const INIT_IMPORT_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS import_id_map (
+                    source TEXT NOT NULL,
+                    external_id TEXT NOT NULL,
+                    user_id TEXT NOT NULL,
+                    PRIMARY KEY (source, external_id)
+                );
+CREATE INDEX IF NOT EXISTS device_login_user_time ON device_login (user_id, created_at);
+"#;
+
+/// Bookings hold local calendar days, the day a session started.
+const INIT_PROJECT_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS project (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    code TEXT NOT NULL UNIQUE,
+                    name TEXT NOT NULL,
+                    client TEXT NOT NULL DEFAULT '',
+                    active INTEGER NOT NULL DEFAULT 1,
+                    created_at TEXT NOT NULL
+                );
+CREATE TABLE IF NOT EXISTS project_task (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    project_id INTEGER NOT NULL REFERENCES project(id),
+                    name TEXT NOT NULL,
+                    created_at TEXT NOT NULL,
+                    UNIQUE (project_id, name)
+                );
+CREATE TABLE IF NOT EXISTS time_allocation (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    user_id TEXT NOT NULL,
+                    date TEXT NOT NULL,
+                    project_id INTEGER NOT NULL REFERENCES project(id),
+                    task_id INTEGER REFERENCES project_task(id),
+                    minutes INTEGER NOT NULL CHECK (minutes > 0),
+                    note TEXT NOT NULL DEFAULT '',
+                    created_by TEXT NOT NULL,
+                    created_at TEXT NOT NULL
+                );
+CREATE INDEX IF NOT EXISTS time_allocation_user_date ON time_allocation (user_id, date);
+CREATE INDEX IF NOT EXISTS time_allocation_project_date ON time_allocation (project_id, date);
+"#;
+
+/// Days are local calendar days, leave covers `start_date` to `end_date`
+/// inclusive.
+const INIT_CALENDAR_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS holiday (
+                    date TEXT PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    created_at TEXT NOT NULL
+                );
+CREATE TABLE IF NOT EXISTS user_leave (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    user_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    start_date TEXT NOT NULL,
+                    end_date TEXT NOT NULL,
+                    note TEXT NOT NULL DEFAULT '',
+                    created_by TEXT NOT NULL,
+                    created_at TEXT NOT NULL
+                );
+CREATE INDEX IF NOT EXISTS user_leave_user_dates ON user_leave (user_id, start_date, end_date);
+"#;
+
 pub async fn init_db(db_url: &str) -> Pool<Sqlite> {
     // Create a connection pool
     let pool = SqlitePool::connect(db_url)
         .await
         .expect("Failed to connect to database");
 
-    // Create the table if it doesn't exist
-    sqlx::query(INIT_DB)
-        .execute(&pool)
-        .await
-        .expect("Failed to create table");
+    // Create the tables if they don't exist
+    for init in [
+        INIT_DB,
+        INIT_WEBHOOK_DB,
+        INIT_NOTIFICATION_DB,
+        INIT_JOB_RUN_DB,
+        INIT_ANOMALY_DB,
+        INIT_POLICY_DB,
+        INIT_DEVICE_DB,
+        INIT_DIRECTORY_DB,
+        INIT_ACCESS_DB,
+        INIT_AUDIT_DB,
+        INIT_IMPORT_DB,
+        INIT_PROJECT_DB,
+        INIT_CALENDAR_DB,
+    ] {
+        sqlx::query(init)
+            .execute(&pool)
+            .await
+            .expect("Failed to create table");
+    }
+    // Tables the main server created before retention lack the column.
+    let anonymized_at = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM pragma_table_info('device_login') WHERE name = 'anonymized_at';",
+    )
+    .fetch_one(&pool)
+    .await
+    .expect("Failed to read the columns of device_login");
+    if anonymized_at == 0 {
+        sqlx::query("ALTER TABLE device_login ADD COLUMN anonymized_at TEXT;")
+            .execute(&pool)
+            .await
+            .expect("Failed to add anonymized_at to device_login");
+    }
+    let legacy = sqlite::count_legacy_rows(&pool).await;
+    if legacy > 0 {
+        log::warn!(
+            "{legacy} clock events have a time or status an older version wrote, run the \
+             `migrate` command once to rewrite them or set them aside"
+        );
+    }
 
     pool
 }
 
+/// The `migrate` command: brings rows an older version left in
+/// `device_login` up to date, logging each one set aside. `init_db` has
+/// already brought the schema up to date.
+pub async fn migrate(pool: &Pool<Sqlite>) -> Result<String, String> {
+    let legacy = sqlite::normalize_legacy_rows(pool).await?;
+    for login in &legacy.quarantined {
+        log::warn!(
+            "Moved clock event {} of {} to device_login_quarantine, login_status {:?} \
+             created_at {:?}",
+            login.login_id,
+            login.user_id,
+            login.login_status,
+            login.created_at
+        );
+    }
+    Ok(format!(
+        "Schema of {DB_FILE} is up to date. Moved {} clock events to \
+         device_login_quarantine, rewrote {} times and {} statuses.",
+        legacy.quarantined.len(),
+        legacy.times_rewritten,
+        legacy.statuses_rewritten
+    ))
+}
+
+pub fn url() -> String {
+    format!("sqlite://{DB_FILE}?mode=rwc")
+}
+
+/// Every feature's database on the one pool, except clock events when
+/// `config` points them elsewhere.
+pub async fn open(pool: &Pool<Sqlite>, config: &Config) -> Db {
+    Db::new()
+        .set_device_login(device_login::connect(&config.storage.device_login_url, pool).await)
+        .set_webhook(WebhookDatabase::new(pool.clone()).await)
+        .set_notification(NotificationDatabase::new(pool.clone()).await)
+        .set_job_run(JobRunDatabase::new(pool.clone()).await)
+        .set_anomaly(AnomalyDatabase::new(pool.clone()).await)
+        .set_policy(PolicyDatabase::new(pool.clone()).await)
+        .set_device(DeviceDatabase::new(pool.clone()).await)
+        .set_directory(DirectoryDatabase::new(pool.clone()).await)
+        .set_access(AccessDatabase::new(pool.clone()).await)
+        .set_audit(AuditDatabase::new(pool.clone()).await)
+        .set_retention(RetentionDatabase::new(pool.clone()).await)
+        .set_privacy(PrivacyDatabase::new(pool.clone()).await)
+        .set_backup(BackupDatabase::new(pool.clone()).await)
+        .set_import(ImportDatabase::new(pool.clone()).await)
+        .set_project(ProjectDatabase::new(pool.clone()).await)
+        .set_calendar(CalendarDatabase::new(pool.clone()).await)
+        .set_scan(ScanDatabase::new(pool.clone()).await)
+}
+
 pub async fn close_db(pool: Pool<Sqlite>) {
     pool.close().await;
 }
 
 #[derive(Clone)]
 pub struct Db {
-    device_login: Option<DeviceLoginDatabase>,
+    device_login: Option<Arc<dyn DeviceLoginStore>>,
+    webhook: Option<WebhookDatabase>,
+    notification: Option<NotificationDatabase>,
+    job_run: Option<JobRunDatabase>,
+    anomaly: Option<AnomalyDatabase>,
+    policy: Option<PolicyDatabase>,
+    device: Option<DeviceDatabase>,
+    directory: Option<DirectoryDatabase>,
+    access: Option<AccessDatabase>,
+    audit: Option<AuditDatabase>,
+    retention: Option<RetentionDatabase>,
+    privacy: Option<PrivacyDatabase>,
+    backup: Option<BackupDatabase>,
+    import: Option<ImportDatabase>,
+    project: Option<ProjectDatabase>,
+    calendar: Option<CalendarDatabase>,
+    scan: Option<ScanDatabase>,
 }
 
 impl Db {
     pub fn new() -> Self {
-        Self { device_login: None }
+        Self {
+            device_login: None,
+            webhook: None,
+            notification: None,
+            job_run: None,
+            anomaly: None,
+            policy: None,
+            device: None,
+            directory: None,
+            access: None,
+            audit: None,
+            retention: None,
+            privacy: None,
+            backup: None,
+            import: None,
+            project: None,
+            calendar: None,
+            scan: None,
+        }
     }
 
-    pub fn device_login(&mut self) -> &mut DeviceLoginDatabase {
-        self.device_login.as_mut().unwrap()
+    pub fn device_login(&mut self) -> &dyn DeviceLoginStore {
+        self.device_login.as_deref().unwrap()
     }
 
-    pub fn set_device_login(mut self, device_login: DeviceLoginDatabase) -> Self {
+    pub fn set_device_login(mut self, device_login: Arc<dyn DeviceLoginStore>) -> Self {
         self.device_login = Some(device_login);
         self
     }
+
+    pub fn webhook(&mut self) -> &mut WebhookDatabase {
+        self.webhook.as_mut().unwrap()
+    }
+
+    pub fn set_webhook(mut self, webhook: WebhookDatabase) -> Self {
+        self.webhook = Some(webhook);
+        self
+    }
+
+    pub fn notification(&mut self) -> &mut NotificationDatabase {
+        self.notification.as_mut().unwrap()
+    }
+
+    pub fn set_notification(mut self, notification: NotificationDatabase) -> Self {
+        self.notification = Some(notification);
+        self
+    }
+
+    pub fn job_run(&mut self) -> &mut JobRunDatabase {
+        self.job_run.as_mut().unwrap()
+    }
+
+    pub fn set_job_run(mut self, job_run: JobRunDatabase) -> Self {
+        self.job_run = Some(job_run);
+        self
+    }
+
+    pub fn anomaly(&mut self) -> &mut AnomalyDatabase {
+        self.anomaly.as_mut().unwrap()
+    }
+
+    pub fn set_anomaly(mut self, anomaly: AnomalyDatabase) -> Self {
+        self.anomaly = Some(anomaly);
+        self
+    }
+
+    pub fn policy(&mut self) -> &mut PolicyDatabase {
+        self.policy.as_mut().unwrap()
+    }
+
+    pub fn set_policy(mut self, policy: PolicyDatabase) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    pub fn device(&mut self) -> &mut DeviceDatabase {
+        self.device.as_mut().unwrap()
+    }
+
+    pub fn set_device(mut self, device: DeviceDatabase) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    pub fn directory(&mut self) -> &mut DirectoryDatabase {
+        self.directory.as_mut().unwrap()
+    }
+
+    pub fn set_directory(mut self, directory: DirectoryDatabase) -> Self {
+        self.directory = Some(directory);
+        self
+    }
+
+    pub fn access(&mut self) -> &mut AccessDatabase {
+        self.access.as_mut().unwrap()
+    }
+
+    pub fn set_access(mut self, access: AccessDatabase) -> Self {
+        self.access = Some(access);
+        self
+    }
+
+    pub fn audit(&mut self) -> &mut AuditDatabase {
+        self.audit.as_mut().unwrap()
+    }
+
+    pub fn set_audit(mut self, audit: AuditDatabase) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    pub fn retention(&mut self) -> &mut RetentionDatabase {
+        self.retention.as_mut().unwrap()
+    }
+
+    pub fn set_retention(mut self, retention: RetentionDatabase) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    pub fn privacy(&mut self) -> &mut PrivacyDatabase {
+        self.privacy.as_mut().unwrap()
+    }
+
+    pub fn set_privacy(mut self, privacy: PrivacyDatabase) -> Self {
+        self.privacy = Some(privacy);
+        self
+    }
+
+    pub fn backup(&mut self) -> &mut BackupDatabase {
+        self.backup.as_mut().unwrap()
+    }
+
+    pub fn set_backup(mut self, backup: BackupDatabase) -> Self {
+        self.backup = Some(backup);
+        self
+    }
+
+    pub fn import(&mut self) -> &mut ImportDatabase {
+        self.import.as_mut().unwrap()
+    }
+
+    pub fn set_import(mut self, import: ImportDatabase) -> Self {
+        self.import = Some(import);
+        self
+    }
+
+    pub fn project(&mut self) -> &mut ProjectDatabase {
+        self.project.as_mut().unwrap()
+    }
+
+    pub fn set_project(mut self, project: ProjectDatabase) -> Self {
+        self.project = Some(project);
+        self
+    }
+
+    pub fn calendar(&mut self) -> &mut CalendarDatabase {
+        self.calendar.as_mut().unwrap()
+    }
+
+    pub fn set_calendar(mut self, calendar: CalendarDatabase) -> Self {
+        self.calendar = Some(calendar);
+        self
+    }
+
+    pub fn scan(&mut self) -> &mut ScanDatabase {
+        self.scan.as_mut().unwrap()
+    }
+
+    pub fn set_scan(mut self, scan: ScanDatabase) -> Self {
+        self.scan = Some(scan);
+        self
+    }
 }
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct Device {
+    pub device_id: String,
+    pub user_id: String,
+    pub label: String,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub status: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct DeviceDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl DeviceDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn devices(&self, status: Option<&str>) -> Vec<Device> {
+        sqlx::query_as::<_, Device>(
+            r#"
+            SELECT device_id, user_id, label, first_seen, last_seen, status
+            FROM device
+            WHERE (?1 IS NULL OR status = ?1)
+            ORDER BY status = 'PENDING' DESC, last_seen DESC;
+            "#,
+        )
+        .bind(status)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("DeviceDatabase::devices: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn device(&self, device_id: &str) -> Option<Device> {
+        sqlx::query_as::<_, Device>(
+            r#"
+            SELECT device_id, user_id, label, first_seen, last_seen, status
+            FROM device
+            WHERE device_id = ?;
+            "#,
+        )
+        .bind(device_id)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("DeviceDatabase::device: {err}");
+            None
+        })
+    }
+
+    /// Registers a device the first time it shows up and keeps `last_seen`
+    /// current afterwards. The first user seen on it becomes its owner.
+    pub async fn observe(&self, device_id: &str, user_id: &str, seen_at: &str, status: &str) {
+        if let Err(err) = sqlx::query(
+            r#"
+            INSERT INTO device (device_id, user_id, label, first_seen, last_seen, status)
+            VALUES (?1, ?2, '', ?3, ?3, ?4)
+            ON CONFLICT (device_id) DO UPDATE
+                SET last_seen = max(last_seen, excluded.last_seen);
+            "#,
+        )
+        .bind(device_id)
+        .bind(user_id)
+        .bind(seen_at)
+        .bind(status)
+        .execute(&self.pool)
+        .await
+        {
+            log::error!("DeviceDatabase::observe: {err}");
+        }
+    }
+
+    /// Activates a device, optionally handing it to another user or
+    /// relabelling it.
+    pub async fn approve(
+        &self,
+        device_id: &str,
+        user_id: Option<&str>,
+        label: Option<&str>,
+    ) -> bool {
+        sqlx::query(
+            r#"
+            UPDATE device
+            SET status = 'ACTIVE',
+                user_id = coalesce(?2, user_id),
+                label = coalesce(?3, label)
+            WHERE device_id = ?1;
+            "#,
+        )
+        .bind(device_id)
+        .bind(user_id)
+        .bind(label)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .unwrap_or_else(|err| {
+            log::error!("DeviceDatabase::approve: {err}");
+            false
+        })
+    }
+
+    pub async fn revoke(&self, device_id: &str) -> bool {
+        sqlx::query("UPDATE device SET status = 'REVOKED' WHERE device_id = ?;")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or_else(|err| {
+                log::error!("DeviceDatabase::revoke: {err}");
+                false
+            })
+    }
+}
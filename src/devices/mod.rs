@@ -0,0 +1,268 @@
+pub mod database;
+
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{
+    Extension,
+    body::{Body, to_bytes},
+    extract::Path,
+    http::{Request, StatusCode},
+    response::{Html, IntoResponse, Redirect},
+};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use strum_macros::{AsRefStr, Display, EnumString};
+use tera::{Context, Tera};
+use url::form_urlencoded;
+
+use crate::{
+    access::{self, Permission},
+    anomalies::AnomalyKind,
+    audit::{self, AuditAction},
+    config::Config,
+    db::Db,
+    scan::{self, LoginScan},
+    scheduler::Job,
+    users::device_login::{DeviceLogin, LoginStatus, canonical_time},
+    utils,
+};
+
+const MAX_BODY_SIZE: usize = 16 * 1024;
+const HTML_PATH: &str = "www/*.html";
+const DEVICES_PATH: &str = "/external/timekeeping/devices";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, AsRefStr, EnumString)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE", ascii_case_insensitive)]
+pub enum DeviceStatus {
+    /// Seen in a clock event but not approved by an admin yet.
+    Pending,
+    Active,
+    Revoked,
+}
+
+/// Registers every device seen in `device_login` and flags clock events
+/// from devices that are not active or belong to someone else.
+pub struct DeviceJob {
+    config: Config,
+}
+
+pub fn jobs(config: &Config) -> Vec<Arc<dyn Job>> {
+    vec![Arc::new(DeviceJob {
+        config: config.clone(),
+    })]
+}
+
+impl DeviceJob {
+    async fn check(&self, db: &mut Db, login: &DeviceLogin) -> Option<(AnomalyKind, String)> {
+        let initial = if self.config.device.trust_on_first_use {
+            DeviceStatus::Active
+        } else {
+            DeviceStatus::Pending
+        };
+        db.device()
+            .observe(
+                &login.device_id,
+                &login.user_id,
+                &canonical_time(&login.created_at),
+                initial.as_ref(),
+            )
+            .await;
+
+        let device = db.device().device(&login.device_id).await?;
+        match DeviceStatus::from_str(&device.status) {
+            Ok(DeviceStatus::Pending) => Some((
+                AnomalyKind::UnregisteredDevice,
+                format!("device {} is not registered", device.device_id),
+            )),
+            Ok(DeviceStatus::Revoked) => Some((
+                AnomalyKind::RevokedDevice,
+                format!("device {} has been revoked", device.device_id),
+            )),
+            Ok(DeviceStatus::Active) if device.user_id != login.user_id => Some((
+                AnomalyKind::ForeignDevice,
+                format!(
+                    "device {} is registered to {}",
+                    device.device_id, device.user_id
+                ),
+            )),
+            Ok(DeviceStatus::Active) => None,
+            Err(_) => {
+                log::error!(
+                    "Unknown status {} on device {}",
+                    device.status,
+                    device.device_id
+                );
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Job for DeviceJob {
+    fn name(&self) -> &'static str {
+        "sync_device_registry"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "15 * * * * *"
+    }
+
+    async fn run(&self, db: &mut Db) -> Result<String, String> {
+        let scanned = scan::run(self, db).await;
+        Ok(format!(
+            "{} events checked, {} flagged",
+            scanned.visited, scanned.findings
+        ))
+    }
+}
+
+#[async_trait]
+impl LoginScan for DeviceJob {
+    fn cursor(&self) -> &'static str {
+        self.name()
+    }
+
+    async fn visit(&self, db: &mut Db, login: &DeviceLogin) -> Option<u64> {
+        if login.login_status == LoginStatus::AutoOut || login.device_id.is_empty() {
+            return None;
+        }
+
+        let Some((kind, detail)) = self.check(db, login).await else {
+            return Some(0);
+        };
+        let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        if db
+            .anomaly()
+            .flag(login.id, &login.user_id, kind.as_ref(), &detail, &now)
+            .await
+        {
+            log::warn!(
+                "device_login {} of {} flagged {kind}: {detail}",
+                login.id,
+                login.user_id
+            );
+            return Some(1);
+        }
+        Some(0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Parameters {
+    status: Option<String>,
+}
+
+/// Non-empty fields of a submitted `application/x-www-form-urlencoded` body.
+async fn form_fields(request: Request<Body>) -> HashMap<String, String> {
+    match to_bytes(request.into_body(), MAX_BODY_SIZE).await {
+        Ok(body) => form_urlencoded::parse(&body)
+            .into_owned()
+            .map(|(key, value)| (key, value.trim().to_string()))
+            .filter(|(_, value)| !value.is_empty())
+            .collect(),
+        Err(err) => {
+            log::error!("Failed to read form body: {err}");
+            HashMap::new()
+        }
+    }
+}
+
+pub async fn handle_devices(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewReports).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let status = match params.status.as_deref().filter(|status| !status.is_empty()) {
+        Some(status) => match DeviceStatus::from_str(status) {
+            Ok(status) => Some(status),
+            Err(_) => {
+                return (StatusCode::BAD_REQUEST, format!("Unknown status {status}."))
+                    .into_response();
+            }
+        },
+        None => None,
+    };
+
+    let devices = db
+        .device()
+        .devices(status.as_ref().map(AsRef::as_ref))
+        .await;
+
+    let tera = Tera::new(HTML_PATH).unwrap();
+    let mut context = Context::new();
+    context.insert("devices", &devices);
+    context.insert("status", &status.map(|status| status.to_string()));
+    context.insert("user_id", &principal.user_id);
+    context.insert("can_manage", &principal.can(Permission::ManageConfig));
+
+    let rendered = tera.render("devices.html", &context).unwrap();
+    Html(rendered).into_response()
+}
+
+pub async fn approve_device(
+    Extension(mut db): Extension<Db>,
+    Path(device_id): Path<String>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageConfig).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let fields = form_fields(request).await;
+
+    if !db
+        .device()
+        .approve(
+            &device_id,
+            fields.get("owner").map(String::as_str),
+            fields.get("label").map(String::as_str),
+        )
+        .await
+    {
+        return (StatusCode::NOT_FOUND, "No such device.").into_response();
+    }
+    log::info!("{} approved device {device_id}", principal.user_id);
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Update,
+        &format!("device {device_id}"),
+        &fields,
+        1,
+    )
+    .await;
+    Redirect::to(DEVICES_PATH).into_response()
+}
+
+pub async fn revoke_device(
+    Extension(mut db): Extension<Db>,
+    Path(device_id): Path<String>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageConfig).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if !db.device().revoke(&device_id).await {
+        return (StatusCode::NOT_FOUND, "No such device.").into_response();
+    }
+    log::info!("{} revoked device {device_id}", principal.user_id);
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Update,
+        &format!("device {device_id}"),
+        &json!({ "status": DeviceStatus::Revoked.as_ref() }),
+        1,
+    )
+    .await;
+    Redirect::to(DEVICES_PATH).into_response()
+}
@@ -0,0 +1,370 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct AppUser {
+    pub user_id: String,
+    pub name: String,
+    pub email: String,
+    pub department: String,
+    pub manager_id: Option<String>,
+    pub employment_status: String,
+    pub hire_date: Option<String>,
+    pub termination_date: Option<String>,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct Team {
+    pub name: String,
+    pub department: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct ReportingLine {
+    pub manager_id: String,
+    pub report_id: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct TeamMember {
+    pub team: String,
+    pub user_id: String,
+}
+
+/// Changes an admin makes to a user. `None` keeps the current value, an
+/// empty string clears an optional one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserUpdate {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub department: Option<String>,
+    pub manager_id: Option<String>,
+    pub employment_status: Option<String>,
+    pub hire_date: Option<String>,
+    pub termination_date: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DirectoryDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl DirectoryDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn users(&self, team: Option<&str>) -> Vec<AppUser> {
+        sqlx::query_as::<_, AppUser>(
+            r#"
+            SELECT user_id, name, email, department, manager_id, employment_status,
+                   hire_date, termination_date, first_seen, last_seen
+            FROM app_user
+            WHERE (?1 IS NULL OR user_id IN (SELECT user_id FROM team_member WHERE team = ?1))
+            ORDER BY name COLLATE NOCASE ASC;
+            "#,
+        )
+        .bind(team)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("DirectoryDatabase::users: {err}");
+            Vec::new()
+        })
+    }
+
+    /// Ids of the users whose name contains `name`, as a JSON array.
+    pub async fn user_ids_named(&self, name: &str) -> String {
+        sqlx::query_scalar::<_, String>(
+            "SELECT json_group_array(user_id) FROM app_user WHERE name LIKE ?;",
+        )
+        .bind(format!("%{name}%"))
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("DirectoryDatabase::user_ids_named: {err}");
+            "[]".to_string()
+        })
+    }
+
+    /// Ids of the team's members, as a JSON array.
+    pub async fn team_user_ids(&self, team: &str) -> String {
+        sqlx::query_scalar::<_, String>(
+            "SELECT json_group_array(user_id) FROM team_member WHERE team = ?;",
+        )
+        .bind(team)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("DirectoryDatabase::team_user_ids: {err}");
+            "[]".to_string()
+        })
+    }
+
+    pub async fn user(&self, user_id: &str) -> Option<AppUser> {
+        sqlx::query_as::<_, AppUser>(
+            r#"
+            SELECT user_id, name, email, department, manager_id, employment_status,
+                   hire_date, termination_date, first_seen, last_seen
+            FROM app_user
+            WHERE user_id = ?;
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("DirectoryDatabase::user: {err}");
+            None
+        })
+    }
+
+    /// Adds a user the first time they clock and keeps `last_seen` current.
+    /// Name and email are only taken from the event while they are unknown,
+    /// afterwards the directory is the source of truth.
+    pub async fn observe(&self, user_id: &str, name: &str, email: &str, seen_at: &str) {
+        if let Err(err) = sqlx::query(
+            r#"
+            INSERT INTO app_user (user_id, name, email, first_seen, last_seen)
+            VALUES (?1, ?2, ?3, ?4, ?4)
+            ON CONFLICT (user_id) DO UPDATE
+                SET name = CASE WHEN name = '' THEN excluded.name ELSE name END,
+                    email = CASE WHEN email = '' THEN excluded.email ELSE email END,
+                    last_seen = max(last_seen, excluded.last_seen);
+            "#,
+        )
+        .bind(user_id)
+        .bind(name)
+        .bind(email)
+        .bind(seen_at)
+        .execute(&self.pool)
+        .await
+        {
+            log::error!("DirectoryDatabase::observe: {err}");
+        }
+    }
+
+    pub async fn update_user(&self, user_id: &str, update: &UserUpdate) -> bool {
+        sqlx::query(
+            r#"
+            UPDATE app_user
+            SET name = coalesce(?2, name),
+                email = coalesce(?3, email),
+                department = coalesce(?4, department),
+                manager_id = CASE WHEN ?5 IS NULL THEN manager_id ELSE nullif(?5, '') END,
+                employment_status = coalesce(?6, employment_status),
+                hire_date = CASE WHEN ?7 IS NULL THEN hire_date ELSE nullif(?7, '') END,
+                termination_date =
+                    CASE WHEN ?8 IS NULL THEN termination_date ELSE nullif(?8, '') END
+            WHERE user_id = ?1;
+            "#,
+        )
+        .bind(user_id)
+        .bind(&update.name)
+        .bind(&update.email)
+        .bind(&update.department)
+        .bind(&update.manager_id)
+        .bind(&update.employment_status)
+        .bind(&update.hire_date)
+        .bind(&update.termination_date)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .unwrap_or_else(|err| {
+            log::error!("DirectoryDatabase::update_user: {err}");
+            false
+        })
+    }
+
+    pub async fn teams(&self) -> Vec<Team> {
+        sqlx::query_as::<_, Team>(
+            "SELECT name, department, created_at FROM team ORDER BY name COLLATE NOCASE ASC;",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("DirectoryDatabase::teams: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn team_members(&self) -> Vec<TeamMember> {
+        sqlx::query_as::<_, TeamMember>(
+            "SELECT team, user_id FROM team_member ORDER BY team, user_id;",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("DirectoryDatabase::team_members: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn add_team(&self, name: &str, department: &str, created_at: &str) -> bool {
+        sqlx::query("INSERT OR IGNORE INTO team (name, department, created_at) VALUES (?, ?, ?);")
+            .bind(name)
+            .bind(department)
+            .bind(created_at)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or_else(|err| {
+                log::error!("DirectoryDatabase::add_team: {err}");
+                false
+            })
+    }
+
+    pub async fn remove_team(&self, name: &str) -> bool {
+        let mut tx = match self.pool.begin().await {
+            Ok(tx) => tx,
+            Err(err) => {
+                log::error!("DirectoryDatabase::remove_team: {err}");
+                return false;
+            }
+        };
+
+        let removed = async {
+            sqlx::query("DELETE FROM team_member WHERE team = ?;")
+                .bind(name)
+                .execute(&mut *tx)
+                .await?;
+            let result = sqlx::query("DELETE FROM team WHERE name = ?;")
+                .bind(name)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok::<bool, sqlx::Error>(result.rows_affected() > 0)
+        }
+        .await;
+
+        removed.unwrap_or_else(|err| {
+            log::error!("DirectoryDatabase::remove_team: {err}");
+            false
+        })
+    }
+
+    pub async fn add_team_member(&self, team: &str, user_id: &str) -> bool {
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO team_member (team, user_id)
+            SELECT name, ? FROM team WHERE name = ?;
+            "#,
+        )
+        .bind(user_id)
+        .bind(team)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .unwrap_or_else(|err| {
+            log::error!("DirectoryDatabase::add_team_member: {err}");
+            false
+        })
+    }
+
+    pub async fn remove_team_member(&self, team: &str, user_id: &str) -> bool {
+        sqlx::query("DELETE FROM team_member WHERE team = ? AND user_id = ?;")
+            .bind(team)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or_else(|err| {
+                log::error!("DirectoryDatabase::remove_team_member: {err}");
+                false
+            })
+    }
+
+    pub async fn reporting_lines(&self) -> Vec<ReportingLine> {
+        sqlx::query_as::<_, ReportingLine>(
+            "SELECT manager_id, report_id, created_at FROM reporting_line ORDER BY manager_id, report_id;",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("DirectoryDatabase::reporting_lines: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn direct_reports(&self, manager_id: &str) -> Vec<String> {
+        sqlx::query_scalar::<_, String>(
+            "SELECT report_id FROM reporting_line WHERE manager_id = ? ORDER BY report_id;",
+        )
+        .bind(manager_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("DirectoryDatabase::direct_reports: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn add_reporting_line(
+        &self,
+        manager_id: &str,
+        report_id: &str,
+        created_at: &str,
+    ) -> bool {
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO reporting_line (manager_id, report_id, created_at)
+            VALUES (?, ?, ?);
+            "#,
+        )
+        .bind(manager_id)
+        .bind(report_id)
+        .bind(created_at)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .unwrap_or_else(|err| {
+            log::error!("DirectoryDatabase::add_reporting_line: {err}");
+            false
+        })
+    }
+
+    pub async fn remove_reporting_line(&self, manager_id: &str, report_id: &str) -> bool {
+        sqlx::query("DELETE FROM reporting_line WHERE manager_id = ? AND report_id = ?;")
+            .bind(manager_id)
+            .bind(report_id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or_else(|err| {
+                log::error!("DirectoryDatabase::remove_reporting_line: {err}");
+                false
+            })
+    }
+
+    /// Makes `manager_id` the only manager of `report_id`, or leaves them
+    /// without one when it is empty.
+    pub async fn replace_manager(&self, report_id: &str, manager_id: &str, created_at: &str) {
+        let replaced = async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query("DELETE FROM reporting_line WHERE report_id = ?;")
+                .bind(report_id)
+                .execute(&mut *tx)
+                .await?;
+            if !manager_id.is_empty() {
+                sqlx::query(
+                    "INSERT INTO reporting_line (manager_id, report_id, created_at) VALUES (?, ?, ?);",
+                )
+                .bind(manager_id)
+                .bind(report_id)
+                .bind(created_at)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await
+        }
+        .await;
+
+        if let Err(err) = replaced {
+            log::error!("DirectoryDatabase::replace_manager: {err}");
+        }
+    }
+}
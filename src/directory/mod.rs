@@ -0,0 +1,431 @@
+pub mod database;
+
+use std::{str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::Path,
+    http::{Request, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use strum_macros::{AsRefStr, Display, EnumString};
+
+use crate::{
+    access::{self, Permission},
+    audit::{self, AuditAction},
+    config::Config,
+    db::Db,
+    scan::{self, LoginScan},
+    scheduler::Job,
+    users::device_login::{DeviceLogin, LoginStatus, canonical_time},
+    utils::{self, DATE_FORMAT, parse_date},
+};
+use database::UserUpdate;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, AsRefStr, EnumString)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE", ascii_case_insensitive)]
+pub enum EmploymentStatus {
+    Active,
+    OnLeave,
+    Terminated,
+}
+
+/// The `named_users` and `team_members` of a `LoginFilter` on `name` and
+/// `team`, resolved from the directory.
+pub async fn filter_users(
+    db: &mut Db,
+    name: &str,
+    team: Option<&str>,
+) -> (Option<String>, Option<String>) {
+    let named_users = if name.is_empty() {
+        None
+    } else {
+        Some(db.directory().user_ids_named(name).await)
+    };
+    let team_members = match team {
+        Some(team) => Some(db.directory().team_user_ids(team).await),
+        None => None,
+    };
+    (named_users, team_members)
+}
+
+/// Adds everyone who shows up in `device_login` to the directory.
+pub struct DirectoryJob;
+
+pub fn jobs(_config: &Config) -> Vec<Arc<dyn Job>> {
+    vec![Arc::new(DirectoryJob)]
+}
+
+#[async_trait]
+impl Job for DirectoryJob {
+    fn name(&self) -> &'static str {
+        "sync_user_directory"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "5 * * * * *"
+    }
+
+    async fn run(&self, db: &mut Db) -> Result<String, String> {
+        let scanned = scan::run(self, db).await;
+        Ok(format!("{} events synced", scanned.visited))
+    }
+}
+
+#[async_trait]
+impl LoginScan for DirectoryJob {
+    fn cursor(&self) -> &'static str {
+        self.name()
+    }
+
+    async fn visit(&self, db: &mut Db, login: &DeviceLogin) -> Option<u64> {
+        if login.login_status == LoginStatus::AutoOut {
+            return None;
+        }
+        db.directory()
+            .observe(
+                &login.user_id,
+                &login.name,
+                &login.email,
+                &canonical_time(&login.created_at),
+            )
+            .await;
+        Some(0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Parameters {
+    team: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NewTeam {
+    name: String,
+    #[serde(default)]
+    department: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NewTeamMember {
+    user_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NewReportingLine {
+    manager_id: String,
+    report_id: String,
+}
+
+fn validate(update: &mut UserUpdate) -> Result<(), String> {
+    if let Some(status) = &update.employment_status {
+        let status = EmploymentStatus::from_str(status)
+            .map_err(|_| format!("Unknown employment status {status}."))?;
+        update.employment_status = Some(status.to_string());
+    }
+    for date in [&mut update.hire_date, &mut update.termination_date]
+        .into_iter()
+        .flatten()
+        .filter(|date| !date.is_empty())
+    {
+        *date = parse_date(date)?.format(DATE_FORMAT).to_string();
+    }
+    Ok(())
+}
+
+pub async fn list_users(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    if let Err(rejection) = access::authorize(&mut db, &request, Permission::ViewAll).await {
+        return rejection.into_response();
+    }
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let team = params.team.as_deref().filter(|team| !team.is_empty());
+    Json(db.directory().users(team).await).into_response()
+}
+
+pub async fn update_user(
+    Extension(mut db): Extension<Db>,
+    Path(user_id): Path<String>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageDirectory).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let mut update = match utils::json_body::<UserUpdate>(request).await {
+        Ok(update) => update,
+        Err(rejection) => return rejection.into_response(),
+    };
+    if let Err(err) = validate(&mut update) {
+        return (StatusCode::BAD_REQUEST, err).into_response();
+    }
+
+    if !db.directory().update_user(&user_id, &update).await {
+        return (StatusCode::NOT_FOUND, "No such user.").into_response();
+    }
+    if let Some(manager_id) = &update.manager_id {
+        db.directory()
+            .replace_manager(
+                &user_id,
+                manager_id,
+                &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            )
+            .await;
+    }
+    log::info!("{} updated directory entry of {user_id}", principal.user_id);
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Update,
+        &format!("user {user_id}"),
+        &update,
+        1,
+    )
+    .await;
+    Json(db.directory().user(&user_id).await).into_response()
+}
+
+pub async fn list_teams(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    if let Err(rejection) = access::authorize(&mut db, &request, Permission::ViewAll).await {
+        return rejection.into_response();
+    }
+
+    Json(json!({
+        "teams": db.directory().teams().await,
+        "members": db.directory().team_members().await,
+    }))
+    .into_response()
+}
+
+pub async fn create_team(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageDirectory).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let new = match utils::json_body::<NewTeam>(request).await {
+        Ok(new) => new,
+        Err(rejection) => return rejection.into_response(),
+    };
+    if new.name.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "A team name is required.").into_response();
+    }
+
+    if !db
+        .directory()
+        .add_team(
+            new.name.trim(),
+            new.department.trim(),
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+        .await
+    {
+        return (StatusCode::CONFLICT, "Team already exists.").into_response();
+    }
+    log::info!("{} created team {}", principal.user_id, new.name.trim());
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Create,
+        &format!("team {}", new.name.trim()),
+        &new,
+        1,
+    )
+    .await;
+    StatusCode::CREATED.into_response()
+}
+
+pub async fn delete_team(
+    Extension(mut db): Extension<Db>,
+    Path(team): Path<String>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageDirectory).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if db.directory().remove_team(&team).await {
+        log::info!("{} removed team {team}", principal.user_id);
+        audit::record(
+            &mut db,
+            &principal,
+            AuditAction::Delete,
+            &format!("team {team}"),
+            &(),
+            1,
+        )
+        .await;
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "No such team.").into_response()
+    }
+}
+
+pub async fn add_team_member(
+    Extension(mut db): Extension<Db>,
+    Path(team): Path<String>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageDirectory).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let new = match utils::json_body::<NewTeamMember>(request).await {
+        Ok(new) => new,
+        Err(rejection) => return rejection.into_response(),
+    };
+    if new.user_id.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "A user is required.").into_response();
+    }
+
+    if db
+        .directory()
+        .add_team_member(&team, new.user_id.trim())
+        .await
+    {
+        audit::record(
+            &mut db,
+            &principal,
+            AuditAction::Create,
+            &format!("team {team}"),
+            &new,
+            1,
+        )
+        .await;
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "No such team, or already a member.").into_response()
+    }
+}
+
+pub async fn remove_team_member(
+    Extension(mut db): Extension<Db>,
+    Path((team, user_id)): Path<(String, String)>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageDirectory).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if db.directory().remove_team_member(&team, &user_id).await {
+        audit::record(
+            &mut db,
+            &principal,
+            AuditAction::Delete,
+            &format!("team {team}"),
+            &json!({ "user_id": user_id }),
+            1,
+        )
+        .await;
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "No such team member.").into_response()
+    }
+}
+
+pub async fn list_reporting_lines(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    if let Err(rejection) = access::authorize(&mut db, &request, Permission::ViewAll).await {
+        return rejection.into_response();
+    }
+
+    Json(db.directory().reporting_lines().await).into_response()
+}
+
+pub async fn add_reporting_line(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageDirectory).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let new = match utils::json_body::<NewReportingLine>(request).await {
+        Ok(new) => new,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let (manager_id, report_id) = (new.manager_id.trim(), new.report_id.trim());
+    if manager_id.is_empty() || report_id.is_empty() || manager_id == report_id {
+        return (
+            StatusCode::BAD_REQUEST,
+            "A manager and a different report are required.",
+        )
+            .into_response();
+    }
+
+    if !db
+        .directory()
+        .add_reporting_line(
+            manager_id,
+            report_id,
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+        .await
+    {
+        return (StatusCode::CONFLICT, "Reporting line already exists.").into_response();
+    }
+    log::info!(
+        "{} made {report_id} report to {manager_id}",
+        principal.user_id
+    );
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Create,
+        &format!("reporting_line {manager_id}/{report_id}"),
+        &new,
+        1,
+    )
+    .await;
+    StatusCode::CREATED.into_response()
+}
+
+pub async fn remove_reporting_line(
+    Extension(mut db): Extension<Db>,
+    Path((manager_id, report_id)): Path<(String, String)>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageDirectory).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if db
+        .directory()
+        .remove_reporting_line(&manager_id, &report_id)
+        .await
+    {
+        log::info!(
+            "{} removed reporting line {report_id} -> {manager_id}",
+            principal.user_id
+        );
+        audit::record(
+            &mut db,
+            &principal,
+            AuditAction::Delete,
+            &format!("reporting_line {manager_id}/{report_id}"),
+            &(),
+            1,
+        )
+        .await;
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "No such reporting line.").into_response()
+    }
+}
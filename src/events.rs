@@ -0,0 +1,207 @@
+use std::{convert::Infallible, time::Duration};
+
+use axum::{
+    Extension,
+    body::Body,
+    http::Request,
+    response::{
+        IntoResponse,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use serde_json::json;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::{
+    StreamExt,
+    wrappers::{BroadcastStream, ReceiverStream},
+};
+
+use crate::{
+    access::{self, Permission, Scope},
+    audit::{self, AuditAction},
+    db::Db,
+    users::device_login::DeviceLogin,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+const CHANNEL_CAPACITY: usize = 256;
+const POLL_BATCH_SIZE: u64 = 100;
+const REPLAY_PAGE_SIZE: u64 = 500;
+
+/// Fan-out of newly inserted `device_login` rows to every connected stream.
+#[derive(Clone)]
+pub struct LoginEvents {
+    sender: broadcast::Sender<DeviceLogin>,
+}
+
+impl LoginEvents {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DeviceLogin> {
+        self.sender.subscribe()
+    }
+
+    pub fn publish(&self, login: DeviceLogin) {
+        // No receivers is not an error, nobody is listening yet.
+        let _ = self.sender.send(login);
+    }
+}
+
+/// Rows are written by the main web server, so new events are detected by
+/// polling the table's max rowid.
+pub async fn watch_device_login(mut db: Db, events: LoginEvents) {
+    let mut last_id = db.device_login().last_id().await;
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+    log::info!("Watching device_login for new events after id {last_id}");
+    loop {
+        interval.tick().await;
+
+        let logins = db
+            .device_login()
+            .device_login_after(last_id, POLL_BATCH_SIZE)
+            .await;
+        for login in logins {
+            last_id = last_id.max(login.id);
+            events.publish(login);
+        }
+    }
+}
+
+pub async fn handle_events(
+    Extension(mut db): Extension<Db>,
+    Extension(events): Extension<LoginEvents>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewOwn).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let scope = principal.scope(&mut db).await;
+
+    let last_event_id = request
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<i64>().ok());
+
+    // Subscribe before replaying so nothing inserted in between is lost.
+    let receiver = events.subscribe();
+
+    // Rows up to here are replayed from the table, later ones arrive live.
+    let replayed_up_to = match last_event_id {
+        Some(_) => db.device_login().last_id().await,
+        None => 0,
+    };
+
+    log::info!(
+        "Event stream opened for {} ({}, resuming after {:?})",
+        principal.user_id,
+        principal.role,
+        last_event_id
+    );
+
+    let (sender, missed) = mpsc::channel(REPLAY_PAGE_SIZE as usize);
+    let replay_scope = scope.clone();
+    tokio::spawn(async move {
+        let replayed = match last_event_id {
+            Some(last_id) => replay(&mut db, &replay_scope, last_id, replayed_up_to, sender).await,
+            None => 0,
+        };
+        audit::record(
+            &mut db,
+            &principal,
+            AuditAction::View,
+            "events",
+            &json!({ "last_event_id": last_event_id }),
+            replayed,
+        )
+        .await;
+    });
+
+    // A lagged receiver has lost events for good, so the stream ends there
+    // and the client reconnects with the last id it saw to replay them.
+    let live = BroadcastStream::new(receiver)
+        .map_while(|result| match result {
+            Ok(login) => Some(login),
+            Err(err) => {
+                log::warn!("Event stream lagged, closing it: {err}");
+                None
+            }
+        })
+        .filter(move |login| login.id > replayed_up_to && scope.allows(&login.user_id));
+
+    let stream = ReceiverStream::new(missed)
+        .chain(live)
+        .map(|login| Ok::<Event, Infallible>(to_sse_event(&login)));
+
+    Sse::new(stream)
+        .keep_alive(
+            KeepAlive::new()
+                .interval(HEARTBEAT_INTERVAL)
+                .text("heartbeat"),
+        )
+        .into_response()
+}
+
+/// Sends every row after `last_id` up to `up_to` that `scope` allows, one
+/// page at a time so a far-behind client gets all of them without the whole
+/// backlog held in memory. Returns how many rows were sent.
+async fn replay(
+    db: &mut Db,
+    scope: &Scope,
+    mut last_id: i64,
+    up_to: i64,
+    sender: mpsc::Sender<DeviceLogin>,
+) -> usize {
+    let mut replayed = 0;
+    while last_id < up_to {
+        let page = match scope {
+            Scope::Own(user_id) => {
+                db.device_login()
+                    .device_login_after_per_user(user_id, last_id, REPLAY_PAGE_SIZE)
+                    .await
+            }
+            _ => {
+                db.device_login()
+                    .device_login_after(last_id, REPLAY_PAGE_SIZE)
+                    .await
+            }
+        };
+        let Some(last) = page.last() else {
+            break;
+        };
+        last_id = last.id;
+        for login in page {
+            if login.id > up_to {
+                return replayed;
+            }
+            if !scope.allows(&login.user_id) {
+                continue;
+            }
+            // The client went away, nobody is reading the rest.
+            if sender.send(login).await.is_err() {
+                return replayed;
+            }
+            replayed += 1;
+        }
+    }
+    replayed
+}
+
+fn to_sse_event(login: &DeviceLogin) -> Event {
+    Event::default()
+        .id(login.id.to_string())
+        .event("device_login")
+        .json_data(login)
+        .unwrap_or_else(|err| {
+            log::error!("Failed to serialize event {}: {err}", login.id);
+            Event::default()
+                .id(login.id.to_string())
+                .comment("serialization error")
+        })
+}
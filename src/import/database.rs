@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+
+/// A source system's employee id mapped to a `user_id`.
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct IdMapping {
+    pub source: String,
+    pub external_id: String,
+    pub user_id: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct ImportDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl ImportDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    /// External employee id to `user_id` for one source system.
+    pub async fn id_map(&self, source: &str) -> Result<HashMap<String, String>, String> {
+        sqlx::query_as::<_, (String, String)>(
+            "SELECT external_id, user_id FROM import_id_map WHERE source = ?;",
+        )
+        .bind(source)
+        .fetch_all(&self.pool)
+        .await
+        .map(|rows| rows.into_iter().collect())
+        .map_err(|err| {
+            log::error!("ImportDatabase::id_map: {err}");
+            err.to_string()
+        })
+    }
+
+    pub async fn map_id(&self, source: &str, external_id: &str, user_id: &str) -> bool {
+        sqlx::query(
+            r#"
+            INSERT INTO import_id_map (source, external_id, user_id)
+            VALUES (?, ?, ?)
+            ON CONFLICT (source, external_id) DO UPDATE SET user_id = excluded.user_id;
+            "#,
+        )
+        .bind(source)
+        .bind(external_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .unwrap_or_else(|err| {
+            log::error!("ImportDatabase::map_id: {err}");
+            false
+        })
+    }
+}
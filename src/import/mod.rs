@@ -0,0 +1,432 @@
+pub mod database;
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use strum_macros::{AsRefStr, Display, EnumString};
+
+use crate::{
+    db::Db,
+    users::device_login::{LOGIN_PROVIDER_IMPORT, LoginStatus, NewDeviceLogin},
+};
+
+const INSERT_BATCH_SIZE: usize = 500;
+const LOCAL_TIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y/%m/%d %H:%M:%S",
+    "%Y/%m/%d %H:%M",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, AsRefStr, EnumString)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum ImportFormat {
+    /// The service's own CSV export, keyed by `user_id`.
+    Export,
+    /// A terminal's CSV with `employee_id`, `punch_time` and `state` columns.
+    Csv,
+    /// A ZKTeco style attlog: PIN, date, time, verify mode and punch state
+    /// separated by whitespace.
+    Attlog,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, AsRefStr, EnumString, Serialize)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Rejection {
+    /// Nobody is mapped to the external id.
+    Unmapped,
+    /// The line could not be read as a punch.
+    Invalid,
+}
+
+/// A line that was not imported, as a row of the import report.
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedLine {
+    pub line: usize,
+    pub reason: Rejection,
+    pub external_id: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub duplicates: usize,
+    pub rejected: Vec<RejectedLine>,
+}
+
+impl ImportReport {
+    fn reject(&mut self, line: usize, reason: Rejection, external_id: &str, detail: String) {
+        self.rejected.push(RejectedLine {
+            line,
+            reason,
+            external_id: external_id.to_string(),
+            detail,
+        });
+    }
+
+    /// Unmapped external ids with how many lines each had.
+    pub fn unmapped(&self) -> BTreeMap<&str, usize> {
+        let mut unmapped = BTreeMap::new();
+        for line in &self.rejected {
+            if line.reason == Rejection::Unmapped {
+                *unmapped.entry(line.external_id.as_str()).or_insert(0) += 1;
+            }
+        }
+        unmapped
+    }
+
+    pub fn summary(&self) -> String {
+        let unmapped = self.unmapped();
+        let invalid = self
+            .rejected
+            .iter()
+            .filter(|line| line.reason == Rejection::Invalid)
+            .count();
+        let mut summary = format!(
+            "Imported {} rows, {} already present, {invalid} invalid, {} unmapped.",
+            self.imported,
+            self.duplicates,
+            self.rejected.len() - invalid
+        );
+        if !unmapped.is_empty() {
+            let ids: Vec<String> = unmapped
+                .iter()
+                .map(|(id, lines)| format!("{id} ({lines})"))
+                .collect();
+            summary.push_str(&format!(" Unmapped ids: {}.", ids.join(", ")));
+        }
+        summary
+    }
+}
+
+/// A punch as read from a file, before its external id is mapped.
+#[derive(Debug, Clone)]
+struct Punch {
+    external_id: String,
+    name: String,
+    email: String,
+    device_id: String,
+    login_status: LoginStatus,
+    ip_address: String,
+    location: String,
+    isp: String,
+    created_at: String,
+}
+
+impl Punch {
+    /// A punch from a terminal, which only knows who, what and when.
+    fn terminal(external_id: &str, login_status: LoginStatus, created_at: String) -> Self {
+        Self {
+            external_id: external_id.to_string(),
+            name: String::new(),
+            email: String::new(),
+            device_id: String::new(),
+            login_status,
+            ip_address: String::new(),
+            location: String::new(),
+            isp: String::new(),
+            created_at,
+        }
+    }
+}
+
+/// A row of the service's own export. Extra columns, such as `id`, are
+/// ignored.
+#[derive(Debug, Deserialize)]
+struct ExportRow {
+    user_id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    email: String,
+    #[serde(default)]
+    device_id: String,
+    login_status: String,
+    #[serde(default)]
+    ip_address: String,
+    #[serde(default)]
+    location: String,
+    #[serde(default)]
+    isp: String,
+    created_at: String,
+}
+
+/// A row of a terminal's CSV export. The aliases cover the column names
+/// common terminal software uses.
+#[derive(Debug, Deserialize)]
+struct TerminalRow {
+    #[serde(alias = "AC-No.", alias = "No.", alias = "pin", alias = "PIN")]
+    employee_id: String,
+    #[serde(
+        alias = "Time",
+        alias = "time",
+        alias = "timestamp",
+        alias = "datetime"
+    )]
+    punch_time: String,
+    #[serde(alias = "State", alias = "status", alias = "Status")]
+    state: String,
+    #[serde(default, alias = "Name")]
+    name: String,
+    #[serde(default, alias = "Device", alias = "device")]
+    device_id: String,
+}
+
+/// The status for a punch state, given as a ZKTeco state code
+/// (0 check in, 1 check out, 2 break out, 3 break in, 4 overtime in,
+/// 5 overtime out) or as text such as `C/In` or `Check Out`.
+fn punch_status(state: &str) -> Option<LoginStatus> {
+    let state = state.trim().to_uppercase().replace(['-', '_', '/'], " ");
+    match state.as_str() {
+        "0" | "4" | "IN" | "C IN" | "CHECK IN" | "OT IN" | "OVERTIME IN" => Some(LoginStatus::In),
+        "1" | "5" | "OUT" | "C OUT" | "CHECK OUT" | "OT OUT" | "OVERTIME OUT" => {
+            Some(LoginStatus::Out)
+        }
+        "2" | "BREAK OUT" | "BREAK START" => Some(LoginStatus::BreakStart),
+        "3" | "BREAK IN" | "BREAK END" => Some(LoginStatus::BreakEnd),
+        _ => None,
+    }
+}
+
+/// RFC3339 as is, anything else as local time of the terminal at `offset`.
+fn punch_time(value: &str, offset: &FixedOffset) -> Result<String, String> {
+    let value = value.trim();
+    let time = match DateTime::parse_from_rfc3339(value) {
+        Ok(time) => time.with_timezone(&Utc),
+        Err(_) => LOCAL_TIME_FORMATS
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+            .and_then(|time| offset.from_local_datetime(&time).single())
+            .map(|time| time.with_timezone(&Utc))
+            .ok_or_else(|| format!("{value} is not a date and time"))?,
+    };
+    Ok(time.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+fn parse_export(content: &str) -> Vec<(usize, Result<Punch, String>)> {
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    reader
+        .deserialize::<ExportRow>()
+        .enumerate()
+        // Line 1 is the header.
+        .map(|(index, row)| {
+            let punch = row.map_err(|err| err.to_string()).and_then(|row| {
+                let login_status = LoginStatus::try_from(row.login_status)?;
+                let created_at = DateTime::parse_from_rfc3339(row.created_at.trim())
+                    .map_err(|_| format!("{} is not an RFC3339 time", row.created_at))?
+                    .with_timezone(&Utc)
+                    .to_rfc3339_opts(SecondsFormat::Millis, true);
+                Ok(Punch {
+                    external_id: row.user_id.trim().to_string(),
+                    name: row.name,
+                    email: row.email,
+                    device_id: row.device_id,
+                    login_status,
+                    ip_address: row.ip_address,
+                    location: row.location,
+                    isp: row.isp,
+                    created_at,
+                })
+            });
+            (index + 2, punch)
+        })
+        .collect()
+}
+
+fn parse_terminal_csv(content: &str, offset: &FixedOffset) -> Vec<(usize, Result<Punch, String>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+    reader
+        .deserialize::<TerminalRow>()
+        .enumerate()
+        .map(|(index, row)| {
+            let punch = row.map_err(|err| err.to_string()).and_then(|row| {
+                let login_status = punch_status(&row.state)
+                    .ok_or_else(|| format!("Unknown punch state {}", row.state))?;
+                Ok(Punch {
+                    name: row.name,
+                    device_id: row.device_id,
+                    ..Punch::terminal(
+                        &row.employee_id,
+                        login_status,
+                        punch_time(&row.punch_time, offset)?,
+                    )
+                })
+            });
+            (index + 2, punch)
+        })
+        .collect()
+}
+
+fn parse_attlog(content: &str, offset: &FixedOffset) -> Vec<(usize, Result<Punch, String>)> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let punch = match fields.as_slice() {
+                [pin, date, time, _verify, state, ..] => punch_status(state)
+                    .ok_or_else(|| format!("Unknown punch state {state}"))
+                    .and_then(|status| {
+                        Ok(Punch::terminal(
+                            pin,
+                            status,
+                            punch_time(&format!("{date} {time}"), offset)?,
+                        ))
+                    }),
+                _ => Err("Expected PIN, date, time, verify mode and state".to_string()),
+            };
+            (index + 1, punch)
+        })
+        .collect()
+}
+
+/// Loads `external_id,user_id` rows from a CSV into the id map of `source`.
+pub async fn load_id_map(db: &mut Db, source: &str, path: &str) -> Result<usize, String> {
+    #[derive(Debug, Deserialize)]
+    struct MapRow {
+        external_id: String,
+        user_id: String,
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|err| format!("Failed to open {path}: {err}"))?;
+    let mut mapped = 0;
+    for row in reader.deserialize::<MapRow>() {
+        let row = row.map_err(|err| format!("{path}: {err}"))?;
+        if row.external_id.is_empty() || row.user_id.is_empty() {
+            continue;
+        }
+        if db
+            .import()
+            .map_id(source, &row.external_id, &row.user_id)
+            .await
+        {
+            mapped += 1;
+        }
+    }
+    Ok(mapped)
+}
+
+/// Reads `content` and adds its punches to `device_login`. Punches already
+/// present, from an earlier import or clocked live, are skipped. Terminal
+/// times without a zone are taken at `offset`.
+pub async fn import(
+    db: &mut Db,
+    format: ImportFormat,
+    source: &str,
+    content: &str,
+    offset: &FixedOffset,
+) -> Result<ImportReport, String> {
+    let punches = match format {
+        ImportFormat::Export => parse_export(content),
+        ImportFormat::Csv => parse_terminal_csv(content, offset),
+        ImportFormat::Attlog => parse_attlog(content, offset),
+    };
+    let id_map = match format {
+        ImportFormat::Export => None,
+        ImportFormat::Csv | ImportFormat::Attlog => Some(db.import().id_map(source).await?),
+    };
+
+    let mut report = ImportReport::default();
+    let mut directory = HashMap::new();
+    let mut batch = Vec::new();
+    for (line, punch) in punches {
+        let punch = match punch {
+            Ok(punch) => punch,
+            Err(err) => {
+                report.reject(line, Rejection::Invalid, "", err);
+                continue;
+            }
+        };
+        let user_id = match &id_map {
+            None if punch.external_id.is_empty() => {
+                report.reject(line, Rejection::Invalid, "", "No user_id".to_string());
+                continue;
+            }
+            None => punch.external_id.clone(),
+            Some(id_map) => match id_map.get(&punch.external_id) {
+                Some(user_id) => user_id.clone(),
+                None => {
+                    report.reject(
+                        line,
+                        Rejection::Unmapped,
+                        &punch.external_id,
+                        format!("No user mapped to {} in {source}", punch.external_id),
+                    );
+                    continue;
+                }
+            },
+        };
+
+        // Terminals only know a PIN, so names come from the directory.
+        if !directory.contains_key(&user_id) {
+            let user = db.directory().user(&user_id).await;
+            directory.insert(user_id.clone(), user);
+        }
+        let known = directory.get(&user_id).and_then(Option::as_ref);
+        let login = NewDeviceLogin {
+            session_id: String::new(),
+            name: match known {
+                Some(user) if punch.name.is_empty() => user.name.clone(),
+                _ => punch.name,
+            },
+            email: match known {
+                Some(user) if punch.email.is_empty() => user.email.clone(),
+                _ => punch.email,
+            },
+            device_id: if punch.device_id.is_empty() {
+                source.to_string()
+            } else {
+                punch.device_id
+            },
+            login_provider: LOGIN_PROVIDER_IMPORT.to_string(),
+            login_status: punch.login_status,
+            ip_address: punch.ip_address,
+            location: punch.location,
+            isp: punch.isp,
+            user_id,
+        };
+        batch.push((login, punch.created_at));
+
+        if batch.len() >= INSERT_BATCH_SIZE {
+            insert_batch(db, &mut batch, &mut report).await?;
+        }
+    }
+    insert_batch(db, &mut batch, &mut report).await?;
+
+    log::info!(
+        "Imported {} clock events from {source} ({format})",
+        report.imported
+    );
+    Ok(report)
+}
+
+async fn insert_batch(
+    db: &mut Db,
+    batch: &mut Vec<(NewDeviceLogin, String)>,
+    report: &mut ImportReport,
+) -> Result<(), String> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    for inserted in db.device_login().insert_new(batch).await? {
+        if inserted {
+            report.imported += 1;
+        } else {
+            report.duplicates += 1;
+        }
+    }
+    batch.clear();
+    Ok(())
+}
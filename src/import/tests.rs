@@ -0,0 +1,299 @@
+//! Reads terminal punches in their various spellings and imports them,
+//! checking what is reported for lines that cannot be taken over.
+
+use chrono::FixedOffset;
+
+use super::{
+    ImportFormat, Punch, Rejection, import, parse_attlog, parse_terminal_csv, punch_status,
+    punch_time,
+};
+use crate::{
+    config::Config,
+    db,
+    users::device_login::{LoginStatus, canonical_time},
+};
+
+const SOURCE: &str = "zk-lobby";
+
+/// Terminals in Manila keep local time.
+fn manila() -> FixedOffset {
+    FixedOffset::east_opt(8 * 3600).unwrap()
+}
+
+/// Who punched what when, or why the line could not be read.
+type Read = Result<(String, LoginStatus, String), String>;
+
+/// Each parsed line as its number and what was read from it.
+fn read(punches: Vec<(usize, Result<Punch, String>)>) -> Vec<(usize, Read)> {
+    punches
+        .into_iter()
+        .map(|(line, punch)| {
+            let punch =
+                punch.map(|punch| (punch.external_id, punch.login_status, punch.created_at));
+            (line, punch)
+        })
+        .collect()
+}
+
+#[test]
+fn punch_states() {
+    let cases = [
+        // ZKTeco state codes.
+        ("0", Some(LoginStatus::In)),
+        ("1", Some(LoginStatus::Out)),
+        ("2", Some(LoginStatus::BreakStart)),
+        ("3", Some(LoginStatus::BreakEnd)),
+        ("4", Some(LoginStatus::In)),
+        ("5", Some(LoginStatus::Out)),
+        // Text as terminal software spells it.
+        ("C/In", Some(LoginStatus::In)),
+        (" check-out ", Some(LoginStatus::Out)),
+        ("OT_In", Some(LoginStatus::In)),
+        ("Break Out", Some(LoginStatus::BreakStart)),
+        ("break_in", Some(LoginStatus::BreakEnd)),
+        ("6", None),
+        ("Lunch", None),
+        ("", None),
+    ];
+    for (state, status) in cases {
+        assert_eq!(punch_status(state), status, "{state:?}");
+    }
+}
+
+#[test]
+fn punch_times() {
+    let cases = [
+        ("2026-01-05 09:00:00", Ok("2026-01-05T01:00:00.000Z")),
+        ("2026-01-05 09:00", Ok("2026-01-05T01:00:00.000Z")),
+        ("2026/01/05 09:00:30", Ok("2026-01-05T01:00:30.000Z")),
+        // Shortly after midnight local is still the previous day in UTC.
+        ("2026/01/05 07:59", Ok("2026-01-04T23:59:00.000Z")),
+        // A zone given with the time wins over the terminal's.
+        ("2026-01-05T09:00:00+09:00", Ok("2026-01-05T00:00:00.000Z")),
+        ("2026-01-05T01:00:00Z", Ok("2026-01-05T01:00:00.000Z")),
+        (
+            "05/01/2026 09:00",
+            Err("05/01/2026 09:00 is not a date and time"),
+        ),
+        ("", Err(" is not a date and time")),
+    ];
+    for (value, time) in cases {
+        assert_eq!(
+            punch_time(value, &manila()),
+            time.map(str::to_string).map_err(str::to_string),
+            "{value:?}"
+        );
+    }
+}
+
+#[test]
+fn attlog_lines() {
+    let content = "\
+101\t2026-01-05\t08:58:12\t1\t0\t0\t0
+  101  2026-01-05  18:02:00  1  1  0  0
+
+garbage
+102 2026-01-05 09:00:00 15 9 0 0
+102 2026-13-05 09:00:00 15 0 0 0
+";
+    assert_eq!(
+        read(parse_attlog(content, &manila())),
+        [
+            (
+                1,
+                Ok((
+                    "101".to_string(),
+                    LoginStatus::In,
+                    "2026-01-05T00:58:12.000Z".to_string()
+                ))
+            ),
+            (
+                2,
+                Ok((
+                    "101".to_string(),
+                    LoginStatus::Out,
+                    "2026-01-05T10:02:00.000Z".to_string()
+                ))
+            ),
+            (
+                4,
+                Err("Expected PIN, date, time, verify mode and state".to_string())
+            ),
+            (5, Err("Unknown punch state 9".to_string())),
+            (
+                6,
+                Err("2026-13-05 09:00:00 is not a date and time".to_string())
+            ),
+        ]
+    );
+}
+
+#[test]
+fn terminal_csv_headers() {
+    let expected = |line, external_id: &str| {
+        (
+            line,
+            Ok((
+                external_id.to_string(),
+                LoginStatus::In,
+                "2026-01-05T01:00:00.000Z".to_string(),
+            )),
+        )
+    };
+
+    // The same punch under each software's column names.
+    for header in [
+        "employee_id,punch_time,state",
+        "AC-No.,Time,State",
+        "No.,time,Status",
+        "PIN,datetime,status",
+        "pin,timestamp,State",
+    ] {
+        let content = format!("{header}\n 7 , 2026-01-05 09:00 , C/In \n");
+        assert_eq!(
+            read(parse_terminal_csv(&content, &manila())),
+            [expected(2, "7")],
+            "{header}"
+        );
+    }
+
+    let content = "\
+AC-No.,Name,Time,State,Device
+7,Ana Reyes,2026-01-05 09:00,0,gate-2
+8,Ben,yesterday,1,gate-2
+9,Cleo,2026-01-05 18:00,Lunch,gate-2
+";
+    let punches = parse_terminal_csv(content, &manila());
+    let ana = punches[0].1.as_ref().unwrap();
+    assert_eq!(
+        (ana.name.as_str(), ana.device_id.as_str()),
+        ("Ana Reyes", "gate-2")
+    );
+    assert_eq!(
+        read(punches),
+        [
+            expected(2, "7"),
+            (3, Err("yesterday is not a date and time".to_string())),
+            (4, Err("Unknown punch state Lunch".to_string())),
+        ]
+    );
+
+    // Without a time column no line can be read.
+    let rows = read(parse_terminal_csv("PIN,State\n7,0\n", &manila()));
+    assert_eq!(rows.len(), 1);
+    assert!(
+        rows[0]
+            .1
+            .as_ref()
+            .is_err_and(|err| err.contains("punch_time"))
+    );
+}
+
+#[tokio::test]
+async fn import_reports_and_skips_what_it_has() {
+    let config = Config::default();
+    let pool = db::init_db("sqlite::memory:").await;
+    let mut db = db::open(&pool, &config).await;
+    assert!(db.import().map_id(SOURCE, "101", "ana").await);
+    db.directory()
+        .observe(
+            "ana",
+            "Ana Reyes",
+            "ana@example.com",
+            "2026-01-01T00:00:00.000Z",
+        )
+        .await;
+
+    let content = "\
+101 2026-01-05 08:58:12 1 0 0 0
+999 2026-01-05 09:01:00 1 0 0 0
+101 2026-01-05 18:02:00 1 1 0 0
+999 2026-01-05 18:00:00 1 1 0 0
+101 2026-01-05 12:00:00 1 7 0 0
+";
+    let report = import(&mut db, ImportFormat::Attlog, SOURCE, content, &manila())
+        .await
+        .unwrap();
+    assert_eq!((report.imported, report.duplicates), (2, 0));
+    let rejected: Vec<(usize, Rejection, &str)> = report
+        .rejected
+        .iter()
+        .map(|line| (line.line, line.reason, line.external_id.as_str()))
+        .collect();
+    assert_eq!(
+        rejected,
+        [
+            (2, Rejection::Unmapped, "999"),
+            (4, Rejection::Unmapped, "999"),
+            (5, Rejection::Invalid, ""),
+        ]
+    );
+    assert_eq!(
+        report.summary(),
+        "Imported 2 rows, 0 already present, 1 invalid, 2 unmapped. Unmapped ids: 999 (2)."
+    );
+
+    // Terminals only know the PIN, the rest comes from the directory.
+    let logins = db.device_login().device_login_of_user("ana").await;
+    let mut imported: Vec<(String, LoginStatus, &str, &str, &str)> = logins
+        .iter()
+        .map(|login| {
+            (
+                canonical_time(&login.created_at),
+                login.login_status,
+                login.name.as_str(),
+                login.email.as_str(),
+                login.device_id.as_str(),
+            )
+        })
+        .collect();
+    imported.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(
+        imported,
+        [
+            (
+                "2026-01-05T00:58:12.000Z".to_string(),
+                LoginStatus::In,
+                "Ana Reyes",
+                "ana@example.com",
+                SOURCE
+            ),
+            (
+                "2026-01-05T10:02:00.000Z".to_string(),
+                LoginStatus::Out,
+                "Ana Reyes",
+                "ana@example.com",
+                SOURCE
+            ),
+        ]
+    );
+
+    // Importing the file again only adds what is new.
+    let content = format!("{content}101 2026-01-06 08:55:00 1 0 0 0\n");
+    let report = import(&mut db, ImportFormat::Attlog, SOURCE, &content, &manila())
+        .await
+        .unwrap();
+    assert_eq!((report.imported, report.duplicates), (1, 2));
+    assert_eq!(db.device_login().device_login_of_user("ana").await.len(), 3);
+}
+
+#[tokio::test]
+async fn import_fails_without_its_id_map() {
+    let config = Config::default();
+    let pool = db::init_db("sqlite::memory:").await;
+    let mut db = db::open(&pool, &config).await;
+    sqlx::query("DROP TABLE import_id_map;")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let content = "101 2026-01-05 08:58:12 1 0 0 0\n";
+    let imported = import(&mut db, ImportFormat::Attlog, SOURCE, content, &manila()).await;
+    assert!(imported.is_err());
+    assert!(
+        db.device_login()
+            .device_login_of_user("ana")
+            .await
+            .is_empty()
+    );
+}
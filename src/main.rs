@@ -11,61 +11,328 @@ use axum::{
     body::Body,
     http::{Response, StatusCode},
     response::IntoResponse,
-    routing::get,
+    routing::{delete, get, post},
 };
 use axum_server::Server;
 use chrono::Local;
+use clap::Parser;
+use cli::{Cli, Command};
+use config::Config;
 use db::Db;
+use events::LoginEvents;
 use fern::Dispatch;
 use log::LevelFilter;
+use scheduler::Scheduler;
 use tokio::fs;
-use users::device_login::DeviceLoginDatabase;
+use tokio_util::sync::CancellationToken;
 
+mod access;
+mod analytics;
+mod anomalies;
+mod attendance;
+mod audit;
+mod auto_close;
+mod backup;
+mod calendar;
+mod cli;
+mod clock;
+mod config;
 mod db;
+mod devices;
+mod directory;
+mod events;
+mod import;
+mod notifications;
+mod policy;
+mod privacy;
+mod projects;
+mod retention;
+mod scan;
+mod scheduler;
 mod timekeeping;
 mod users;
 mod utils;
+mod webhooks;
 
 pub const DEFAULT_SERVER_ADDRESS: &str = "127.0.0.1:3000";
 
 #[tokio::main]
 async fn main() {
+    let command = Cli::parse().command.unwrap_or(Command::Serve);
+
     let name = env!("CARGO_PKG_NAME");
     let version = env!("CARGO_PKG_VERSION");
     eprintln!("{name} has started v{version}...");
 
-    setup_logger();
+    // Keep stdout clean for the CSV other commands print.
+    setup_logger(matches!(command, Command::Serve));
 
     log::debug!("Debug is enabled.");
     log::trace!("Trace is enabled.");
 
-    let pool = db::init_db("sqlite://enzowebserver.db?mode=rwc").await;
-    let db = Db::new().set_device_login(DeviceLoginDatabase::new(pool.clone()).await);
+    let config = match config::load(config::DEFAULT_CONFIG_PATH) {
+        Ok(config) => config,
+        Err(err) => {
+            log::error!("{err}");
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+    let outcome = match command {
+        Command::Serve => {
+            serve(config).await;
+            Ok(String::new())
+        }
+        Command::Verify { snapshot } => backup::verify(&snapshot, &config).await,
+        Command::Restore { snapshot } => backup::restore(&snapshot, &config).await,
+        Command::CheckDb => backup::verify(db::DB_FILE, &config).await,
+        Command::Migrate => {
+            let pool = db::init_db(&db::url()).await;
+            let outcome = db::migrate(&pool).await;
+            db::close_db(pool).await;
+            outcome
+        }
+        command => {
+            let pool = db::init_db(&db::url()).await;
+            let mut db = db::open(&pool, &config).await;
+            let outcome = cli::run(command, &mut db, &config).await;
+            db::close_db(pool).await;
+            outcome
+        }
+    };
+
+    match outcome {
+        Ok(message) if message.is_empty() => {}
+        Ok(message) => println!("{message}"),
+        Err(err) => {
+            log::error!("{err}");
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+    eprintln!("{name} has ended...");
+}
+
+async fn serve(config: Config) {
+    let pool = db::init_db(&db::url()).await;
+    let db = db::open(&pool, &config).await;
+
+    let events = LoginEvents::new();
+
+    tokio::spawn(events::watch_device_login(db.clone(), events.clone()));
+    tokio::spawn(webhooks::run(db.clone(), config.clone(), events.clone()));
+    tokio::spawn(http_server(db.clone(), config.clone(), events));
 
-    tokio::spawn(http_server(db));
+    let scheduler = notifications::jobs(&config)
+        .into_iter()
+        .chain(auto_close::jobs(&config))
+        .chain(anomalies::jobs(&config))
+        .chain(policy::jobs(&config))
+        .chain(devices::jobs(&config))
+        .chain(directory::jobs(&config))
+        .chain(retention::jobs(&config))
+        .chain(backup::jobs(&config))
+        .fold(Scheduler::new(config.shift.offset()), |scheduler, job| {
+            scheduler.register(&config.scheduler, job)
+        });
+    let token = CancellationToken::new();
+    let scheduler = tokio::spawn(scheduler.run(db, token.clone()));
 
     tokio::select! {
         _ = shutdown_signal() => {
             log::info!("Shutdown signal received.");
         }
     }
+    token.cancel();
+    if let Err(err) = scheduler.await {
+        log::error!("Scheduler task failed: {err}");
+    }
     db::close_db(pool).await;
-    eprintln!("{name} has ended...");
 }
 
-async fn http_server(db: Db) {
+async fn http_server(db: Db, config: Config, events: LoginEvents) {
     let app = Router::new()
         .route("/external/timekeeping/css/{*file}", get(serve_css))
         .route(
             "/external/timekeeping",
             get(timekeeping::handle_timekeeping),
         )
-        .layer(Extension(db));
+        .route(
+            "/external/timekeeping/calendar",
+            get(calendar::handle_calendar),
+        )
+        .route(
+            "/external/timekeeping/analytics",
+            get(analytics::handle_dashboard),
+        )
+        .route(
+            "/external/timekeeping/analytics/arrivals",
+            get(analytics::handle_arrivals),
+        )
+        .route(
+            "/external/timekeeping/analytics/attendance",
+            get(analytics::handle_attendance),
+        )
+        .route(
+            "/external/timekeeping/analytics/overtime",
+            get(analytics::handle_overtime),
+        )
+        .route(
+            "/external/timekeeping/analytics/networks",
+            get(analytics::handle_networks),
+        )
+        .route("/external/timekeeping/clock", post(clock::handle_clock))
+        .route(
+            "/external/timekeeping/export",
+            get(timekeeping::handle_export),
+        )
+        .route("/external/timekeeping/events", get(events::handle_events))
+        .route(
+            "/external/timekeeping/jobs",
+            get(scheduler::handle_job_runs),
+        )
+        .route(
+            "/external/timekeeping/anomalies",
+            get(anomalies::handle_anomalies),
+        )
+        .route(
+            "/external/timekeeping/webhooks",
+            get(webhooks::list_subscriptions).post(webhooks::create_subscription),
+        )
+        .route(
+            "/external/timekeeping/webhooks/{id}",
+            delete(webhooks::delete_subscription),
+        )
+        .route(
+            "/external/timekeeping/webhooks/{id}/deliveries",
+            get(webhooks::list_deliveries),
+        )
+        .route(
+            "/external/timekeeping/policies",
+            get(policy::list_policies).post(policy::create_policy),
+        )
+        .route(
+            "/external/timekeeping/policies/{id}",
+            delete(policy::delete_policy),
+        )
+        .route(
+            "/external/timekeeping/policies/groups",
+            post(policy::add_group_member),
+        )
+        .route(
+            "/external/timekeeping/policies/groups/{group}/{user_id}",
+            delete(policy::remove_group_member),
+        )
+        .route(
+            "/external/timekeeping/policies/violations",
+            get(policy::list_violations),
+        )
+        .route(
+            "/external/timekeeping/devices",
+            get(devices::handle_devices),
+        )
+        .route(
+            "/external/timekeeping/devices/{device_id}/approve",
+            post(devices::approve_device),
+        )
+        .route(
+            "/external/timekeeping/devices/{device_id}/revoke",
+            post(devices::revoke_device),
+        )
+        .route("/external/timekeeping/users", get(directory::list_users))
+        .route(
+            "/external/timekeeping/users/{user_id}",
+            post(directory::update_user),
+        )
+        .route(
+            "/external/timekeeping/users/{user_id}/data",
+            get(privacy::export_user_data),
+        )
+        .route(
+            "/external/timekeeping/users/{user_id}/erase",
+            post(privacy::erase_user_data),
+        )
+        .route(
+            "/external/timekeeping/teams",
+            get(directory::list_teams).post(directory::create_team),
+        )
+        .route(
+            "/external/timekeeping/teams/{team}",
+            delete(directory::delete_team),
+        )
+        .route(
+            "/external/timekeeping/teams/{team}/members",
+            post(directory::add_team_member),
+        )
+        .route(
+            "/external/timekeeping/teams/{team}/members/{user_id}",
+            delete(directory::remove_team_member),
+        )
+        .route(
+            "/external/timekeeping/reporting-lines",
+            get(directory::list_reporting_lines).post(directory::add_reporting_line),
+        )
+        .route(
+            "/external/timekeeping/reporting-lines/{manager_id}/{report_id}",
+            delete(directory::remove_reporting_line),
+        )
+        .route(
+            "/external/timekeeping/projects",
+            get(projects::list_projects).post(projects::create_project),
+        )
+        .route(
+            "/external/timekeeping/projects/report",
+            get(projects::handle_report),
+        )
+        .route(
+            "/external/timekeeping/projects/{id}",
+            delete(projects::archive_project),
+        )
+        .route(
+            "/external/timekeeping/projects/{id}/tasks",
+            post(projects::add_task),
+        )
+        .route(
+            "/external/timekeeping/allocations",
+            get(projects::list_allocations).post(projects::create_allocation),
+        )
+        .route(
+            "/external/timekeeping/allocations/{id}",
+            delete(projects::delete_allocation),
+        )
+        .route(
+            "/external/timekeeping/holidays",
+            get(calendar::list_holidays).post(calendar::create_holiday),
+        )
+        .route(
+            "/external/timekeeping/holidays/{date}",
+            delete(calendar::delete_holiday),
+        )
+        .route(
+            "/external/timekeeping/leave",
+            get(calendar::list_leave).post(calendar::create_leave),
+        )
+        .route(
+            "/external/timekeeping/leave/{id}",
+            delete(calendar::delete_leave),
+        )
+        .route("/external/timekeeping/audit", get(audit::handle_audit_log))
+        .route(
+            "/external/timekeeping/roles",
+            get(access::list_roles).post(access::assign_role),
+        )
+        .route(
+            "/external/timekeeping/roles/{user_id}",
+            delete(access::remove_role),
+        )
+        .layer(Extension(db))
+        .layer(Extension(config))
+        .layer(Extension(events));
 
     let addr = SocketAddr::from_str(DEFAULT_SERVER_ADDRESS).unwrap();
 
     Server::bind(addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
@@ -118,7 +385,7 @@ async fn shutdown_signal() {
     }
 }
 
-fn setup_logger() {
+fn setup_logger(to_stdout: bool) {
     let level_filter = match (Path::new("trace").exists(), Path::new("debug").exists()) {
         (true, true) | (true, false) => LevelFilter::Trace,
         (false, true) => LevelFilter::Debug,
@@ -157,7 +424,11 @@ fn setup_logger() {
             }
         })
         .level(level_filter)
-        .chain(io::stdout())
+        .chain(if to_stdout {
+            fern::Output::from(io::stdout())
+        } else {
+            fern::Output::from(io::stderr())
+        })
         .apply()
     {
         log::error!("Logger initialization failed: {:?}", e);
@@ -0,0 +1,53 @@
+use sqlx::{Pool, Sqlite};
+
+#[derive(Clone, Debug)]
+pub struct NotificationDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl NotificationDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn was_sent(&self, kind: &str, dedupe_key: &str, recipient: &str) -> bool {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*)
+            FROM notification_log
+            WHERE kind = ?
+              AND dedupe_key = ?
+              AND recipient = ?;
+            "#,
+        )
+        .bind(kind)
+        .bind(dedupe_key)
+        .bind(recipient)
+        .fetch_one(&self.pool)
+        .await
+        .map(|count| count > 0)
+        .unwrap_or_else(|err| {
+            log::error!("NotificationDatabase::was_sent: {err}");
+            // Pretend it was sent rather than risk mailing someone repeatedly.
+            true
+        })
+    }
+
+    pub async fn record_sent(&self, kind: &str, dedupe_key: &str, recipient: &str, sent_at: &str) {
+        if let Err(err) = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO notification_log (kind, dedupe_key, recipient, sent_at)
+            VALUES (?, ?, ?, ?);
+            "#,
+        )
+        .bind(kind)
+        .bind(dedupe_key)
+        .bind(recipient)
+        .bind(sent_at)
+        .execute(&self.pool)
+        .await
+        {
+            log::error!("NotificationDatabase::record_sent: {err}");
+        }
+    }
+}
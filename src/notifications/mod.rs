@@ -0,0 +1,313 @@
+pub mod database;
+
+#[cfg(test)]
+mod tests;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Days, SecondsFormat, Utc};
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, header::ContentType},
+    transport::smtp::authentication::Credentials,
+};
+use tera::{Context, Tera};
+
+use crate::{
+    attendance,
+    config::{Config, SmtpConfig},
+    db::Db,
+    scheduler::Job,
+    users::device_login::LoginStatus,
+};
+
+const EMAIL_TEMPLATE_PATH: &str = "www/email/*";
+const KIND_MISSED_CLOCK_OUT: &str = "missed_clock_out";
+const KIND_DAILY_DIGEST: &str = "daily_digest";
+
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    tera: Tera,
+}
+
+impl Mailer {
+    /// Returns `None` when no SMTP host is configured.
+    pub fn new(smtp: &SmtpConfig) -> Option<Self> {
+        if smtp.host.is_empty() {
+            return None;
+        }
+
+        let builder = match smtp.security.as_str() {
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host),
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host),
+            _ => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &smtp.host,
+            )),
+        };
+        let mut builder = match builder {
+            Ok(builder) => builder.port(smtp.port),
+            Err(err) => {
+                log::error!("Invalid SMTP host {}: {err}", smtp.host);
+                return None;
+            }
+        };
+        if !smtp.username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                smtp.username.clone(),
+                smtp.password.clone(),
+            ));
+        }
+
+        let from = match smtp.from.parse::<Mailbox>() {
+            Ok(from) => from,
+            Err(err) => {
+                log::error!("Invalid SMTP from address {}: {err}", smtp.from);
+                return None;
+            }
+        };
+        let tera = match Tera::new(EMAIL_TEMPLATE_PATH) {
+            Ok(tera) => tera,
+            Err(err) => {
+                log::error!("Failed to load email templates: {err}");
+                return None;
+            }
+        };
+
+        Some(Self {
+            transport: builder.build(),
+            from,
+            tera,
+        })
+    }
+
+    pub async fn send(
+        &self,
+        to: &str,
+        subject: &str,
+        template: &str,
+        context: &Context,
+    ) -> Result<(), String> {
+        let to = to.parse::<Mailbox>().map_err(|e| e.to_string())?;
+        let body = self
+            .tera
+            .render(template, context)
+            .map_err(|e| e.to_string())?;
+        let content_type = if template.ends_with(".html") {
+            ContentType::TEXT_HTML
+        } else {
+            ContentType::TEXT_PLAIN
+        };
+
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .header(content_type)
+            .body(body)
+            .map_err(|e| e.to_string())?;
+
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+fn now_rfc3339() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Builds the notification jobs, or none when email is not configured.
+pub fn jobs(config: &Config) -> Vec<Arc<dyn Job>> {
+    let Some(mailer) = Mailer::new(&config.smtp) else {
+        log::info!("Email notifications are disabled.");
+        return Vec::new();
+    };
+    let mailer = Arc::new(mailer);
+
+    log::info!("Email notifications via {}", config.smtp.host);
+    vec![
+        Arc::new(MissedClockOutJob {
+            config: config.clone(),
+            mailer: mailer.clone(),
+        }),
+        Arc::new(DailyDigestJob {
+            config: config.clone(),
+            mailer,
+        }),
+    ]
+}
+
+pub struct MissedClockOutJob {
+    config: Config,
+    mailer: Arc<Mailer>,
+}
+
+#[async_trait]
+impl Job for MissedClockOutJob {
+    fn name(&self) -> &'static str {
+        "missed_clock_out_notices"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "0 */5 * * * *"
+    }
+
+    async fn run(&self, db: &mut Db) -> Result<String, String> {
+        let sent = send_missed_clock_outs(db, &self.config, &self.mailer).await;
+        Ok(format!("{sent} notices sent"))
+    }
+}
+
+pub struct DailyDigestJob {
+    config: Config,
+    mailer: Arc<Mailer>,
+}
+
+#[async_trait]
+impl Job for DailyDigestJob {
+    fn name(&self) -> &'static str {
+        "daily_digest"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "0 0 7 * * *"
+    }
+
+    async fn run(&self, db: &mut Db) -> Result<String, String> {
+        let sent = send_daily_digest(db, &self.config, &self.mailer).await;
+        Ok(format!("{sent} digests sent"))
+    }
+}
+
+/// Mails everyone still on the clock, clocked in or on a break, whose latest
+/// event is older than the configured number of hours, once per session.
+pub async fn send_missed_clock_outs(db: &mut Db, config: &Config, mailer: &Mailer) -> usize {
+    let hours = config.shift.missing_clock_out_hours;
+    let (oldest, cutoff) = config.shift.missing_clock_out_window(Utc::now());
+    let offset = config.shift.offset();
+    let open = db
+        .device_login()
+        .open_sessions_before(
+            Some(&oldest.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            &cutoff.to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+        .await;
+    let local_time = |at: DateTime<Utc>| {
+        at.with_timezone(&offset)
+            .format("%Y-%m-%d %H:%M %:z")
+            .to_string()
+    };
+
+    let mut sent = 0;
+    for session in open {
+        let login = session.login;
+        let key = format!(
+            "{}@{}",
+            login.user_id,
+            session
+                .clocked_in_at
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+        );
+        if login.email.is_empty()
+            || db
+                .notification()
+                .was_sent(KIND_MISSED_CLOCK_OUT, &key, &login.email)
+                .await
+        {
+            continue;
+        }
+
+        let mut context = Context::new();
+        context.insert("name", &login.name);
+        context.insert("clocked_in_at", &local_time(session.clocked_in_at));
+        context.insert("hours", &hours);
+        if login.login_status != LoginStatus::In {
+            context.insert("last_status", &login.login_status);
+            context.insert("last_event_at", &local_time(login.created_at));
+        }
+        context.insert("device_id", &login.device_id);
+        context.insert("location", &login.location);
+
+        match mailer
+            .send(
+                &login.email,
+                "You have not clocked out",
+                "missed_clock_out.txt",
+                &context,
+            )
+            .await
+        {
+            Ok(()) => {
+                log::info!("Sent missed clock-out notice to {}", login.email);
+                db.notification()
+                    .record_sent(KIND_MISSED_CLOCK_OUT, &key, &login.email, &now_rfc3339())
+                    .await;
+                sent += 1;
+            }
+            Err(err) => log::error!("Failed to mail {}: {err}", login.email),
+        }
+    }
+    sent
+}
+
+/// Mails the previous local day's attendance to every admin address that
+/// has not had it yet.
+pub async fn send_daily_digest(db: &mut Db, config: &Config, mailer: &Mailer) -> usize {
+    let offset = config.shift.offset();
+    let now = Utc::now().with_timezone(&offset);
+    let Some(date) = now.date_naive().checked_sub_days(Days::new(1)) else {
+        return 0;
+    };
+    let key = date.to_string();
+
+    let mut recipients = Vec::new();
+    for admin in &config.notification.admin_emails {
+        if !db
+            .notification()
+            .was_sent(KIND_DAILY_DIGEST, &key, admin)
+            .await
+        {
+            recipients.push(admin.clone());
+        }
+    }
+    if recipients.is_empty() {
+        return 0;
+    }
+
+    let (start, end) = attendance::local_day_bounds(date, &offset);
+    let logins = db.device_login().device_login_between(&start, &end).await;
+    let summaries = attendance::daily_summaries(&logins, config);
+
+    let mut context = Context::new();
+    context.insert("date", &key);
+    context.insert("present", &summaries.len());
+    context.insert("late", &summaries.iter().filter(|s| s.late).count());
+    context.insert(
+        "open_sessions",
+        &summaries.iter().filter(|s| s.open_session).count(),
+    );
+    context.insert("summaries", &summaries);
+
+    let subject = format!("Attendance digest for {key}");
+    let mut sent = 0;
+    for admin in recipients {
+        match mailer
+            .send(&admin, &subject, "daily_digest.html", &context)
+            .await
+        {
+            Ok(()) => {
+                log::info!("Sent daily digest for {key} to {admin}");
+                db.notification()
+                    .record_sent(KIND_DAILY_DIGEST, &key, &admin, &now_rfc3339())
+                    .await;
+                sent += 1;
+            }
+            Err(err) => log::error!("Failed to mail {admin}: {err}"),
+        }
+    }
+    sent
+}
@@ -0,0 +1,135 @@
+//! Mails missed clock-out notices through a local SMTP sink, checking what
+//! a user receives and that each session is only reported once.
+
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use super::{Mailer, send_missed_clock_outs};
+use crate::{
+    config::Config,
+    db::{self, Db},
+    users::device_login::{LoginStatus, NewDeviceLogin, canonical_time},
+};
+
+/// The `DATA` of every message the sink accepted.
+type Received = Arc<Mutex<Vec<String>>>;
+
+/// Speaks just enough SMTP to accept whatever it is sent.
+async fn accept(stream: TcpStream, received: Received) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    writer.write_all(b"220 sink ESMTP\r\n").await.unwrap();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let command = line.to_ascii_uppercase();
+        let reply: &[u8] = if command.starts_with("EHLO") || command.starts_with("HELO") {
+            b"250 sink\r\n"
+        } else if command.starts_with("DATA") {
+            writer.write_all(b"354 go ahead\r\n").await.unwrap();
+            let mut data = String::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line == "." {
+                    break;
+                }
+                data.push_str(&line);
+                data.push('\n');
+            }
+            received.lock().unwrap().push(data);
+            b"250 queued\r\n"
+        } else if command.starts_with("QUIT") {
+            writer.write_all(b"221 bye\r\n").await.unwrap();
+            break;
+        } else {
+            b"250 ok\r\n"
+        };
+        writer.write_all(reply).await.unwrap();
+    }
+}
+
+async fn serve_sink() -> (u16, Received) {
+    let received = Received::default();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let sink = received.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(accept(stream, sink.clone()));
+        }
+    });
+    (port, received)
+}
+
+fn clock(user_id: &str, status: LoginStatus) -> NewDeviceLogin {
+    NewDeviceLogin {
+        user_id: user_id.to_string(),
+        session_id: format!("{user_id}-session"),
+        name: user_id.to_string(),
+        email: format!("{user_id}@example.com"),
+        device_id: "front-door".to_string(),
+        login_provider: "google".to_string(),
+        login_status: status,
+        ip_address: "10.1.2.3".to_string(),
+        location: "Manila".to_string(),
+        isp: "PLDT".to_string(),
+    }
+}
+
+async fn insert(db: &mut Db, user_id: &str, status: LoginStatus, at: DateTime<Utc>) {
+    db.device_login()
+        .insert(&clock(user_id, status), &canonical_time(&at))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn missed_clock_outs_are_mailed_once_per_session() {
+    let (port, received) = serve_sink().await;
+    let mut config = Config::default();
+    config.shift.utc_offset_hours = 0;
+    config.smtp.host = "127.0.0.1".to_string();
+    config.smtp.port = port;
+    let mailer = Mailer::new(&config.smtp).unwrap();
+    let pool = db::init_db("sqlite::memory:").await;
+    let mut db = db::open(&pool, &config).await;
+
+    // Still in, on a break since, clocked out, and left open long ago.
+    let now = Utc::now();
+    let hours_ago = |hours| now - Duration::hours(hours);
+    insert(&mut db, "ivan", LoginStatus::In, hours_ago(14)).await;
+    insert(&mut db, "bea", LoginStatus::In, hours_ago(20)).await;
+    insert(&mut db, "bea", LoginStatus::BreakStart, hours_ago(16)).await;
+    insert(&mut db, "olga", LoginStatus::In, hours_ago(20)).await;
+    insert(&mut db, "olga", LoginStatus::Out, hours_ago(13)).await;
+    insert(&mut db, "old", LoginStatus::In, hours_ago(24 * 30)).await;
+
+    assert_eq!(send_missed_clock_outs(&mut db, &config, &mailer).await, 2);
+    let messages = received.lock().unwrap().clone();
+    assert_eq!(messages.len(), 2);
+    let to = |user: &str| {
+        messages
+            .iter()
+            .find(|message| message.contains(&format!("To: {user}@example.com")))
+            .unwrap_or_else(|| panic!("no notice to {user}"))
+            .clone()
+    };
+
+    let bea = to("bea");
+    assert!(bea.contains(&format!(
+        "You clocked IN on {}",
+        hours_ago(20).format("%Y-%m-%d %H:%M +00:00")
+    )));
+    assert!(bea.contains("Your last clock event was BREAK_START"));
+    let ivan = to("ivan");
+    assert!(ivan.contains("You clocked IN on"));
+    assert!(!ivan.contains("Your last clock event"));
+
+    // A later break does not make it a new session to report.
+    insert(&mut db, "ivan", LoginStatus::BreakStart, hours_ago(13)).await;
+    assert_eq!(send_missed_clock_outs(&mut db, &config, &mailer).await, 0);
+    assert_eq!(received.lock().unwrap().len(), 2);
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, QueryBuilder, Sqlite};
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct NetworkPolicy {
+    pub id: i64,
+    pub scope: String,
+    pub scope_value: String,
+    pub rule_type: String,
+    pub value: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct GroupMember {
+    pub group_name: String,
+    pub user_id: String,
+}
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct LoginCompliance {
+    pub login_id: i64,
+    pub user_id: String,
+    pub compliant: bool,
+    pub reason: String,
+    pub evaluated_at: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct PolicyDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl PolicyDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn policies(&self) -> Vec<NetworkPolicy> {
+        sqlx::query_as::<_, NetworkPolicy>(
+            r#"
+            SELECT id, scope, scope_value, rule_type, value, created_at
+            FROM network_policy
+            ORDER BY id ASC;
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PolicyDatabase::policies: {err}");
+            Vec::new()
+        })
+    }
+
+    /// Policies that apply to `user_id`: their own, their groups' and
+    /// everyone's. Teams from the directory count as groups too.
+    pub async fn policies_for_user(&self, user_id: &str) -> Vec<NetworkPolicy> {
+        sqlx::query_as::<_, NetworkPolicy>(
+            r#"
+            SELECT id, scope, scope_value, rule_type, value, created_at
+            FROM network_policy
+            WHERE scope = 'EVERYONE'
+               OR (scope = 'USER' AND scope_value = ?1)
+               OR (scope = 'GROUP' AND scope_value IN (
+                       SELECT group_name FROM policy_group_member WHERE user_id = ?1
+                       UNION
+                       SELECT team FROM team_member WHERE user_id = ?1
+                   ))
+            ORDER BY id ASC;
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PolicyDatabase::policies_for_user: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn add_policy(
+        &self,
+        scope: &str,
+        scope_value: &str,
+        rule_type: &str,
+        value: &str,
+        created_at: &str,
+    ) -> Result<i64, String> {
+        sqlx::query(
+            r#"
+            INSERT INTO network_policy (scope, scope_value, rule_type, value, created_at)
+            VALUES (?, ?, ?, ?, ?);
+            "#,
+        )
+        .bind(scope)
+        .bind(scope_value)
+        .bind(rule_type)
+        .bind(value)
+        .bind(created_at)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.last_insert_rowid())
+        .map_err(|err| {
+            log::error!("PolicyDatabase::add_policy: {err}");
+            err.to_string()
+        })
+    }
+
+    pub async fn remove_policy(&self, id: i64) -> bool {
+        sqlx::query("DELETE FROM network_policy WHERE id = ?;")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or_else(|err| {
+                log::error!("PolicyDatabase::remove_policy: {err}");
+                false
+            })
+    }
+
+    pub async fn group_members(&self) -> Vec<GroupMember> {
+        sqlx::query_as::<_, GroupMember>(
+            "SELECT group_name, user_id FROM policy_group_member ORDER BY group_name, user_id;",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PolicyDatabase::group_members: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn add_group_member(&self, group_name: &str, user_id: &str) -> bool {
+        sqlx::query(
+            "INSERT OR IGNORE INTO policy_group_member (group_name, user_id) VALUES (?, ?);",
+        )
+        .bind(group_name)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .unwrap_or_else(|err| {
+            log::error!("PolicyDatabase::add_group_member: {err}");
+            false
+        })
+    }
+
+    pub async fn remove_group_member(&self, group_name: &str, user_id: &str) -> bool {
+        sqlx::query("DELETE FROM policy_group_member WHERE group_name = ? AND user_id = ?;")
+            .bind(group_name)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or_else(|err| {
+                log::error!("PolicyDatabase::remove_group_member: {err}");
+                false
+            })
+    }
+
+    pub async fn record_compliance(
+        &self,
+        login_id: i64,
+        user_id: &str,
+        compliant: bool,
+        reason: &str,
+        evaluated_at: &str,
+    ) {
+        if let Err(err) = sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO login_compliance
+                (login_id, user_id, compliant, reason, evaluated_at)
+            VALUES (?, ?, ?, ?, ?);
+            "#,
+        )
+        .bind(login_id)
+        .bind(user_id)
+        .bind(compliant)
+        .bind(reason)
+        .bind(evaluated_at)
+        .execute(&self.pool)
+        .await
+        {
+            log::error!("PolicyDatabase::record_compliance: {err}");
+        }
+    }
+
+    /// Compliance of the given logins, keyed by login id.
+    pub async fn for_logins(&self, login_ids: &[i64]) -> HashMap<i64, LoginCompliance> {
+        if login_ids.is_empty() {
+            return HashMap::new();
+        }
+
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "SELECT login_id, user_id, compliant, reason, evaluated_at FROM login_compliance WHERE login_id IN (",
+        );
+        let mut ids = query.separated(", ");
+        for id in login_ids {
+            ids.push_bind(id);
+        }
+        query.push(");");
+
+        query
+            .build_query_as::<LoginCompliance>()
+            .fetch_all(&self.pool)
+            .await
+            .unwrap_or_else(|err| {
+                log::error!("PolicyDatabase::for_logins: {err}");
+                Vec::new()
+            })
+            .into_iter()
+            .map(|compliance| (compliance.login_id, compliance))
+            .collect()
+    }
+
+    pub async fn violations(&self, limit_per_page: u64, page_number: u64) -> Vec<LoginCompliance> {
+        let limit = limit_per_page;
+        let offset = limit * (page_number - 1);
+
+        sqlx::query_as::<_, LoginCompliance>(
+            r#"
+            SELECT login_id, user_id, compliant, reason, evaluated_at
+            FROM login_compliance
+            WHERE compliant = 0
+            ORDER BY login_id DESC
+            LIMIT ? OFFSET ?;
+            "#,
+        )
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PolicyDatabase::violations: {err}");
+            Vec::new()
+        })
+    }
+}
@@ -0,0 +1,438 @@
+pub mod database;
+
+#[cfg(test)]
+mod tests;
+
+use std::{net::IpAddr, str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::Path,
+    http::{Request, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{SecondsFormat, Utc};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use strum_macros::{AsRefStr, Display, EnumString};
+
+use crate::{
+    access::{self, Permission},
+    audit::{self, AuditAction},
+    config::Config,
+    db::Db,
+    scan::{self, LoginScan},
+    scheduler::Job,
+    users::device_login::{DeviceLogin, LoginStatus},
+    utils,
+};
+use database::{LoginCompliance, NetworkPolicy};
+
+const VIOLATIONS_PER_PAGE: u64 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, AsRefStr, EnumString)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE", ascii_case_insensitive)]
+pub enum PolicyScope {
+    User,
+    Group,
+    Everyone,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, AsRefStr, EnumString)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE", ascii_case_insensitive)]
+pub enum RuleType {
+    /// An IP range such as `203.0.113.0/24`, or a single address.
+    Cidr,
+    /// The ISP name, compared case-insensitively.
+    Isp,
+    /// One component of `location`, e.g. a city or a country.
+    Location,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Verdict {
+    pub compliant: bool,
+    pub reason: String,
+}
+
+fn parse_net(value: &str) -> Option<IpNet> {
+    IpNet::from_str(value)
+        .ok()
+        .or_else(|| IpAddr::from_str(value).ok().map(IpNet::from))
+}
+
+fn matches(policy: &NetworkPolicy, ip_address: &str, location: &str, isp: &str) -> bool {
+    match RuleType::from_str(&policy.rule_type) {
+        Ok(RuleType::Cidr) => match (parse_net(&policy.value), IpAddr::from_str(ip_address)) {
+            (Some(net), Ok(ip)) => net.contains(&ip),
+            _ => false,
+        },
+        Ok(RuleType::Isp) => isp.trim().eq_ignore_ascii_case(policy.value.trim()),
+        Ok(RuleType::Location) => location
+            .split(',')
+            .any(|part| part.trim().eq_ignore_ascii_case(policy.value.trim())),
+        Err(_) => {
+            log::error!(
+                "Unknown rule type {} on policy {}",
+                policy.rule_type,
+                policy.id
+            );
+            false
+        }
+    }
+}
+
+/// Policies are allow-lists: without any the user may clock in from
+/// anywhere, otherwise the event has to match at least one of them.
+pub fn evaluate(
+    policies: &[NetworkPolicy],
+    ip_address: &str,
+    location: &str,
+    isp: &str,
+) -> Verdict {
+    if policies.is_empty() {
+        return Verdict {
+            compliant: true,
+            reason: "no policy applies".to_string(),
+        };
+    }
+
+    match policies
+        .iter()
+        .find(|policy| matches(policy, ip_address, location, isp))
+    {
+        Some(policy) => Verdict {
+            compliant: true,
+            reason: format!(
+                "allowed by {} {} (policy {})",
+                policy.rule_type, policy.value, policy.id
+            ),
+        },
+        None => Verdict {
+            compliant: false,
+            reason: format!("matches none of {} allowed networks", policies.len()),
+        },
+    }
+}
+
+pub async fn evaluate_for_user(
+    db: &mut Db,
+    user_id: &str,
+    ip_address: &str,
+    location: &str,
+    isp: &str,
+) -> Verdict {
+    let policies = db.policy().policies_for_user(user_id).await;
+    evaluate(&policies, ip_address, location, isp)
+}
+
+/// Marks every `device_login` row as compliant or not, in insertion order.
+pub struct ComplianceJob;
+
+pub fn jobs(_config: &Config) -> Vec<Arc<dyn Job>> {
+    vec![Arc::new(ComplianceJob)]
+}
+
+#[async_trait]
+impl Job for ComplianceJob {
+    fn name(&self) -> &'static str {
+        "evaluate_network_policies"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "45 * * * * *"
+    }
+
+    async fn run(&self, db: &mut Db) -> Result<String, String> {
+        let scanned = scan::run(self, db).await;
+        Ok(format!(
+            "{} events evaluated, {} violations",
+            scanned.visited, scanned.findings
+        ))
+    }
+}
+
+#[async_trait]
+impl LoginScan for ComplianceJob {
+    fn cursor(&self) -> &'static str {
+        self.name()
+    }
+
+    async fn visit(&self, db: &mut Db, login: &DeviceLogin) -> Option<u64> {
+        if login.login_status == LoginStatus::AutoOut {
+            return None;
+        }
+
+        let verdict = evaluate_for_user(
+            db,
+            &login.user_id,
+            &login.ip_address,
+            &login.location,
+            &login.isp,
+        )
+        .await;
+        if !verdict.compliant {
+            log::warn!(
+                "device_login {} of {} breaks network policy: {}",
+                login.id,
+                login.user_id,
+                verdict.reason
+            );
+        }
+        db.policy()
+            .record_compliance(
+                login.id,
+                &login.user_id,
+                verdict.compliant,
+                &verdict.reason,
+                &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            )
+            .await;
+        Some(u64::from(!verdict.compliant))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Parameters {
+    page: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NewPolicy {
+    scope: String,
+    #[serde(default)]
+    scope_value: String,
+    rule_type: String,
+    value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NewGroupMember {
+    group: String,
+    user_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Violation {
+    #[serde(flatten)]
+    compliance: LoginCompliance,
+    login: Option<DeviceLogin>,
+}
+
+pub async fn list_policies(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    if let Err(rejection) = access::authorize(&mut db, &request, Permission::ViewReports).await {
+        return rejection.into_response();
+    }
+
+    Json(json!({
+        "policies": db.policy().policies().await,
+        "groups": db.policy().group_members().await,
+    }))
+    .into_response()
+}
+
+pub async fn create_policy(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageConfig).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let new = match utils::json_body::<NewPolicy>(request).await {
+        Ok(new) => new,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let Ok(scope) = PolicyScope::from_str(&new.scope) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Scope must be USER, GROUP or EVERYONE.",
+        )
+            .into_response();
+    };
+    let Ok(rule_type) = RuleType::from_str(&new.rule_type) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Rule type must be CIDR, ISP or LOCATION.",
+        )
+            .into_response();
+    };
+    let scope_value = match scope {
+        PolicyScope::Everyone => "",
+        _ if new.scope_value.trim().is_empty() => {
+            return (StatusCode::BAD_REQUEST, "A user or group is required.").into_response();
+        }
+        _ => new.scope_value.trim(),
+    };
+    if rule_type == RuleType::Cidr && parse_net(new.value.trim()).is_none() {
+        return (StatusCode::BAD_REQUEST, "Invalid CIDR.").into_response();
+    }
+    if new.value.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "A value is required.").into_response();
+    }
+
+    match db
+        .policy()
+        .add_policy(
+            scope.as_ref(),
+            scope_value,
+            rule_type.as_ref(),
+            new.value.trim(),
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+        .await
+    {
+        Ok(id) => {
+            log::info!(
+                "{} added network policy {id}: {scope} {scope_value} {rule_type} {}",
+                principal.user_id,
+                new.value
+            );
+            audit::record(
+                &mut db,
+                &principal,
+                AuditAction::Create,
+                &format!("policy {id}"),
+                &new,
+                1,
+            )
+            .await;
+            (StatusCode::CREATED, Json(json!({ "id": id }))).into_response()
+        }
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err).into_response(),
+    }
+}
+
+pub async fn delete_policy(
+    Extension(mut db): Extension<Db>,
+    Path(id): Path<i64>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageConfig).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if db.policy().remove_policy(id).await {
+        log::info!("{} removed network policy {id}", principal.user_id);
+        audit::record(
+            &mut db,
+            &principal,
+            AuditAction::Delete,
+            &format!("policy {id}"),
+            &(),
+            1,
+        )
+        .await;
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "No such policy.").into_response()
+    }
+}
+
+pub async fn add_group_member(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageConfig).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let new = match utils::json_body::<NewGroupMember>(request).await {
+        Ok(new) => new,
+        Err(rejection) => return rejection.into_response(),
+    };
+    if new.group.trim().is_empty() || new.user_id.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "A group and a user are required.").into_response();
+    }
+
+    db.policy()
+        .add_group_member(new.group.trim(), new.user_id.trim())
+        .await;
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Create,
+        &format!("policy_group {}", new.group.trim()),
+        &new,
+        1,
+    )
+    .await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+pub async fn remove_group_member(
+    Extension(mut db): Extension<Db>,
+    Path((group, user_id)): Path<(String, String)>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageConfig).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if db.policy().remove_group_member(&group, &user_id).await {
+        audit::record(
+            &mut db,
+            &principal,
+            AuditAction::Delete,
+            &format!("policy_group {group}"),
+            &json!({ "user_id": user_id }),
+            1,
+        )
+        .await;
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "No such group member.").into_response()
+    }
+}
+
+pub async fn list_violations(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewReports).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let page = params
+        .page
+        .as_deref()
+        .and_then(|page| page.parse::<u64>().ok())
+        .unwrap_or(1)
+        .max(1);
+
+    let violations = db.policy().violations(VIOLATIONS_PER_PAGE, page).await;
+    let ids: Vec<i64> = violations.iter().map(|v| v.login_id).collect();
+    let mut logins = db.device_login().device_login_by_ids(&ids).await;
+
+    let violations: Vec<Violation> = violations
+        .into_iter()
+        .map(|compliance| {
+            let login = logins
+                .iter()
+                .position(|login| login.id == compliance.login_id)
+                .map(|index| logins.swap_remove(index));
+            Violation { compliance, login }
+        })
+        .collect();
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::View,
+        "policy_violations",
+        &params,
+        violations.len(),
+    )
+    .await;
+
+    Json(violations).into_response()
+}
@@ -0,0 +1,101 @@
+//! Evaluates clock-in networks against allow-lists, both built in place and
+//! looked up for a user through their own, group and everyone policies.
+
+use super::{database::NetworkPolicy, evaluate, evaluate_for_user};
+use crate::{config::Config, db};
+
+const CREATED_AT: &str = "2026-01-05T00:00:00.000Z";
+
+fn policy(id: i64, rule_type: &str, value: &str) -> NetworkPolicy {
+    NetworkPolicy {
+        id,
+        scope: "EVERYONE".to_string(),
+        scope_value: String::new(),
+        rule_type: rule_type.to_string(),
+        value: value.to_string(),
+        created_at: CREATED_AT.to_string(),
+    }
+}
+
+#[test]
+fn cidr_rules_match_ipv4_and_ipv6() {
+    let cases = [
+        ("203.0.113.0/24", "203.0.113.77", true),
+        ("203.0.113.0/24", "203.0.114.1", false),
+        ("203.0.113.5", "203.0.113.5", true),
+        ("2001:db8:abcd::/48", "2001:db8:abcd:12::1", true),
+        ("2001:db8:abcd::/48", "2001:db8:abce::1", false),
+        ("2001:db8::1", "2001:db8::1", true),
+        ("203.0.113.0/24", "2001:db8::1", false),
+        ("203.0.113.0/24", "not an address", false),
+        ("not a network", "203.0.113.77", false),
+    ];
+    for (value, ip_address, compliant) in cases {
+        let verdict = evaluate(&[policy(1, "CIDR", value)], ip_address, "", "");
+        assert_eq!(verdict.compliant, compliant, "{value} {ip_address}");
+    }
+}
+
+#[test]
+fn isp_and_location_rules_ignore_case() {
+    let policies = [policy(1, "isp", " pldt "), policy(2, "LOCATION", "cebu")];
+    let cases = [
+        ("PLDT", "", true),
+        ("Pldt", "Manila, PH", true),
+        ("Globe", "Cebu City, Cebu, PH", true),
+        ("Globe", "Manila, PH", false),
+        ("PLDT Fibr", "", false),
+    ];
+    for (isp, location, compliant) in cases {
+        let verdict = evaluate(&policies, "10.0.0.1", location, isp);
+        assert_eq!(verdict.compliant, compliant, "{isp} {location}");
+    }
+    assert_eq!(
+        evaluate(&policies, "10.0.0.1", "Cebu", "Globe").reason,
+        "allowed by LOCATION cebu (policy 2)"
+    );
+}
+
+#[test]
+fn unknown_rules_match_nothing() {
+    let verdict = evaluate(&[policy(1, "ASN", "9299")], "10.0.0.1", "", "9299");
+    assert!(!verdict.compliant);
+    assert_eq!(verdict.reason, "matches none of 1 allowed networks");
+}
+
+#[tokio::test]
+async fn policies_apply_to_their_user_and_group() {
+    let pool = db::init_db("sqlite::memory:").await;
+    let mut db = db::open(&pool, &Config::default()).await;
+
+    // Without any policy everyone may clock in from anywhere.
+    let verdict = evaluate_for_user(&mut db, "ana", "198.51.100.9", "", "").await;
+    assert!(verdict.compliant);
+    assert_eq!(verdict.reason, "no policy applies");
+
+    let policies = db.policy();
+    policies
+        .add_policy("USER", "ana", "CIDR", "198.51.100.0/24", CREATED_AT)
+        .await
+        .unwrap();
+    policies
+        .add_policy("GROUP", "field", "ISP", "Globe", CREATED_AT)
+        .await
+        .unwrap();
+    assert!(policies.add_group_member("field", "ben").await);
+
+    let cases = [
+        // A user policy binds only that user.
+        ("ana", "198.51.100.9", "PLDT", true),
+        ("ana", "192.0.2.1", "Globe", false),
+        // Group policies bind the group's members.
+        ("ben", "192.0.2.1", "globe", true),
+        ("ben", "198.51.100.9", "PLDT", false),
+        // Nobody else is bound by either.
+        ("cleo", "192.0.2.1", "PLDT", true),
+    ];
+    for (user_id, ip_address, isp, compliant) in cases {
+        let verdict = evaluate_for_user(&mut db, user_id, ip_address, "", isp).await;
+        assert_eq!(verdict.compliant, compliant, "{user_id} {ip_address} {isp}");
+    }
+}
@@ -0,0 +1,452 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+
+use crate::{
+    anomalies::database::LoginAnomaly, audit::database::AuditEntry, calendar::database::Leave,
+    devices::database::Device, import::database::IdMapping, policy::database::LoginCompliance,
+    projects::database::Allocation, webhooks::database::WebhookDelivery,
+};
+
+/// Webhook payloads carry the login either as `data` or as `data.login`.
+const DELIVERY_USER_ID: &str = "coalesce(json_extract(payload, '$.data.user_id'), json_extract(payload, '$.data.login.user_id'))";
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct SentNotification {
+    pub kind: String,
+    pub dedupe_key: String,
+    pub recipient: String,
+    pub sent_at: String,
+}
+
+/// Rows removed or rewritten by an erasure, per table.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ErasedRows {
+    pub device_login: u64,
+    pub device_login_quarantine: u64,
+    pub login_anomaly: u64,
+    pub login_compliance: u64,
+    pub device: u64,
+    pub app_user: u64,
+    pub team_member: u64,
+    pub reporting_line: u64,
+    pub user_role: u64,
+    pub import_id_map: u64,
+    pub policy_group_member: u64,
+    pub notification_log: u64,
+    pub webhook_delivery: u64,
+    pub time_allocation: u64,
+    pub user_leave: u64,
+}
+
+impl ErasedRows {
+    pub fn total(&self) -> u64 {
+        self.device_login
+            + self.device_login_quarantine
+            + self.login_anomaly
+            + self.login_compliance
+            + self.device
+            + self.app_user
+            + self.team_member
+            + self.reporting_line
+            + self.user_role
+            + self.import_id_map
+            + self.policy_group_member
+            + self.notification_log
+            + self.webhook_delivery
+            + self.time_allocation
+            + self.user_leave
+    }
+}
+
+/// Everything held about one person, across the other modules' tables.
+#[derive(Clone, Debug)]
+pub struct PrivacyDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl PrivacyDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn anomalies(&self, user_id: &str) -> Vec<LoginAnomaly> {
+        sqlx::query_as::<_, LoginAnomaly>(
+            r#"
+            SELECT id, login_id, user_id, kind, detail, created_at
+            FROM login_anomaly
+            WHERE user_id = ?
+            ORDER BY id;
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PrivacyDatabase::anomalies: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn compliance(&self, user_id: &str) -> Vec<LoginCompliance> {
+        sqlx::query_as::<_, LoginCompliance>(
+            r#"
+            SELECT login_id, user_id, compliant, reason, evaluated_at
+            FROM login_compliance
+            WHERE user_id = ?
+            ORDER BY login_id;
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PrivacyDatabase::compliance: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn allocations(&self, user_id: &str) -> Vec<Allocation> {
+        sqlx::query_as::<_, Allocation>(
+            r#"
+            SELECT a.id, a.user_id, a.date, a.project_id, p.code AS project_code,
+                   a.task_id, t.name AS task, a.minutes, a.note, a.created_by, a.created_at
+            FROM time_allocation a
+            JOIN project p ON p.id = a.project_id
+            LEFT JOIN project_task t ON t.id = a.task_id
+            WHERE a.user_id = ?
+            ORDER BY a.id;
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PrivacyDatabase::allocations: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn leave(&self, user_id: &str) -> Vec<Leave> {
+        sqlx::query_as::<_, Leave>(
+            r#"
+            SELECT id, user_id, kind, start_date, end_date, note, created_by, created_at
+            FROM user_leave
+            WHERE user_id = ?
+            ORDER BY id;
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PrivacyDatabase::leave: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn devices(&self, user_id: &str) -> Vec<Device> {
+        sqlx::query_as::<_, Device>(
+            r#"
+            SELECT device_id, user_id, label, first_seen, last_seen, status
+            FROM device
+            WHERE user_id = ?
+            ORDER BY first_seen;
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PrivacyDatabase::devices: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn teams(&self, user_id: &str) -> Vec<String> {
+        sqlx::query_scalar::<_, String>(
+            "SELECT team FROM team_member WHERE user_id = ? ORDER BY team;",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PrivacyDatabase::teams: {err}");
+            Vec::new()
+        })
+    }
+
+    /// The user's employee ids in the systems clock events were imported
+    /// from.
+    pub async fn import_ids(&self, user_id: &str) -> Vec<IdMapping> {
+        sqlx::query_as::<_, IdMapping>(
+            r#"
+            SELECT source, external_id, user_id
+            FROM import_id_map
+            WHERE user_id = ?
+            ORDER BY source, external_id;
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PrivacyDatabase::import_ids: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn policy_groups(&self, user_id: &str) -> Vec<String> {
+        sqlx::query_scalar::<_, String>(
+            "SELECT group_name FROM policy_group_member WHERE user_id = ? ORDER BY group_name;",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PrivacyDatabase::policy_groups: {err}");
+            Vec::new()
+        })
+    }
+
+    /// Notifications sent to any address the user clocked with, given as a
+    /// JSON array in `emails`, or has in the directory.
+    pub async fn notifications(&self, user_id: &str, emails: &str) -> Vec<SentNotification> {
+        sqlx::query_as::<_, SentNotification>(
+            r#"
+            SELECT kind, dedupe_key, recipient, sent_at
+            FROM notification_log
+            WHERE recipient IN (SELECT value FROM json_each(?2)
+                                UNION SELECT email FROM app_user WHERE user_id = ?1)
+            ORDER BY sent_at;
+            "#,
+        )
+        .bind(user_id)
+        .bind(emails)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PrivacyDatabase::notifications: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn webhook_deliveries(&self, user_id: &str) -> Vec<WebhookDelivery> {
+        sqlx::query_as::<_, WebhookDelivery>(&format!(
+            r#"
+            SELECT id, subscription_id, event, dedupe_key, payload, status, attempts,
+                   next_attempt_at, created_at, delivered_at
+            FROM webhook_delivery
+            WHERE {DELIVERY_USER_ID} = ?
+            ORDER BY id;
+            "#
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PrivacyDatabase::webhook_deliveries: {err}");
+            Vec::new()
+        })
+    }
+
+    /// What the user did themselves, as recorded in the audit log.
+    pub async fn audit_trail(&self, user_id: &str) -> Vec<AuditEntry> {
+        sqlx::query_as::<_, AuditEntry>(
+            r#"
+            SELECT id, actor, role, action, target, params, row_count, client_ip, created_at
+            FROM audit_log
+            WHERE actor = ?
+            ORDER BY id;
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PrivacyDatabase::audit_trail: {err}");
+            Vec::new()
+        })
+    }
+
+    /// A random id to stand in for an erased user.
+    pub async fn new_pseudonym(&self) -> Result<String, String> {
+        sqlx::query_scalar::<_, String>("SELECT 'erased-' || lower(hex(randomblob(8)));")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|err| err.to_string())
+    }
+
+    /// Deletes everything held about the user but their clock events, which
+    /// `DeviceLoginStore` erases. With a `pseudonym`, anomaly and compliance
+    /// records are kept under it for payroll instead, stripped of anything
+    /// identifying. The audit log is append-only and keeps its entries
+    /// either way. `emails` is a JSON array of the addresses the user
+    /// clocked with.
+    pub async fn erase(
+        &self,
+        user_id: &str,
+        pseudonym: Option<&str>,
+        emails: &str,
+    ) -> Result<ErasedRows, String> {
+        let erased = async {
+            let mut tx = self.pool.begin().await?;
+            // Notifications are matched by address, so go before the rows
+            // holding the addresses do.
+            let notification_log = sqlx::query(
+                r#"
+                DELETE FROM notification_log
+                WHERE recipient IN (SELECT value FROM json_each(?2)
+                                    UNION SELECT email FROM app_user WHERE user_id = ?1);
+                "#,
+            )
+            .bind(user_id)
+            .bind(emails)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+            let mut erased = ErasedRows {
+                notification_log,
+                ..ErasedRows::default()
+            };
+
+            sqlx::query(&format!(
+                r#"
+                DELETE FROM webhook_attempt
+                WHERE delivery_id IN (SELECT id FROM webhook_delivery
+                                      WHERE {DELIVERY_USER_ID} = ?);
+                "#
+            ))
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+            erased.webhook_delivery = sqlx::query(&format!(
+                "DELETE FROM webhook_delivery WHERE {DELIVERY_USER_ID} = ?;"
+            ))
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+            match pseudonym {
+                Some(pseudonym) => {
+                    erased.login_anomaly = sqlx::query(
+                        "UPDATE login_anomaly SET user_id = ?, detail = '' WHERE user_id = ?;",
+                    )
+                    .bind(pseudonym)
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await?
+                    .rows_affected();
+                    erased.login_compliance = sqlx::query(
+                        "UPDATE login_compliance SET user_id = ?, reason = '' WHERE user_id = ?;",
+                    )
+                    .bind(pseudonym)
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await?
+                    .rows_affected();
+                    erased.time_allocation = sqlx::query(
+                        "UPDATE time_allocation SET user_id = ?, note = '' WHERE user_id = ?;",
+                    )
+                    .bind(pseudonym)
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await?
+                    .rows_affected();
+                    erased.user_leave = sqlx::query(
+                        "UPDATE user_leave SET user_id = ?, note = '' WHERE user_id = ?;",
+                    )
+                    .bind(pseudonym)
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await?
+                    .rows_affected();
+                }
+                None => {
+                    erased.login_anomaly =
+                        sqlx::query("DELETE FROM login_anomaly WHERE user_id = ?;")
+                            .bind(user_id)
+                            .execute(&mut *tx)
+                            .await?
+                            .rows_affected();
+                    erased.login_compliance =
+                        sqlx::query("DELETE FROM login_compliance WHERE user_id = ?;")
+                            .bind(user_id)
+                            .execute(&mut *tx)
+                            .await?
+                            .rows_affected();
+                    erased.time_allocation =
+                        sqlx::query("DELETE FROM time_allocation WHERE user_id = ?;")
+                            .bind(user_id)
+                            .execute(&mut *tx)
+                            .await?
+                            .rows_affected();
+                    erased.user_leave = sqlx::query("DELETE FROM user_leave WHERE user_id = ?;")
+                        .bind(user_id)
+                        .execute(&mut *tx)
+                        .await?
+                        .rows_affected();
+                }
+            }
+
+            for (table, query, rows) in [
+                (
+                    "device",
+                    "DELETE FROM device WHERE user_id = ?1;",
+                    &mut erased.device,
+                ),
+                (
+                    "team_member",
+                    "DELETE FROM team_member WHERE user_id = ?1;",
+                    &mut erased.team_member,
+                ),
+                (
+                    "reporting_line",
+                    "DELETE FROM reporting_line WHERE manager_id = ?1 OR report_id = ?1;",
+                    &mut erased.reporting_line,
+                ),
+                (
+                    "user_role",
+                    "DELETE FROM user_role WHERE user_id = ?1;",
+                    &mut erased.user_role,
+                ),
+                (
+                    "policy_group_member",
+                    "DELETE FROM policy_group_member WHERE user_id = ?1;",
+                    &mut erased.policy_group_member,
+                ),
+                (
+                    "import_id_map",
+                    "DELETE FROM import_id_map WHERE user_id = ?1;",
+                    &mut erased.import_id_map,
+                ),
+                (
+                    "app_user",
+                    "DELETE FROM app_user WHERE user_id = ?1;",
+                    &mut erased.app_user,
+                ),
+            ] {
+                *rows = sqlx::query(query)
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await
+                    .inspect_err(|err| log::error!("Failed to erase from {table}: {err}"))?
+                    .rows_affected();
+            }
+            sqlx::query("UPDATE app_user SET manager_id = NULL WHERE manager_id = ?;")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+
+            tx.commit().await?;
+            Ok::<ErasedRows, sqlx::Error>(erased)
+        }
+        .await;
+
+        erased.map_err(|err| {
+            log::error!("PrivacyDatabase::erase: {err}");
+            err.to_string()
+        })
+    }
+}
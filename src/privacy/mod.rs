@@ -0,0 +1,348 @@
+pub mod database;
+
+use std::io::{Cursor, Write};
+
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::Path,
+    http::{Request, StatusCode, header},
+    response::IntoResponse,
+};
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use strum_macros::{AsRefStr, Display, EnumString};
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    access::{self, Permission},
+    anomalies::database::LoginAnomaly,
+    audit::{self, AuditAction, database::AuditEntry},
+    calendar::database::Leave,
+    db::Db,
+    devices::database::Device,
+    directory::database::{AppUser, ReportingLine},
+    import::database::IdMapping,
+    policy::database::LoginCompliance,
+    projects::database::Allocation,
+    users::device_login::{DeviceLogin, QuarantinedLogin},
+    utils,
+    webhooks::database::WebhookDelivery,
+};
+use database::SentNotification;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, AsRefStr, EnumString, Serialize)]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE", ascii_case_insensitive)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErasureMode {
+    /// Remove every row about the user.
+    Delete,
+    /// Keep clock events for payroll under a random id, without anything
+    /// that identifies the user.
+    Pseudonymize,
+}
+
+/// All data held about one user, as handed out on a subject access request.
+#[derive(Debug, Serialize)]
+pub struct SubjectData {
+    pub user_id: String,
+    pub generated_at: String,
+    pub profile: Option<AppUser>,
+    pub role: Option<String>,
+    pub teams: Vec<String>,
+    pub reporting_lines: Vec<ReportingLine>,
+    pub policy_groups: Vec<String>,
+    pub devices: Vec<Device>,
+    pub import_ids: Vec<IdMapping>,
+    pub device_logins: Vec<DeviceLogin>,
+    pub quarantined_logins: Vec<QuarantinedLogin>,
+    pub anomalies: Vec<LoginAnomaly>,
+    pub compliance: Vec<LoginCompliance>,
+    pub allocations: Vec<Allocation>,
+    pub leave: Vec<Leave>,
+    pub notifications: Vec<SentNotification>,
+    pub webhook_deliveries: Vec<WebhookDelivery>,
+    pub audit_trail: Vec<AuditEntry>,
+}
+
+impl SubjectData {
+    pub async fn collect(db: &mut Db, user_id: &str) -> Self {
+        let reporting_lines = db
+            .directory()
+            .reporting_lines()
+            .await
+            .into_iter()
+            .filter(|line| line.manager_id == user_id || line.report_id == user_id)
+            .collect();
+
+        let emails = clocked_emails(db, user_id).await;
+        Self {
+            user_id: user_id.to_string(),
+            generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            profile: db.directory().user(user_id).await,
+            role: db.access().role(user_id).await,
+            teams: db.privacy().teams(user_id).await,
+            reporting_lines,
+            policy_groups: db.privacy().policy_groups(user_id).await,
+            devices: db.privacy().devices(user_id).await,
+            import_ids: db.privacy().import_ids(user_id).await,
+            device_logins: db.device_login().device_login_of_user(user_id).await,
+            quarantined_logins: db.device_login().quarantined_of_user(user_id).await,
+            anomalies: db.privacy().anomalies(user_id).await,
+            compliance: db.privacy().compliance(user_id).await,
+            allocations: db.privacy().allocations(user_id).await,
+            leave: db.privacy().leave(user_id).await,
+            notifications: db.privacy().notifications(user_id, &emails).await,
+            webhook_deliveries: db.privacy().webhook_deliveries(user_id).await,
+            audit_trail: db.privacy().audit_trail(user_id).await,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.row_count() == 0
+    }
+
+    pub fn row_count(&self) -> usize {
+        usize::from(self.profile.is_some())
+            + usize::from(self.role.is_some())
+            + self.teams.len()
+            + self.reporting_lines.len()
+            + self.policy_groups.len()
+            + self.devices.len()
+            + self.import_ids.len()
+            + self.device_logins.len()
+            + self.quarantined_logins.len()
+            + self.anomalies.len()
+            + self.compliance.len()
+            + self.allocations.len()
+            + self.leave.len()
+            + self.notifications.len()
+            + self.webhook_deliveries.len()
+            + self.audit_trail.len()
+    }
+
+    /// A zip with one CSV per kind of record.
+    pub fn to_csv_zip(&self) -> Result<Vec<u8>, String> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+        let mut add = |name: &str, csv: Result<Vec<u8>, String>| -> Result<(), String> {
+            zip.start_file(name, SimpleFileOptions::default())
+                .map_err(|err| err.to_string())?;
+            zip.write_all(&csv?).map_err(|err| err.to_string())
+        };
+        add("profile.csv", to_csv(self.profile.iter()))?;
+        add(
+            "memberships.csv",
+            to_csv(
+                self.role
+                    .iter()
+                    .map(|role| Membership {
+                        kind: "role",
+                        name: role,
+                    })
+                    .chain(self.teams.iter().map(|team| Membership {
+                        kind: "team",
+                        name: team,
+                    }))
+                    .chain(self.policy_groups.iter().map(|group| Membership {
+                        kind: "policy_group",
+                        name: group,
+                    })),
+            ),
+        )?;
+        add("reporting_lines.csv", to_csv(&self.reporting_lines))?;
+        add("devices.csv", to_csv(&self.devices))?;
+        add("import_ids.csv", to_csv(&self.import_ids))?;
+        add("device_logins.csv", to_csv(&self.device_logins))?;
+        add("quarantined_logins.csv", to_csv(&self.quarantined_logins))?;
+        add("anomalies.csv", to_csv(&self.anomalies))?;
+        add("compliance.csv", to_csv(&self.compliance))?;
+        add("allocations.csv", to_csv(&self.allocations))?;
+        add("leave.csv", to_csv(&self.leave))?;
+        add("notifications.csv", to_csv(&self.notifications))?;
+        add("webhook_deliveries.csv", to_csv(&self.webhook_deliveries))?;
+        add("audit_trail.csv", to_csv(&self.audit_trail))?;
+
+        zip.finish()
+            .map(Cursor::into_inner)
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// A role, team or policy group the user belongs to, as a CSV row.
+#[derive(Debug, Serialize)]
+struct Membership<'a> {
+    kind: &'static str,
+    name: &'a str,
+}
+
+/// Every address the user clocked with, as a JSON array.
+async fn clocked_emails(db: &mut Db, user_id: &str) -> String {
+    let emails = db.device_login().emails_of_user(user_id).await;
+    serde_json::to_string(&emails).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// `user_id` made safe to use as a download's file name.
+fn file_stem(user_id: &str) -> String {
+    user_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn to_csv<T: Serialize>(rows: impl IntoIterator<Item = T>) -> Result<Vec<u8>, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row).map_err(|err| err.to_string())?;
+    }
+    writer.into_inner().map_err(|err| err.to_string())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Parameters {
+    format: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ErasureRequest {
+    mode: String,
+}
+
+/// Everything held about `user_id`, as JSON or with `format=csv` as a zip
+/// of CSV files.
+pub async fn export_user_data(
+    Extension(mut db): Extension<Db>,
+    Path(user_id): Path<String>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::Export).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let csv = match params.format.as_deref().unwrap_or("json") {
+        "json" => false,
+        "csv" => true,
+        format => {
+            return (StatusCode::BAD_REQUEST, format!("Unknown format {format}.")).into_response();
+        }
+    };
+
+    let data = SubjectData::collect(&mut db, &user_id).await;
+    if data.is_empty() {
+        return (StatusCode::NOT_FOUND, "No data held about this user.").into_response();
+    }
+    log::info!(
+        "{} exported all data held about {user_id}",
+        principal.user_id
+    );
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Export,
+        &format!("user {user_id}"),
+        &params,
+        data.row_count(),
+    )
+    .await;
+
+    if !csv {
+        return Json(data).into_response();
+    }
+    match data.to_csv_zip() {
+        Ok(body) => (
+            [
+                (header::CONTENT_TYPE, "application/zip".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}.zip\"", file_stem(&user_id)),
+                ),
+            ],
+            body,
+        )
+            .into_response(),
+        Err(err) => {
+            log::error!("Failed to bundle data of {user_id}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+pub async fn erase_user_data(
+    Extension(mut db): Extension<Db>,
+    Path(user_id): Path<String>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageConfig).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let erasure = match utils::json_body::<ErasureRequest>(request).await {
+        Ok(erasure) => erasure,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let Ok(mode) = erasure.mode.parse::<ErasureMode>() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Mode must be DELETE or PSEUDONYMIZE.",
+        )
+            .into_response();
+    };
+
+    let pseudonym = match mode {
+        ErasureMode::Delete => None,
+        ErasureMode::Pseudonymize => match db.privacy().new_pseudonym().await {
+            Ok(pseudonym) => Some(pseudonym),
+            Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err).into_response(),
+        },
+    };
+    // Addresses are looked up before the clock events holding them go.
+    let emails = clocked_emails(&mut db, &user_id).await;
+    let mut erased = match db
+        .privacy()
+        .erase(&user_id, pseudonym.as_deref(), &emails)
+        .await
+    {
+        Ok(erased) => erased,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err).into_response(),
+    };
+    let device_login = match &pseudonym {
+        Some(pseudonym) => {
+            db.device_login()
+                .pseudonymize_user(&user_id, pseudonym)
+                .await
+        }
+        None => db.device_login().delete_user(&user_id).await,
+    };
+    erased.device_login = match device_login {
+        Ok(rows) => rows,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err).into_response(),
+    };
+    erased.device_login_quarantine =
+        match db.device_login().delete_quarantined_of_user(&user_id).await {
+            Ok(rows) => rows,
+            Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err).into_response(),
+        };
+    if erased.total() == 0 {
+        return (StatusCode::NOT_FOUND, "No data held about this user.").into_response();
+    }
+
+    log::info!("{} erased {user_id} ({mode})", principal.user_id);
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Delete,
+        &format!("user {user_id}"),
+        &json!({ "mode": mode, "rows": erased }),
+        erased.total() as usize,
+    )
+    .await;
+    Json(json!({ "mode": mode, "pseudonym": pseudonym, "rows": erased })).into_response()
+}
@@ -0,0 +1,354 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct Project {
+    pub id: i64,
+    /// Short reference clients are billed under, unique.
+    pub code: String,
+    pub name: String,
+    pub client: String,
+    /// Archived projects keep their allocations but take no new ones.
+    pub active: bool,
+    pub created_at: String,
+}
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct ProjectTask {
+    pub id: i64,
+    pub project_id: i64,
+    pub name: String,
+    pub created_at: String,
+}
+
+/// Part of a user's attended day booked to a project.
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct Allocation {
+    pub id: i64,
+    pub user_id: String,
+    /// Local calendar day, `YYYY-MM-DD`.
+    pub date: String,
+    pub project_id: i64,
+    pub project_code: String,
+    pub task_id: Option<i64>,
+    pub task: Option<String>,
+    pub minutes: i64,
+    pub note: String,
+    pub created_by: String,
+    pub created_at: String,
+}
+
+/// A booking as submitted. `user_id` defaults to whoever submits it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewAllocation {
+    #[serde(default)]
+    pub user_id: Option<String>,
+    pub date: String,
+    pub project_id: i64,
+    #[serde(default)]
+    pub task_id: Option<i64>,
+    pub minutes: i64,
+    #[serde(default)]
+    pub note: String,
+}
+
+/// Booked time per project, task and user over a range of days.
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct ProjectReportRow {
+    pub project_code: String,
+    pub project: String,
+    pub client: String,
+    pub task: String,
+    pub user_id: String,
+    pub name: String,
+    pub days: i64,
+    pub minutes: i64,
+    /// `minutes` as `H:MM`.
+    pub hours: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProjectDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl ProjectDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn projects(&self) -> Vec<Project> {
+        sqlx::query_as::<_, Project>(
+            r#"
+            SELECT id, code, name, client, active, created_at
+            FROM project
+            ORDER BY active DESC, code COLLATE NOCASE ASC;
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("ProjectDatabase::projects: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn project(&self, id: i64) -> Option<Project> {
+        sqlx::query_as::<_, Project>(
+            "SELECT id, code, name, client, active, created_at FROM project WHERE id = ?;",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("ProjectDatabase::project: {err}");
+            None
+        })
+    }
+
+    /// The new project's id, or `None` when the code is taken.
+    pub async fn add_project(
+        &self,
+        code: &str,
+        name: &str,
+        client: &str,
+        created_at: &str,
+    ) -> Option<i64> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            INSERT INTO project (code, name, client, created_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (code) DO NOTHING
+            RETURNING id;
+            "#,
+        )
+        .bind(code)
+        .bind(name)
+        .bind(client)
+        .bind(created_at)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("ProjectDatabase::add_project: {err}");
+            None
+        })
+    }
+
+    pub async fn archive_project(&self, id: i64) -> bool {
+        sqlx::query("UPDATE project SET active = 0 WHERE id = ? AND active = 1;")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or_else(|err| {
+                log::error!("ProjectDatabase::archive_project: {err}");
+                false
+            })
+    }
+
+    pub async fn tasks(&self) -> Vec<ProjectTask> {
+        sqlx::query_as::<_, ProjectTask>(
+            r#"
+            SELECT id, project_id, name, created_at
+            FROM project_task
+            ORDER BY project_id, name COLLATE NOCASE ASC;
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("ProjectDatabase::tasks: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn task(&self, id: i64) -> Option<ProjectTask> {
+        sqlx::query_as::<_, ProjectTask>(
+            "SELECT id, project_id, name, created_at FROM project_task WHERE id = ?;",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("ProjectDatabase::task: {err}");
+            None
+        })
+    }
+
+    /// The new task's id, or `None` when the project is archived or already
+    /// has a task by that name.
+    pub async fn add_task(&self, project_id: i64, name: &str, created_at: &str) -> Option<i64> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            INSERT INTO project_task (project_id, name, created_at)
+            SELECT ?1, ?2, ?3
+            WHERE EXISTS (SELECT 1 FROM project WHERE id = ?1 AND active = 1)
+            ON CONFLICT (project_id, name) DO NOTHING
+            RETURNING id;
+            "#,
+        )
+        .bind(project_id)
+        .bind(name)
+        .bind(created_at)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("ProjectDatabase::add_task: {err}");
+            None
+        })
+    }
+
+    /// Minutes already booked by the user on the day.
+    pub async fn allocated_minutes(&self, user_id: &str, date: &str) -> i64 {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT coalesce(sum(minutes), 0) FROM time_allocation WHERE user_id = ? AND date = ?;",
+        )
+        .bind(user_id)
+        .bind(date)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("ProjectDatabase::allocated_minutes: {err}");
+            0
+        })
+    }
+
+    /// Books the allocation for `user_id` unless the day's bookings would
+    /// then exceed `attended` minutes, answering `None` then. The check and
+    /// the insert are one statement so concurrent bookings cannot overshoot
+    /// together.
+    pub async fn allocate(
+        &self,
+        user_id: &str,
+        new: &NewAllocation,
+        attended: i64,
+        created_by: &str,
+        created_at: &str,
+    ) -> Result<Option<i64>, String> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            INSERT INTO time_allocation
+                (user_id, date, project_id, task_id, minutes, note, created_by, created_at)
+            SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?8, ?9
+            WHERE (SELECT coalesce(sum(minutes), 0) FROM time_allocation
+                   WHERE user_id = ?1 AND date = ?2) + ?5 <= ?7
+            RETURNING id;
+            "#,
+        )
+        .bind(user_id)
+        .bind(&new.date)
+        .bind(new.project_id)
+        .bind(new.task_id)
+        .bind(new.minutes)
+        .bind(&new.note)
+        .bind(attended)
+        .bind(created_by)
+        .bind(created_at)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("ProjectDatabase::allocate: {err}");
+            err.to_string()
+        })
+    }
+
+    /// Bookings between two local days, inclusive. `visible_users` is a JSON
+    /// array of user ids, `None` for everyone.
+    pub async fn allocations(
+        &self,
+        visible_users: Option<&str>,
+        user_id: Option<&str>,
+        from: &str,
+        to: &str,
+    ) -> Vec<Allocation> {
+        sqlx::query_as::<_, Allocation>(
+            r#"
+            SELECT a.id, a.user_id, a.date, a.project_id, p.code AS project_code,
+                   a.task_id, t.name AS task, a.minutes, a.note, a.created_by, a.created_at
+            FROM time_allocation a
+            JOIN project p ON p.id = a.project_id
+            LEFT JOIN project_task t ON t.id = a.task_id
+            WHERE a.date BETWEEN ?3 AND ?4
+              AND (?1 IS NULL OR a.user_id IN (SELECT value FROM json_each(?1)))
+              AND (?2 IS NULL OR a.user_id = ?2)
+            ORDER BY a.date ASC, a.user_id ASC, a.id ASC;
+            "#,
+        )
+        .bind(visible_users)
+        .bind(user_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("ProjectDatabase::allocations: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn allocation(&self, id: i64) -> Option<Allocation> {
+        sqlx::query_as::<_, Allocation>(
+            r#"
+            SELECT a.id, a.user_id, a.date, a.project_id, p.code AS project_code,
+                   a.task_id, t.name AS task, a.minutes, a.note, a.created_by, a.created_at
+            FROM time_allocation a
+            JOIN project p ON p.id = a.project_id
+            LEFT JOIN project_task t ON t.id = a.task_id
+            WHERE a.id = ?;
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("ProjectDatabase::allocation: {err}");
+            None
+        })
+    }
+
+    pub async fn remove_allocation(&self, id: i64) -> bool {
+        sqlx::query("DELETE FROM time_allocation WHERE id = ?;")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or_else(|err| {
+                log::error!("ProjectDatabase::remove_allocation: {err}");
+                false
+            })
+    }
+
+    /// Totals between two local days, inclusive, optionally for one project.
+    pub async fn report(
+        &self,
+        project_id: Option<i64>,
+        from: &str,
+        to: &str,
+    ) -> Vec<ProjectReportRow> {
+        sqlx::query_as::<_, ProjectReportRow>(
+            r#"
+            SELECT p.code AS project_code, p.name AS project, p.client,
+                   coalesce(t.name, '') AS task, a.user_id, coalesce(u.name, '') AS name,
+                   count(DISTINCT a.date) AS days, sum(a.minutes) AS minutes,
+                   printf('%d:%02d', sum(a.minutes) / 60, sum(a.minutes) % 60) AS hours
+            FROM time_allocation a
+            JOIN project p ON p.id = a.project_id
+            LEFT JOIN project_task t ON t.id = a.task_id
+            LEFT JOIN app_user u ON u.user_id = a.user_id
+            WHERE a.date BETWEEN ?2 AND ?3
+              AND (?1 IS NULL OR a.project_id = ?1)
+            GROUP BY p.id, t.id, a.user_id
+            ORDER BY p.code COLLATE NOCASE ASC, task COLLATE NOCASE ASC, a.user_id ASC;
+            "#,
+        )
+        .bind(project_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("ProjectDatabase::report: {err}");
+            Vec::new()
+        })
+    }
+}
@@ -0,0 +1,470 @@
+pub mod database;
+
+use std::collections::BTreeMap;
+
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::Path,
+    http::{Request, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{NaiveDate, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    access::{self, Permission},
+    attendance,
+    audit::{self, AuditAction},
+    config::Config,
+    db::Db,
+    utils::{self, DATE_FORMAT, parse_date},
+};
+use database::{Allocation, NewAllocation, ProjectReportRow};
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Parameters {
+    from: Option<String>,
+    to: Option<String>,
+    user: Option<String>,
+    project: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NewProject {
+    code: String,
+    name: String,
+    #[serde(default)]
+    client: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NewTask {
+    name: String,
+}
+
+/// A user's day as far as bookings go.
+#[derive(Debug, Serialize)]
+struct DayAllocation {
+    user_id: String,
+    date: NaiveDate,
+    attended_minutes: i64,
+    allocated_minutes: i64,
+    unallocated_minutes: i64,
+}
+
+/// `from` and `to` of the query, each defaulting to today, local time.
+fn date_range(config: &Config, params: &Parameters) -> Result<(NaiveDate, NaiveDate), String> {
+    let today = Utc::now()
+        .with_timezone(&config.shift.offset())
+        .date_naive();
+    let from = match params.from.as_deref().filter(|from| !from.is_empty()) {
+        Some(from) => parse_date(from)?,
+        None => today,
+    };
+    let to = match params.to.as_deref().filter(|to| !to.is_empty()) {
+        Some(to) => parse_date(to)?,
+        None => today,
+    };
+    if from > to {
+        return Err(format!("{from} is after {to}."));
+    }
+    Ok((from, to))
+}
+
+/// Worked minutes per user and local day between `from` and `to`, as
+/// `daily_summaries` counts them, for the users `allows` keeps.
+async fn attended_minutes(
+    db: &mut Db,
+    config: &Config,
+    from: NaiveDate,
+    to: NaiveDate,
+    allows: impl Fn(&str) -> bool,
+) -> BTreeMap<(String, NaiveDate), i64> {
+    let (start, end) = attendance::summary_bounds(config, from, to);
+
+    let mut logins = db.device_login().device_login_between(&start, &end).await;
+    logins.retain(|login| allows(&login.user_id));
+    attendance::daily_summaries(&logins, config)
+        .into_iter()
+        .filter(|summary| summary.date <= to)
+        .map(|summary| ((summary.user_id, summary.date), summary.worked_minutes))
+        .collect()
+}
+
+/// Booked time per project, task and user between two local days.
+pub async fn report(
+    db: &mut Db,
+    project_id: Option<i64>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Vec<ProjectReportRow> {
+    db.project()
+        .report(
+            project_id,
+            &from.format(DATE_FORMAT).to_string(),
+            &to.format(DATE_FORMAT).to_string(),
+        )
+        .await
+}
+
+pub async fn list_projects(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    if let Err(rejection) = access::authorize(&mut db, &request, Permission::ViewOwn).await {
+        return rejection.into_response();
+    }
+
+    Json(json!({
+        "projects": db.project().projects().await,
+        "tasks": db.project().tasks().await,
+    }))
+    .into_response()
+}
+
+pub async fn create_project(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageProjects).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let new = match utils::json_body::<NewProject>(request).await {
+        Ok(new) => new,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let (code, name) = (new.code.trim(), new.name.trim());
+    if code.is_empty() || name.is_empty() {
+        return (StatusCode::BAD_REQUEST, "A code and a name are required.").into_response();
+    }
+
+    let Some(id) = db
+        .project()
+        .add_project(
+            code,
+            name,
+            new.client.trim(),
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+        .await
+    else {
+        return (StatusCode::CONFLICT, "Project code already exists.").into_response();
+    };
+    log::info!("{} created project {code}", principal.user_id);
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Create,
+        &format!("project {id}"),
+        &new,
+        1,
+    )
+    .await;
+    (StatusCode::CREATED, Json(db.project().project(id).await)).into_response()
+}
+
+/// Projects are archived rather than deleted so their bookings stay
+/// reportable.
+pub async fn archive_project(
+    Extension(mut db): Extension<Db>,
+    Path(id): Path<i64>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageProjects).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if db.project().archive_project(id).await {
+        log::info!("{} archived project {id}", principal.user_id);
+        audit::record(
+            &mut db,
+            &principal,
+            AuditAction::Delete,
+            &format!("project {id}"),
+            &(),
+            1,
+        )
+        .await;
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "No such active project.").into_response()
+    }
+}
+
+pub async fn add_task(
+    Extension(mut db): Extension<Db>,
+    Path(project_id): Path<i64>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageProjects).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let new = match utils::json_body::<NewTask>(request).await {
+        Ok(new) => new,
+        Err(rejection) => return rejection.into_response(),
+    };
+    if new.name.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, "A task name is required.").into_response();
+    }
+
+    let Some(id) = db
+        .project()
+        .add_task(
+            project_id,
+            new.name.trim(),
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+        .await
+    else {
+        return (
+            StatusCode::CONFLICT,
+            "No such active project, or the task already exists.",
+        )
+            .into_response();
+    };
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Create,
+        &format!("project {project_id} task {id}"),
+        &new,
+        1,
+    )
+    .await;
+    (StatusCode::CREATED, Json(db.project().task(id).await)).into_response()
+}
+
+pub async fn list_allocations(
+    Extension(mut db): Extension<Db>,
+    Extension(config): Extension<Config>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewOwn).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let scope = principal.scope(&mut db).await;
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let (from, to) = match date_range(&config, &params) {
+        Ok(range) => range,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    let user = params.user.as_deref().filter(|user| !user.is_empty());
+
+    let allocations = db
+        .project()
+        .allocations(
+            scope.visible_users().as_deref(),
+            user,
+            &from.format(DATE_FORMAT).to_string(),
+            &to.format(DATE_FORMAT).to_string(),
+        )
+        .await;
+    let attended = attended_minutes(&mut db, &config, from, to, |user_id| {
+        scope.allows(user_id) && user.is_none_or(|user| user == user_id)
+    })
+    .await;
+    let days = day_allocations(attended, &allocations);
+
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::View,
+        "allocations",
+        &params,
+        allocations.len(),
+    )
+    .await;
+    Json(json!({ "days": days, "allocations": allocations })).into_response()
+}
+
+fn day_allocations(
+    attended: BTreeMap<(String, NaiveDate), i64>,
+    allocations: &[Allocation],
+) -> Vec<DayAllocation> {
+    let mut days: BTreeMap<(String, NaiveDate), (i64, i64)> = attended
+        .into_iter()
+        .map(|(day, minutes)| (day, (minutes, 0)))
+        .collect();
+    for allocation in allocations {
+        let Ok(date) = parse_date(&allocation.date) else {
+            continue;
+        };
+        days.entry((allocation.user_id.clone(), date))
+            .or_default()
+            .1 += allocation.minutes;
+    }
+
+    days.into_iter()
+        .map(|((user_id, date), (attended, allocated))| DayAllocation {
+            user_id,
+            date,
+            attended_minutes: attended,
+            allocated_minutes: allocated,
+            unallocated_minutes: (attended - allocated).max(0),
+        })
+        .collect()
+}
+
+pub async fn create_allocation(
+    Extension(mut db): Extension<Db>,
+    Extension(config): Extension<Config>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewOwn).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let new = match utils::json_body::<NewAllocation>(request).await {
+        Ok(new) => new,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let user_id = new
+        .user_id
+        .as_deref()
+        .map(str::trim)
+        .filter(|user_id| !user_id.is_empty())
+        .unwrap_or(&principal.user_id)
+        .to_string();
+    if user_id != principal.user_id && !principal.can(Permission::ClockForOthers) {
+        return (StatusCode::FORBIDDEN, "You can only book your own time.").into_response();
+    }
+
+    let date = match parse_date(&new.date) {
+        Ok(date) => date,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+    if new.minutes <= 0 {
+        return (StatusCode::BAD_REQUEST, "Minutes must be positive.").into_response();
+    }
+    match db.project().project(new.project_id).await {
+        Some(project) if project.active => {}
+        Some(_) => {
+            return (StatusCode::BAD_REQUEST, "The project is archived.").into_response();
+        }
+        None => return (StatusCode::BAD_REQUEST, "No such project.").into_response(),
+    }
+    if let Some(task_id) = new.task_id {
+        match db.project().task(task_id).await {
+            Some(task) if task.project_id == new.project_id => {}
+            _ => {
+                return (StatusCode::BAD_REQUEST, "No such task on the project.").into_response();
+            }
+        }
+    }
+
+    let attended = attended_minutes(&mut db, &config, date, date, |id| id == user_id)
+        .await
+        .remove(&(user_id.clone(), date))
+        .unwrap_or(0);
+    let new = NewAllocation {
+        date: date.format(DATE_FORMAT).to_string(),
+        note: new.note.trim().to_string(),
+        ..new
+    };
+    let booked = db
+        .project()
+        .allocate(
+            &user_id,
+            &new,
+            attended,
+            &principal.user_id,
+            &Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+        .await;
+    let id = match booked {
+        Ok(Some(id)) => id,
+        Ok(None) => {
+            let allocated = db.project().allocated_minutes(&user_id, &new.date).await;
+            return (
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "{} more minutes would exceed the {attended} minutes attended on {}, \
+                     {allocated} are already allocated.",
+                    new.minutes, new.date
+                ),
+            )
+                .into_response();
+        }
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err).into_response(),
+    };
+
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Create,
+        &format!("allocation {id}"),
+        &json!({ "user_id": user_id, "allocation": new }),
+        1,
+    )
+    .await;
+    (StatusCode::CREATED, Json(db.project().allocation(id).await)).into_response()
+}
+
+pub async fn delete_allocation(
+    Extension(mut db): Extension<Db>,
+    Path(id): Path<i64>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewOwn).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let Some(allocation) = db.project().allocation(id).await else {
+        return (StatusCode::NOT_FOUND, "No such allocation.").into_response();
+    };
+    if allocation.user_id != principal.user_id && !principal.can(Permission::ClockForOthers) {
+        return (
+            StatusCode::FORBIDDEN,
+            "You can only remove your own bookings.",
+        )
+            .into_response();
+    }
+
+    if !db.project().remove_allocation(id).await {
+        return (StatusCode::NOT_FOUND, "No such allocation.").into_response();
+    }
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Delete,
+        &format!("allocation {id}"),
+        &allocation,
+        1,
+    )
+    .await;
+    StatusCode::NO_CONTENT.into_response()
+}
+
+pub async fn handle_report(
+    Extension(mut db): Extension<Db>,
+    Extension(config): Extension<Config>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewReports).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let (from, to) = match date_range(&config, &params) {
+        Ok(range) => range,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+
+    let rows = report(&mut db, params.project, from, to).await;
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::View,
+        "project_report",
+        &params,
+        rows.len(),
+    )
+    .await;
+    Json(rows).into_response()
+}
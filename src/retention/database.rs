@@ -0,0 +1,64 @@
+use sqlx::{Pool, QueryBuilder, Sqlite};
+
+/// Records that hang off clock events, which themselves are deleted through
+/// `DeviceLoginStore` as they may live in another database.
+#[derive(Clone, Debug)]
+pub struct RetentionDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl RetentionDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    /// Blanks the anomaly details and compliance reasons of the events, as
+    /// they may name the device, ISP or country of the event.
+    pub async fn scrub_dependents(&self, login_ids: &[i64]) -> bool {
+        let scrubbed = async {
+            let mut tx = self.pool.begin().await?;
+            for (dependent, column) in [("login_anomaly", "detail"), ("login_compliance", "reason")]
+            {
+                let mut query = QueryBuilder::<Sqlite>::new(format!(
+                    "UPDATE {dependent} SET {column} = '' WHERE login_id IN ("
+                ));
+                let mut separated = query.separated(", ");
+                for login_id in login_ids {
+                    separated.push_bind(login_id);
+                }
+                query.push(");");
+                query.build().execute(&mut *tx).await?;
+            }
+            tx.commit().await
+        }
+        .await;
+
+        scrubbed
+            .inspect_err(|err| log::error!("RetentionDatabase::scrub_dependents: {err}"))
+            .is_ok()
+    }
+
+    /// Deletes the anomaly flags and compliance results of the events.
+    pub async fn delete_dependents(&self, login_ids: &[i64]) -> bool {
+        let deleted = async {
+            let mut tx = self.pool.begin().await?;
+            for dependent in ["login_anomaly", "login_compliance"] {
+                let mut query = QueryBuilder::<Sqlite>::new(format!(
+                    "DELETE FROM {dependent} WHERE login_id IN ("
+                ));
+                let mut separated = query.separated(", ");
+                for login_id in login_ids {
+                    separated.push_bind(login_id);
+                }
+                query.push(");");
+                query.build().execute(&mut *tx).await?;
+            }
+            tx.commit().await
+        }
+        .await;
+
+        deleted
+            .inspect_err(|err| log::error!("RetentionDatabase::delete_dependents: {err}"))
+            .is_ok()
+    }
+}
@@ -0,0 +1,186 @@
+pub mod database;
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use chrono::{SecondsFormat, Utc};
+
+use crate::{config::Config, db::Db, scheduler::Job};
+
+const ANONYMIZE_BATCH_SIZE: u64 = 500;
+const DELETE_BATCH_SIZE: u64 = 500;
+
+/// Strips personal data from old clock events and eventually deletes them,
+/// keeping statuses and timestamps for payroll in between.
+pub struct RetentionJob {
+    config: Config,
+}
+
+pub fn jobs(config: &Config) -> Vec<Arc<dyn Job>> {
+    if !config.retention.is_enabled() {
+        log::info!("Data retention is disabled.");
+        return Vec::new();
+    }
+
+    vec![Arc::new(RetentionJob {
+        config: config.clone(),
+    })]
+}
+
+/// The network an address belongs to: /24 for IPv4, /48 for IPv6, and /24
+/// of the IPv4 address an IPv4-mapped one carries. Anything that is not an
+/// address is dropped.
+pub fn mask_ip(ip_address: &str) -> String {
+    match ip_address.trim().parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => mask_ipv4(ip).to_string(),
+        Ok(IpAddr::V6(ip)) => match ip.to_ipv4_mapped() {
+            Some(ip) => mask_ipv4(ip).to_ipv6_mapped().to_string(),
+            None => {
+                let [a, b, c, ..] = ip.segments();
+                Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0).to_string()
+            }
+        },
+        Err(_) => String::new(),
+    }
+}
+
+fn mask_ipv4(ip: Ipv4Addr) -> Ipv4Addr {
+    let [a, b, c, _] = ip.octets();
+    Ipv4Addr::new(a, b, c, 0)
+}
+
+fn cutoff(days: i64) -> String {
+    (Utc::now() - chrono::Duration::days(days)).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+impl RetentionJob {
+    /// Masks the IP addresses of events before the cutoff and drops their
+    /// ISP and location, along with the anomaly details and compliance
+    /// reasons that may repeat them.
+    async fn anonymize(&self, db: &mut Db, cutoff: &str) -> u64 {
+        let mut anonymized = 0;
+
+        loop {
+            let batch = db
+                .device_login()
+                .to_anonymize(cutoff, ANONYMIZE_BATCH_SIZE)
+                .await;
+            if batch.is_empty() {
+                break;
+            }
+
+            let masked: Vec<(i64, String)> = batch
+                .into_iter()
+                .map(|(login_id, ip_address)| (login_id, mask_ip(&ip_address)))
+                .collect();
+            let ids: Vec<i64> = masked.iter().map(|(login_id, _)| *login_id).collect();
+            if !db.retention().scrub_dependents(&ids).await {
+                break;
+            }
+            let updated = db.device_login().anonymize(&masked).await;
+            if updated == 0 {
+                break;
+            }
+            anonymized += updated;
+        }
+
+        anonymized
+    }
+
+    /// Deletes events before the cutoff, their anomaly flags and compliance
+    /// results first so none are left pointing at a deleted event.
+    async fn delete(&self, db: &mut Db, cutoff: &str) -> u64 {
+        let mut deleted = 0;
+
+        loop {
+            let ids = db
+                .device_login()
+                .expired_ids(cutoff, DELETE_BATCH_SIZE)
+                .await;
+            if ids.is_empty() {
+                break;
+            }
+
+            if !db.retention().delete_dependents(&ids).await {
+                break;
+            }
+            let removed = db.device_login().delete_by_ids(&ids).await;
+            if removed == 0 {
+                break;
+            }
+            deleted += removed;
+        }
+
+        deleted
+    }
+}
+
+#[async_trait]
+impl Job for RetentionJob {
+    fn name(&self) -> &'static str {
+        "apply_retention_policy"
+    }
+
+    fn default_schedule(&self) -> &'static str {
+        "0 30 2 * * *"
+    }
+
+    async fn run(&self, db: &mut Db) -> Result<String, String> {
+        let policy = &self.config.retention;
+        let mut report = Vec::new();
+
+        // Deleting first spares anonymizing rows that are about to go anyway.
+        if let Some(days) = policy.delete_after_days {
+            let cutoff = cutoff(days);
+            if policy.dry_run {
+                let expired = db.device_login().count_expired(&cutoff).await;
+                let quarantined = db.device_login().count_quarantined_expired(&cutoff).await;
+                report.push(format!(
+                    "would delete {expired} events and {quarantined} quarantined events before \
+                     {cutoff}"
+                ));
+            } else {
+                let deleted = self.delete(db, &cutoff).await;
+                let quarantined = db.device_login().delete_quarantined_expired(&cutoff).await;
+                report.push(format!(
+                    "deleted {deleted} events and {quarantined} quarantined events before \
+                     {cutoff}"
+                ));
+            }
+        }
+
+        if let Some(days) = policy.anonymize_after_days {
+            let cutoff = cutoff(days);
+            if policy.dry_run {
+                let pending = db.device_login().count_to_anonymize(&cutoff).await;
+                let quarantined = db
+                    .device_login()
+                    .count_quarantined_to_anonymize(&cutoff)
+                    .await;
+                report.push(format!(
+                    "would anonymize {pending} events and {quarantined} quarantined events \
+                     before {cutoff}"
+                ));
+            } else {
+                let anonymized = self.anonymize(db, &cutoff).await;
+                let quarantined = db.device_login().anonymize_quarantined(&cutoff).await;
+                report.push(format!(
+                    "anonymized {anonymized} events and {quarantined} quarantined events before \
+                     {cutoff}"
+                ));
+            }
+        }
+
+        let report = report.join(", ");
+        if policy.dry_run {
+            log::info!("Retention dry run: {report}");
+        }
+        Ok(report)
+    }
+}
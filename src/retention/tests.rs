@@ -0,0 +1,26 @@
+//! What is left of an address once it is anonymized.
+
+use super::mask_ip;
+
+#[test]
+fn masked_addresses() {
+    let cases = [
+        ("203.0.113.77", "203.0.113.0"),
+        (" 203.0.113.77 ", "203.0.113.0"),
+        ("203.0.113.0", "203.0.113.0"),
+        ("2001:db8:abcd:12:34:56:78:9a", "2001:db8:abcd::"),
+        ("2001:db8::1", "2001:db8::"),
+        ("::1", "::"),
+        ("::ffff:203.0.113.77", "::ffff:203.0.113.0"),
+        ("::ffff:cb00:714d", "::ffff:203.0.113.0"),
+        ("", ""),
+        ("unknown", ""),
+        ("203.0.113.256", ""),
+        ("203.0.113.77:8080", ""),
+        ("203.0.113.0/24", ""),
+        ("fe80::1%eth0", ""),
+    ];
+    for (ip_address, masked) in cases {
+        assert_eq!(mask_ip(ip_address), masked, "{ip_address:?}");
+    }
+}
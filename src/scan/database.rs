@@ -0,0 +1,41 @@
+use sqlx::{Pool, Sqlite};
+
+/// How far each reader of `device_login` has got, by name.
+#[derive(Clone, Debug)]
+pub struct ScanDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl ScanDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    /// Id of the last `device_login` row the scan `name` has looked at.
+    pub async fn cursor(&self, name: &str) -> Option<i64> {
+        sqlx::query_scalar::<_, i64>("SELECT last_login_id FROM scan_cursor WHERE name = ?;")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+            .unwrap_or_else(|err| {
+                log::error!("ScanDatabase::cursor: {err}");
+                None
+            })
+    }
+
+    pub async fn set_cursor(&self, name: &str, last_login_id: i64) {
+        if let Err(err) = sqlx::query(
+            r#"
+            INSERT INTO scan_cursor (name, last_login_id) VALUES (?, ?)
+            ON CONFLICT (name) DO UPDATE SET last_login_id = excluded.last_login_id;
+            "#,
+        )
+        .bind(name)
+        .bind(last_login_id)
+        .execute(&self.pool)
+        .await
+        {
+            log::error!("ScanDatabase::set_cursor: {err}");
+        }
+    }
+}
@@ -0,0 +1,56 @@
+pub mod database;
+
+use async_trait::async_trait;
+
+use crate::{db::Db, users::device_login::DeviceLogin};
+
+const SCAN_BATCH_SIZE: u64 = 500;
+
+/// Something that reads every `device_login` row once, in insertion order,
+/// resuming after the last row it saw. Imported history is skipped, it
+/// happened long before anyone could act on it.
+#[async_trait]
+pub trait LoginScan: Send + Sync {
+    /// Key of the scan's cursor in `scan_cursor`.
+    fn cursor(&self) -> &'static str;
+
+    /// Handles one event: `None` when it is skipped, otherwise how many
+    /// findings it produced.
+    async fn visit(&self, db: &mut Db, login: &DeviceLogin) -> Option<u64>;
+}
+
+/// What one pass of a `LoginScan` got through.
+#[derive(Debug, Default)]
+pub struct Scanned {
+    /// Events visited and not skipped.
+    pub visited: u64,
+    pub findings: u64,
+}
+
+/// Feeds every event after the scan's cursor to it, moving the cursor on
+/// after each batch.
+pub async fn run(scan: &dyn LoginScan, db: &mut Db) -> Scanned {
+    let mut cursor = db.scan().cursor(scan.cursor()).await.unwrap_or(0);
+    let mut scanned = Scanned::default();
+
+    loop {
+        let logins = db
+            .device_login()
+            .live_device_login_after(cursor, SCAN_BATCH_SIZE)
+            .await;
+        if logins.is_empty() {
+            break;
+        }
+
+        for login in logins {
+            cursor = login.id;
+            if let Some(findings) = scan.visit(db, &login).await {
+                scanned.visited += 1;
+                scanned.findings += findings;
+            }
+        }
+        db.scan().set_cursor(scan.cursor(), cursor).await;
+    }
+
+    scanned
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+
+pub const RUN_RUNNING: &str = "RUNNING";
+pub const RUN_SUCCEEDED: &str = "SUCCEEDED";
+pub const RUN_FAILED: &str = "FAILED";
+pub const RUN_SKIPPED: &str = "SKIPPED";
+pub const RUN_CANCELLED: &str = "CANCELLED";
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct JobRun {
+    pub id: i64,
+    pub job: String,
+    pub status: String,
+    pub message: Option<String>,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct JobRunDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl JobRunDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn start(&self, job: &str, status: &str, started_at: &str) -> i64 {
+        sqlx::query("INSERT INTO job_run (job, status, started_at) VALUES (?, ?, ?);")
+            .bind(job)
+            .bind(status)
+            .bind(started_at)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.last_insert_rowid())
+            .unwrap_or_else(|err| {
+                log::error!("JobRunDatabase::start: {err}");
+                0
+            })
+    }
+
+    pub async fn finish(&self, id: i64, status: &str, message: Option<&str>, finished_at: &str) {
+        if let Err(err) =
+            sqlx::query("UPDATE job_run SET status = ?, message = ?, finished_at = ? WHERE id = ?;")
+                .bind(status)
+                .bind(message)
+                .bind(finished_at)
+                .bind(id)
+                .execute(&self.pool)
+                .await
+        {
+            log::error!("JobRunDatabase::finish: {err}");
+        }
+    }
+
+    /// Runs left RUNNING by a crash can never finish, so they are closed on start.
+    pub async fn abandon_running(&self, finished_at: &str) {
+        if let Err(err) = sqlx::query(
+            r#"
+            UPDATE job_run
+            SET status = ?, message = 'service stopped while running', finished_at = ?
+            WHERE status = ?;
+            "#,
+        )
+        .bind(RUN_FAILED)
+        .bind(finished_at)
+        .bind(RUN_RUNNING)
+        .execute(&self.pool)
+        .await
+        {
+            log::error!("JobRunDatabase::abandon_running: {err}");
+        }
+    }
+
+    pub async fn history(&self, limit_per_page: u64, page_number: u64) -> Vec<JobRun> {
+        let limit = limit_per_page;
+        let offset = limit * (page_number - 1);
+
+        sqlx::query_as::<_, JobRun>(
+            r#"
+            SELECT id, job, status, message, started_at, finished_at
+            FROM job_run
+            ORDER BY id DESC
+            LIMIT ? OFFSET ?;
+            "#,
+        )
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("JobRunDatabase::history: {err}");
+            Vec::new()
+        })
+    }
+}
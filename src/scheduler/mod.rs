@@ -0,0 +1,185 @@
+pub mod database;
+
+use std::{
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use async_trait::async_trait;
+use axum::{Extension, Json, body::Body, http::Request, response::IntoResponse};
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use cron::Schedule;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    access::{self, Permission},
+    config::SchedulerConfig,
+    db::Db,
+    utils,
+};
+use database::{RUN_CANCELLED, RUN_FAILED, RUN_RUNNING, RUN_SKIPPED, RUN_SUCCEEDED};
+
+const RUNS_PER_PAGE: u64 = 50;
+
+/// A recurring task. The returned string is stored with the run.
+#[async_trait]
+pub trait Job: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Used when the configuration has no entry for this job.
+    fn default_schedule(&self) -> &'static str;
+
+    async fn run(&self, db: &mut Db) -> Result<String, String>;
+}
+
+struct Entry {
+    job: Arc<dyn Job>,
+    schedule: Schedule,
+    next: Option<DateTime<FixedOffset>>,
+    running: Arc<AtomicBool>,
+}
+
+pub struct Scheduler {
+    offset: FixedOffset,
+    entries: Vec<Entry>,
+}
+
+fn now_rfc3339() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+impl Scheduler {
+    pub fn new(offset: FixedOffset) -> Self {
+        Self {
+            offset,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn register(mut self, config: &SchedulerConfig, job: Arc<dyn Job>) -> Self {
+        let Some(expression) = config.schedule_for(job.name(), job.default_schedule()) else {
+            log::info!("Job {} is disabled.", job.name());
+            return self;
+        };
+
+        match Schedule::from_str(&expression) {
+            Ok(schedule) => {
+                log::info!("Job {} scheduled at \"{expression}\"", job.name());
+                self.entries.push(Entry {
+                    job,
+                    schedule,
+                    next: None,
+                    running: Arc::new(AtomicBool::new(false)),
+                });
+            }
+            Err(err) => log::error!("Invalid schedule for job {}: {err}", job.name()),
+        }
+        self
+    }
+
+    /// Fires jobs as they come due until `token` is cancelled, then waits
+    /// for the runs still in flight to wind down.
+    pub async fn run(mut self, db: Db, token: CancellationToken) {
+        let mut running = JoinSet::new();
+
+        db.clone().job_run().abandon_running(&now_rfc3339()).await;
+        for entry in self.entries.iter_mut() {
+            entry.next = entry.schedule.upcoming(self.offset).next();
+        }
+
+        loop {
+            let Some(next) = self.entries.iter().filter_map(|entry| entry.next).min() else {
+                log::info!("No jobs scheduled.");
+                break;
+            };
+            let wait = (next.with_timezone(&Utc) - Utc::now())
+                .to_std()
+                .unwrap_or_default();
+
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = tokio::time::sleep(wait) => {}
+                Some(_) = running.join_next(), if !running.is_empty() => continue,
+            }
+
+            let now = Utc::now().with_timezone(&self.offset);
+            for entry in self.entries.iter_mut() {
+                if entry.next.is_some_and(|next| next <= now) {
+                    entry.next = entry.schedule.after(&now).next();
+                    running.spawn(fire(
+                        entry.job.clone(),
+                        entry.running.clone(),
+                        db.clone(),
+                        token.clone(),
+                    ));
+                }
+            }
+        }
+
+        while running.join_next().await.is_some() {}
+        log::info!("Scheduler stopped.");
+    }
+}
+
+async fn fire(job: Arc<dyn Job>, running: Arc<AtomicBool>, mut db: Db, token: CancellationToken) {
+    let name = job.name();
+
+    if running.swap(true, Ordering::SeqCst) {
+        log::warn!("Job {name} is still running, skipping this run.");
+        let id = db.job_run().start(name, RUN_SKIPPED, &now_rfc3339()).await;
+        db.job_run()
+            .finish(
+                id,
+                RUN_SKIPPED,
+                Some("previous run still in progress"),
+                &now_rfc3339(),
+            )
+            .await;
+        return;
+    }
+
+    let id = db.job_run().start(name, RUN_RUNNING, &now_rfc3339()).await;
+    log::info!("Job {name} started (run {id}).");
+
+    let mut job_db = db.clone();
+    let (status, message) = tokio::select! {
+        result = job.run(&mut job_db) => match result {
+            Ok(message) => (RUN_SUCCEEDED, message),
+            Err(err) => (RUN_FAILED, err),
+        },
+        _ = token.cancelled() => (RUN_CANCELLED, "service is shutting down".to_string()),
+    };
+
+    log::info!("Job {name} finished (run {id}): {status} {message}");
+    db.job_run()
+        .finish(id, status, Some(&message), &now_rfc3339())
+        .await;
+    running.store(false, Ordering::SeqCst);
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Parameters {
+    page: Option<String>,
+}
+
+pub async fn handle_job_runs(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    if let Err(rejection) = access::authorize(&mut db, &request, Permission::ViewReports).await {
+        return rejection.into_response();
+    }
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let page = params
+        .page
+        .and_then(|page| page.parse::<u64>().ok())
+        .unwrap_or(1)
+        .max(1);
+
+    Json(db.job_run().history(RUNS_PER_PAGE, page).await).into_response()
+}
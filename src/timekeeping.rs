@@ -1,28 +1,201 @@
 use axum::{
     Extension,
     body::Body,
-    http::Request,
+    http::{Request, StatusCode, header},
     response::{Html, IntoResponse},
 };
-use chrono::{DateTime, FixedOffset, Utc};
+use chrono::{Days, FixedOffset, Utc};
 use serde::{Deserialize, Serialize};
 use tera::{Context, Tera};
 
-use crate::{db::Db, utils};
+use crate::{
+    access::{self, Permission, Principal, Scope},
+    anomalies::database::LoginAnomaly,
+    attendance::{self, DailySummary},
+    audit::{self, AuditAction},
+    config::Config,
+    db::Db,
+    directory,
+    policy::database::LoginCompliance,
+    users::device_login::{DeviceLogin, LoginFilter, LoginSort, LoginStatus},
+    utils,
+};
 
 const ITEMS_PER_PAGE: u64 = 20;
 const HTML_PATH: &str = "www/*.html";
+const VIEW_MANAGER: &str = "manager";
+/// Days of daily totals a manager sees when no date range is filtered.
+const DEFAULT_TOTALS_DAYS: u64 = 7;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct Parameters {
-    user_id: String,
-    #[serde(deserialize_with = "normalize_is_admin")]
-    is_admin: bool,
     name: Option<String>,
+    team: Option<String>,
     start_date: Option<String>,
     end_date: Option<String>,
-    #[serde(deserialize_with = "normalize_page")]
+    #[serde(default, deserialize_with = "normalize_page")]
     page: Option<u64>,
+    /// `manager` to list only the caller's direct reports.
+    view: Option<String>,
+    /// Free text matched against email, device id, IP address and location.
+    search: Option<String>,
+    /// A `SortColumn`, and `asc` or `desc`.
+    sort: Option<String>,
+    order: Option<String>,
+}
+
+impl Parameters {
+    fn team(&self) -> Option<&str> {
+        self.team.as_deref().filter(|team| !team.is_empty())
+    }
+
+    fn manager_view(&self) -> bool {
+        self.view.as_deref() == Some(VIEW_MANAGER)
+    }
+
+    fn search(&self) -> &str {
+        self.search.as_deref().unwrap_or_default().trim()
+    }
+
+    /// The requested order. Unknown columns or directions fall back to the
+    /// default of latest first rather than reaching the query.
+    fn sort(&self) -> LoginSort {
+        LoginSort {
+            column: self
+                .sort
+                .as_deref()
+                .and_then(|column| column.parse().ok())
+                .unwrap_or_default(),
+            order: self
+                .order
+                .as_deref()
+                .and_then(|order| order.parse().ok())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Sort and search for the table headers and pagination links.
+    fn insert_listing(&self, context: &mut Context) {
+        let sort = self.sort();
+        context.insert("search", self.search());
+        context.insert("sort", sort.column.as_ref());
+        context.insert("order", sort.order.as_ref());
+    }
+}
+
+/// What the page shows on top of the login history for managers.
+#[derive(Debug, Default)]
+struct ManagerView {
+    /// Listing the caller's direct reports rather than their usual scope.
+    active: bool,
+    has_reports: bool,
+    daily_totals: Vec<DailySummary>,
+}
+
+impl ManagerView {
+    fn insert_into(&self, context: &mut Context) {
+        context.insert("view", if self.active { VIEW_MANAGER } else { "" });
+        context.insert("has_reports", &self.has_reports);
+        context.insert("daily_totals", &self.daily_totals);
+    }
+}
+
+/// Per report and day totals over the filtered range, or the last
+/// `DEFAULT_TOTALS_DAYS` local days.
+async fn daily_totals(
+    db: &mut Db,
+    config: &Config,
+    scope: &Scope,
+    pagination: &Parameters,
+) -> Vec<DailySummary> {
+    let shift = &config.shift;
+    let (start, end) = match (&pagination.start_date, &pagination.end_date) {
+        (Some(start), Some(end)) => (start.clone(), end.clone()),
+        _ => {
+            let offset = shift.offset();
+            let today = Utc::now().with_timezone(&offset).date_naive();
+            let first = today - Days::new(DEFAULT_TOTALS_DAYS - 1);
+            (
+                attendance::local_day_bounds(first, &offset).0,
+                attendance::local_day_bounds(today, &offset).1,
+            )
+        }
+    };
+
+    let mut logins = db.device_login().device_login_between(&start, &end).await;
+    logins.retain(|login| scope.allows(&login.user_id));
+    attendance::daily_summaries(&logins, config)
+}
+
+/// A table row as admins see it, with whatever the anomaly detector flagged
+/// and how the event fared against the network policies.
+#[derive(Debug, Serialize)]
+struct LoginRow {
+    #[serde(flatten)]
+    login: LoginView,
+    flags: Vec<LoginAnomaly>,
+    compliance: Option<LoginCompliance>,
+}
+
+/// A clock event as the tables show it, with the time in the office's
+/// local time.
+#[derive(Debug, Serialize)]
+pub struct LoginView {
+    id: i64,
+    user_id: String,
+    name: String,
+    email: String,
+    device_id: String,
+    login_status: LoginStatus,
+    ip_address: String,
+    location: String,
+    isp: String,
+    created_at: String,
+}
+
+impl LoginView {
+    pub fn new(login: DeviceLogin, offset: &FixedOffset) -> Self {
+        let created_at = login
+            .created_at
+            .with_timezone(offset)
+            .format("%Y-%m-%d %H:%M:%S%.3f %:z")
+            .to_string();
+
+        Self {
+            id: login.id,
+            user_id: login.user_id,
+            name: login.name,
+            email: login.email,
+            device_id: login.device_id,
+            login_status: login.login_status,
+            ip_address: login.ip_address,
+            location: login.location,
+            isp: login.isp,
+            created_at,
+        }
+    }
+}
+
+pub fn views(users: Vec<DeviceLogin>, offset: &FixedOffset) -> Vec<LoginView> {
+    users
+        .into_iter()
+        .map(|login| LoginView::new(login, offset))
+        .collect()
+}
+
+async fn with_flags(db: &mut Db, users: Vec<DeviceLogin>, offset: &FixedOffset) -> Vec<LoginRow> {
+    let ids: Vec<i64> = users.iter().map(|user| user.id).collect();
+    let mut flags = db.anomaly().for_logins(&ids).await;
+    let mut compliance = db.policy().for_logins(&ids).await;
+
+    users
+        .into_iter()
+        .map(|login| LoginRow {
+            flags: flags.remove(&login.id).unwrap_or_default(),
+            compliance: compliance.remove(&login.id),
+            login: LoginView::new(login, offset),
+        })
+        .collect()
 }
 
 fn normalize_page<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
@@ -42,7 +215,7 @@ where
     }
 }
 
-fn normalize_is_admin<'de, D>(deserializer: D) -> Result<bool, D::Error>
+pub(crate) fn normalize_is_admin<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
@@ -60,99 +233,270 @@ where
 }
 pub async fn handle_timekeeping(
     Extension(mut db): Extension<Db>,
+    Extension(config): Extension<Config>,
     request: Request<Body>,
 ) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ViewOwn).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
     // Extract URL parameters
     let Ok(pagination) = utils::extract_url_params::<Parameters>(&request) else {
         log::error!("Failed to extract URL parameters");
         return Html("No parameters given.").into_response();
     };
 
-    if pagination.is_admin {
-        if pagination.name.is_some()
-            && pagination.start_date.is_some()
-            && pagination.end_date.is_some()
-        {
-            render_for_admins_with_filter(&pagination, &mut db).await
-        } else {
-            render_for_admins(&pagination, &mut db).await
+    let manager_scope = principal.manager_scope(&mut db).await;
+    let mut view = ManagerView {
+        active: pagination.manager_view(),
+        has_reports: manager_scope.is_some(),
+        ..ManagerView::default()
+    };
+    let scope = if view.active {
+        let Some(scope) = manager_scope else {
+            return (StatusCode::FORBIDDEN, "You have no direct reports.").into_response();
+        };
+        view.daily_totals = daily_totals(&mut db, &config, &scope, &pagination).await;
+        scope
+    } else {
+        principal.scope(&mut db).await
+    };
+    let offset = config.shift.offset();
+    if scope == Scope::Own(principal.user_id.clone()) {
+        return render_for_none_admins(&pagination, &principal, &view, &mut db, &offset).await;
+    }
+
+    let visible_users = scope.visible_users();
+    if (pagination.name.is_some() || pagination.team().is_some())
+        && pagination.start_date.is_some()
+        && pagination.end_date.is_some()
+    {
+        render_for_admins_with_filter(
+            &pagination,
+            &principal,
+            visible_users.as_deref(),
+            &view,
+            &mut db,
+            &offset,
+        )
+        .await
+    } else {
+        render_for_admins(
+            &pagination,
+            &principal,
+            visible_users.as_deref(),
+            &view,
+            &mut db,
+            &offset,
+        )
+        .await
+    }
+}
+
+/// CSV of the filtered rows the caller may see, for payroll and HR.
+/// Managers may export their direct reports with `view=manager`.
+pub async fn handle_export(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let permission = if params.manager_view() {
+        Permission::ViewOwn
+    } else {
+        Permission::Export
+    };
+    let principal = match access::authorize(&mut db, &request, permission).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let (Some(start_date), Some(end_date)) = (&params.start_date, &params.end_date) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "start_date and end_date are required.",
+        )
+            .into_response();
+    };
+    let name = params.name.clone().unwrap_or_default();
+    let scope = if params.manager_view() {
+        match principal.manager_scope(&mut db).await {
+            Some(scope) => scope,
+            None => {
+                return (StatusCode::FORBIDDEN, "You have no direct reports.").into_response();
+            }
         }
     } else {
-        render_for_none_admins(&pagination, &mut db).await
+        principal.scope(&mut db).await
+    };
+    let visible_users = scope.visible_users();
+
+    let (named_users, team_members) = directory::filter_users(&mut db, &name, params.team()).await;
+    let filter = LoginFilter {
+        name: &name,
+        named_users: named_users.as_deref(),
+        team_members: team_members.as_deref(),
+        visible_users: visible_users.as_deref(),
+        start_rfc3339: start_date,
+        end_rfc3339: end_date,
+        search: params.search(),
+    };
+
+    let total = db
+        .device_login()
+        .admin_filter_login_status_by_name_and_date_count(&filter)
+        .await;
+    let logins = db
+        .device_login()
+        .admin_filter_login_status_by_name_and_date(total.max(1) as u64, 1, &filter, params.sort())
+        .await;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for login in &logins {
+        if let Err(err) = writer.serialize(login) {
+            log::error!("Failed to write export row {}: {err}", login.id);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
     }
+    let Ok(body) = writer.into_inner() else {
+        log::error!("Failed to finish export");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    log::info!(
+        "{} exported {} rows between {start_date} and {end_date}",
+        principal.user_id,
+        logins.len()
+    );
+    audit::record(
+        &mut db,
+        &principal,
+        AuditAction::Export,
+        "timekeeping",
+        &params,
+        logins.len(),
+    )
+    .await;
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"timekeeping.csv\"",
+            ),
+        ],
+        body,
+    )
+        .into_response()
 }
 
-async fn render_for_admins(pagination: &Parameters, db: &mut Db) -> axum::response::Response {
+async fn render_for_admins(
+    pagination: &Parameters,
+    principal: &Principal,
+    visible_users: Option<&str>,
+    view: &ManagerView,
+    db: &mut Db,
+    offset: &FixedOffset,
+) -> axum::response::Response {
     let page = pagination.page.unwrap_or(1).max(1);
     let per_page = ITEMS_PER_PAGE;
     let tera = Tera::new(HTML_PATH).unwrap();
-    let total_users = db.device_login().size().await;
+    let search = pagination.search();
+    let total_users = db.device_login().size(visible_users, search).await;
     let total_pages = (total_users as f64 / per_page as f64).ceil() as u64;
     let page = page.min(total_pages.max(1));
-    let mut users = db.device_login().device_login_history(per_page, page).await;
-
-    users.iter_mut().for_each(|user| {
-        let utc_time = user.created_at.parse::<DateTime<Utc>>().unwrap();
-        let gmt_plus_8 = FixedOffset::east_opt(8 * 3600).expect("Invalid offset");
-        let local_time = utc_time.with_timezone(&gmt_plus_8);
-        let created_at = local_time.format("%Y-%m-%d %H:%M:%S%.3f %:z").to_string();
+    let users = db
+        .device_login()
+        .device_login_history(per_page, page, visible_users, search, pagination.sort())
+        .await;
 
-        user.created_at = created_at
-    });
+    audit::record(
+        db,
+        principal,
+        AuditAction::View,
+        "timekeeping",
+        pagination,
+        users.len(),
+    )
+    .await;
 
     let mut context = Context::new();
-    context.insert("users", &users);
+    context.insert("users", &with_flags(db, users, offset).await);
     context.insert("current_page", &page);
     context.insert("per_page", &per_page);
     context.insert("total_pages", &total_pages);
     context.insert("next_page", &(page + 1));
     context.insert("prev_page", &(page.saturating_sub(1)));
     context.insert("name", &"");
+    context.insert("team", &"");
+    context.insert("teams", &db.directory().teams().await);
     context.insert("start_date", &"");
     context.insert("end_date", &"");
-    context.insert("is_admin", &true);
-    context.insert("user_id", &pagination.user_id);
+    context.insert("sees_others", &true);
+    context.insert(
+        "can_export",
+        &(principal.can(Permission::Export) || view.active),
+    );
+    context.insert("user_id", &principal.user_id);
+    view.insert_into(&mut context);
+    pagination.insert_listing(&mut context);
 
     let rendered = tera.render("timekeeping.html", &context).unwrap();
     Html(rendered).into_response()
 }
 
-async fn render_for_none_admins(pagination: &Parameters, db: &mut Db) -> axum::response::Response {
+async fn render_for_none_admins(
+    pagination: &Parameters,
+    principal: &Principal,
+    view: &ManagerView,
+    db: &mut Db,
+    offset: &FixedOffset,
+) -> axum::response::Response {
     let page = pagination.page.unwrap_or(1).max(1);
     let per_page = ITEMS_PER_PAGE;
     let tera = Tera::new(HTML_PATH).unwrap();
+    let search = pagination.search();
     let total_users = db
         .device_login()
-        .size_per_user(pagination.user_id.as_str())
+        .size_per_user(principal.user_id.as_str(), search)
         .await;
     let total_pages = (total_users as f64 / per_page as f64).ceil() as u64;
     let page = page.min(total_pages.max(1));
-    let mut users = db
+    let users = db
         .device_login()
-        .device_login_history_per_user(pagination.user_id.as_str(), per_page, page)
+        .device_login_history_per_user(
+            principal.user_id.as_str(),
+            per_page,
+            page,
+            search,
+            pagination.sort(),
+        )
         .await;
 
-    users.iter_mut().for_each(|user| {
-        let utc_time = user.created_at.parse::<DateTime<Utc>>().unwrap();
-        let gmt_plus_8 = FixedOffset::east_opt(8 * 3600).expect("Invalid offset");
-        let local_time = utc_time.with_timezone(&gmt_plus_8);
-        let created_at = local_time.format("%Y-%m-%d %H:%M:%S%.3f %:z").to_string();
-
-        user.created_at = created_at
-    });
+    audit::record(
+        db,
+        principal,
+        AuditAction::View,
+        "timekeeping",
+        pagination,
+        users.len(),
+    )
+    .await;
 
     let mut context = Context::new();
-    context.insert("users", &users);
+    context.insert("users", &views(users, offset));
     context.insert("current_page", &page);
     context.insert("per_page", &per_page);
     context.insert("total_pages", &total_pages);
     context.insert("next_page", &(page + 1));
     context.insert("prev_page", &(page.saturating_sub(1)));
     context.insert("name", &"");
+    context.insert("team", &"");
     context.insert("start_date", &"");
     context.insert("end_date", &"");
-    context.insert("is_admin", &false);
-    context.insert("user_id", &pagination.user_id);
+    context.insert("sees_others", &false);
+    context.insert("can_export", &false);
+    context.insert("user_id", &principal.user_id);
+    view.insert_into(&mut context);
+    pagination.insert_listing(&mut context);
 
     let rendered = tera.render("timekeeping.html", &context).unwrap();
     Html(rendered).into_response()
@@ -160,61 +504,74 @@ async fn render_for_none_admins(pagination: &Parameters, db: &mut Db) -> axum::r
 
 async fn render_for_admins_with_filter(
     pagination: &Parameters,
+    principal: &Principal,
+    visible_users: Option<&str>,
+    view: &ManagerView,
     db: &mut Db,
+    offset: &FixedOffset,
 ) -> axum::response::Response {
-    let (Some(name), Some(start_date), Some(end_date)) = (
-        pagination.name.clone(),
-        pagination.start_date.clone(),
-        pagination.end_date.clone(),
-    ) else {
+    let (Some(start_date), Some(end_date)) =
+        (pagination.start_date.clone(), pagination.end_date.clone())
+    else {
         log::error!("Invalid input");
         return Html("Invalid input").into_response();
     };
+    let name = pagination.name.clone().unwrap_or_default();
+    let team = pagination.team();
     let page = pagination.page.unwrap_or(1).max(1);
     let per_page = ITEMS_PER_PAGE;
     let tera = Tera::new(HTML_PATH).unwrap();
+    let (named_users, team_members) = directory::filter_users(db, &name, team).await;
+    let filter = LoginFilter {
+        name: &name,
+        named_users: named_users.as_deref(),
+        team_members: team_members.as_deref(),
+        visible_users,
+        start_rfc3339: &start_date,
+        end_rfc3339: &end_date,
+        search: pagination.search(),
+    };
     let total_users = db
         .device_login()
-        .admin_filter_login_status_by_name_and_date_count(
-            name.as_str(),
-            start_date.as_str(),
-            end_date.as_str(),
-        )
+        .admin_filter_login_status_by_name_and_date_count(&filter)
         .await;
     let total_pages = (total_users as f64 / per_page as f64).ceil() as u64;
     let page = page.min(total_pages.max(1));
-    let mut users = db
+    let users = db
         .device_login()
-        .admin_filter_login_status_by_name_and_date(
-            per_page,
-            page,
-            name.as_str(),
-            start_date.as_str(),
-            end_date.as_str(),
-        )
+        .admin_filter_login_status_by_name_and_date(per_page, page, &filter, pagination.sort())
         .await;
 
-    users.iter_mut().for_each(|user| {
-        let utc_time = user.created_at.parse::<DateTime<Utc>>().unwrap();
-        let gmt_plus_8 = FixedOffset::east_opt(8 * 3600).expect("Invalid offset");
-        let local_time = utc_time.with_timezone(&gmt_plus_8);
-        let created_at = local_time.format("%Y-%m-%d %H:%M:%S%.3f %:z").to_string();
-
-        user.created_at = created_at
-    });
+    audit::record(
+        db,
+        principal,
+        AuditAction::Filter,
+        "timekeeping",
+        pagination,
+        users.len(),
+    )
+    .await;
 
     let mut context = Context::new();
-    context.insert("users", &users);
+    context.insert("users", &with_flags(db, users, offset).await);
     context.insert("current_page", &page);
     context.insert("per_page", &per_page);
     context.insert("total_pages", &total_pages);
     context.insert("next_page", &(page + 1));
     context.insert("prev_page", &(page.saturating_sub(1)));
     context.insert("name", &name);
+    context.insert("team", &team.unwrap_or_default());
+    context.insert("teams", &db.directory().teams().await);
     context.insert("start_date", &start_date);
     context.insert("end_date", &end_date);
-    context.insert("is_admin", &true);
-    context.insert("user_id", &pagination.user_id);
+    context.insert("sees_others", &true);
+    context.insert(
+        "can_export",
+        &(principal.can(Permission::Export) || view.active),
+    );
+    context.insert("user_id", &principal.user_id);
+    view.insert_into(&mut context);
+    pagination.insert_listing(&mut context);
 
     let rendered = tera.render("timekeeping.html", &context).unwrap();
     Html(rendered).into_response()
@@ -223,7 +580,9 @@ async fn render_for_admins_with_filter(
 #[allow(dead_code)]
 async fn render_for_none_admins_with_filter(
     pagination: &Parameters,
+    principal: &Principal,
     db: &mut Db,
+    offset: &FixedOffset,
 ) -> axum::response::Response {
     let (Some(name), Some(start_date), Some(end_date)) = (
         pagination.name.clone(),
@@ -239,17 +598,17 @@ async fn render_for_none_admins_with_filter(
     let total_users = db
         .device_login()
         .none_admin_filter_login_status_by_name_and_date_count(
-            pagination.user_id.as_str(),
+            principal.user_id.as_str(),
             start_date.as_str(),
             end_date.as_str(),
         )
         .await;
     let total_pages = (total_users as f64 / per_page as f64).ceil() as u64;
     let page = page.min(total_pages.max(1));
-    let mut users = db
+    let users = db
         .device_login()
         .none_admin_filter_login_status_by_name_and_date(
-            pagination.user_id.as_str(),
+            principal.user_id.as_str(),
             per_page,
             page,
             name.as_str(),
@@ -258,27 +617,22 @@ async fn render_for_none_admins_with_filter(
         )
         .await;
 
-    users.iter_mut().for_each(|user| {
-        let utc_time = user.created_at.parse::<DateTime<Utc>>().unwrap();
-        let gmt_plus_8 = FixedOffset::east_opt(8 * 3600).expect("Invalid offset");
-        let local_time = utc_time.with_timezone(&gmt_plus_8);
-        let created_at = local_time.format("%Y-%m-%d %H:%M:%S%.3f %:z").to_string();
-
-        user.created_at = created_at
-    });
-
     let mut context = Context::new();
-    context.insert("users", &users);
+    context.insert("users", &views(users, offset));
     context.insert("current_page", &page);
     context.insert("per_page", &per_page);
     context.insert("total_pages", &total_pages);
     context.insert("next_page", &(page + 1));
     context.insert("prev_page", &(page.saturating_sub(1)));
     context.insert("name", &name);
+    context.insert("team", &"");
     context.insert("start_date", &start_date);
     context.insert("end_date", &end_date);
-    context.insert("is_admin", &false);
-    context.insert("user_id", &pagination.user_id);
+    context.insert("sees_others", &false);
+    context.insert("can_export", &false);
+    context.insert("user_id", &principal.user_id);
+    ManagerView::default().insert_into(&mut context);
+    pagination.insert_listing(&mut context);
 
     let rendered = tera.render("timekeeping.html", &context).unwrap();
     Html(rendered).into_response()
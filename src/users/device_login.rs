@@ -1,238 +1,487 @@
-use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, Pool, Sqlite};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+use sqlx::{
+    Database, Decode, Encode, FromRow, Pool, Sqlite, Type, encode::IsNull, error::BoxDynError,
+};
+use strum_macros::{AsRefStr, Display, EnumString, IntoStaticStr};
+
+pub use super::{postgres::PgDeviceLoginDatabase, sqlite::DeviceLoginDatabase};
+
+pub const LOGIN_PROVIDER_AUTO_CLOSE: &str = "auto_close";
+pub const LOGIN_PROVIDER_IMPORT: &str = "import";
+
+/// What a clock event records. Stored and serialized by its
+/// `SCREAMING_SNAKE_CASE` name; anything else is refused on write.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    Display,
+    AsRefStr,
+    IntoStaticStr,
+    EnumString,
+)]
+#[serde(try_from = "String", into = "&'static str")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE", ascii_case_insensitive)]
+pub enum LoginStatus {
+    In,
+    Out,
+    BreakStart,
+    BreakEnd,
+    /// Closes a session the user forgot to clock out of.
+    AutoOut,
+    /// Entered by an admin to fix a missed or wrong clock.
+    Corrected,
+}
+
+impl TryFrom<String> for LoginStatus {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .trim()
+            .parse()
+            .map_err(|_| format!("Unknown login status {value}."))
+    }
+}
+
+impl<DB: Database> Type<DB> for LoginStatus
+where
+    String: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, DB: Database> Encode<'q, DB> for LoginStatus
+where
+    &'q str: Encode<'q, DB>,
+{
+    fn encode_by_ref(
+        &self,
+        buf: &mut <DB as Database>::ArgumentBuffer<'q>,
+    ) -> Result<IsNull, BoxDynError> {
+        let name: &'q str = <&'static str>::from(*self);
+        name.encode_by_ref(buf)
+    }
+}
+
+impl<'r, DB: Database> Decode<'r, DB> for LoginStatus
+where
+    &'r str: Decode<'r, DB>,
+{
+    fn decode(value: <DB as Database>::ValueRef<'r>) -> Result<Self, BoxDynError> {
+        Ok(<&str as Decode<DB>>::decode(value)?.parse()?)
+    }
+}
 
 #[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
 pub struct DeviceLogin {
+    pub id: i64,
+    pub user_id: String,
+    pub name: String,
+    pub email: String,
+    pub device_id: String,
+    pub login_status: LoginStatus,
+    pub ip_address: String,
+    pub location: String,
+    pub isp: String,
+    #[serde(serialize_with = "serialize_canonical_time")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// A user still on the clock: their latest event, an `IN` or a break, and
+/// when the session it belongs to was clocked into.
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct OpenSession {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub login: DeviceLogin,
+    #[serde(serialize_with = "serialize_canonical_time")]
+    pub clocked_in_at: DateTime<Utc>,
+}
+
+/// A clock event set aside because its `created_at` is not a time or its
+/// `login_status` not a `LoginStatus`. It keeps its id and the original
+/// text so it can be fixed by hand.
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct QuarantinedLogin {
+    pub login_id: i64,
     pub user_id: String,
+    pub session_id: String,
     pub name: String,
     pub email: String,
     pub device_id: String,
+    pub login_provider: String,
     pub login_status: String,
     pub ip_address: String,
     pub location: String,
     pub isp: String,
-    pub created_at: String, // Assuming it's stored as TEXT (ISO8601)
+    pub created_at: String,
+    pub quarantined_at: String,
 }
 
-#[derive(Clone, Debug)]
-pub struct DeviceLoginDatabase {
-    pool: Pool<Sqlite>,
+/// Clock-ins from one location and ISP.
+#[derive(Debug, FromRow, Serialize, Clone)]
+pub struct NetworkCount {
+    pub location: String,
+    pub isp: String,
+    pub clock_ins: i64,
+    pub users: i64,
+}
+
+/// A `LIKE` pattern matching `text` anywhere, with its own `%`, `_` and
+/// `\` taken literally. Queries pair it with `ESCAPE '\'`.
+pub fn like_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
+/// `at` the way `created_at` is stored: UTC with milliseconds and a `Z`, so
+/// that times compare correctly as text.
+pub fn canonical_time(at: &DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Keeps exports, webhooks and the JSON API in the same form as the column.
+fn serialize_canonical_time<S: Serializer>(
+    at: &DateTime<Utc>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&canonical_time(at))
 }
 
-impl DeviceLoginDatabase {
-    pub async fn new(pool: Pool<Sqlite>) -> Self {
-        Self { pool }
+/// A clock event as submitted through the service's own write path.
+#[derive(Debug, Serialize, Clone, Deserialize)]
+pub struct NewDeviceLogin {
+    pub user_id: String,
+    #[serde(default)]
+    pub session_id: String,
+    pub name: String,
+    pub email: String,
+    pub device_id: String,
+    pub login_provider: String,
+    pub login_status: LoginStatus,
+    pub ip_address: String,
+    pub location: String,
+    pub isp: String,
+}
+
+/// Columns the timekeeping table can be sorted by. Queries only ever see
+/// the column names below, never the requested text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display, AsRefStr, EnumString)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum SortColumn {
+    Name,
+    Email,
+    Status,
+    Ip,
+    Location,
+    Isp,
+    #[default]
+    Time,
+}
+
+impl SortColumn {
+    fn column(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Email => "email",
+            Self::Status => "login_status",
+            Self::Ip => "ip_address",
+            Self::Location => "location",
+            Self::Isp => "isp",
+            Self::Time => "created_at",
+        }
     }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Display, AsRefStr, EnumString)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Order of a listing, latest first unless asked otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LoginSort {
+    pub column: SortColumn,
+    pub order: SortOrder,
+}
 
-    pub async fn device_login_history(
+impl LoginSort {
+    /// The `ORDER BY` clause, latest first among equal values.
+    pub fn order_by(&self) -> String {
+        let order = match self.order {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+        match self.column {
+            SortColumn::Time => format!("created_at {order}"),
+            column => format!("{} {order}, created_at DESC", column.column()),
+        }
+    }
+}
+
+/// Criteria of the filtered admin listing. The user lists are JSON arrays
+/// resolved from the directory beforehand, as clock events may be stored
+/// in another database than the directory.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoginFilter<'a> {
+    pub name: &'a str,
+    /// Users whose directory name matches `name`, so history recorded
+    /// under an older name is included.
+    pub named_users: Option<&'a str>,
+    /// Members of the selected team, or `None` for any team.
+    pub team_members: Option<&'a str>,
+    /// Users the caller may see, or `None` for everyone.
+    pub visible_users: Option<&'a str>,
+    pub start_rfc3339: &'a str,
+    pub end_rfc3339: &'a str,
+    /// Free text matched against email, device id, IP address and location.
+    pub search: &'a str,
+}
+
+/// Storage of clock events. Every query on `device_login` goes through
+/// here so the table can live in SQLite or PostgreSQL.
+#[async_trait]
+pub trait DeviceLoginStore: Send + Sync {
+    /// `visible_users` is a JSON array of the user ids whose rows may be
+    /// returned, or `None` for everyone's. `search` is matched as for
+    /// `LoginFilter::search`, empty for every row.
+    async fn device_login_history(
         &self,
         limit_per_page: u64,
         page_number: u64,
-    ) -> Vec<DeviceLogin> {
-        let limit = limit_per_page;
-        let offset = limit * (page_number - 1);
-
-        sqlx::query_as::<_, DeviceLogin>(
-            r#"
-            SELECT user_id, name, email, device_id,
-                   login_status, ip_address, location, isp, created_at
-            FROM device_login
-            ORDER BY created_at DESC
-            LIMIT ? OFFSET ?;
-            "#,
-        )
-        .bind(limit as i64)
-        .bind(offset as i64)
-        .fetch_all(&self.pool)
-        .await
-        .unwrap_or_else(|err| {
-            log::error!("DeviceLoginDatabase::device_login_history: {err}");
-            Vec::new()
-        })
-    }
+        visible_users: Option<&str>,
+        search: &str,
+        sort: LoginSort,
+    ) -> Vec<DeviceLogin>;
 
-    pub async fn device_login_history_per_user(
+    async fn device_login_history_per_user(
         &self,
         user_id: &str,
         limit_per_page: u64,
         page_number: u64,
-    ) -> Vec<DeviceLogin> {
-        let limit = limit_per_page;
-        let offset = limit * (page_number - 1);
-
-        sqlx::query_as::<_, DeviceLogin>(
-            r#"
-            SELECT user_id, name, email, device_id, 
-                   login_status, ip_address, location, isp, created_at
-            FROM device_login
-            WHERE user_id = ?
-            ORDER BY created_at DESC
-            LIMIT ? OFFSET ?;
-            "#,
-        )
-        .bind(user_id)
-        .bind(limit as i64)
-        .bind(offset as i64)
-        .fetch_all(&self.pool)
-        .await
-        .unwrap_or_else(|err| {
-            log::error!("DeviceLoginDatabase::device_login_history_per_user: {err}");
-            Vec::new()
-        })
-    }
+        search: &str,
+        sort: LoginSort,
+    ) -> Vec<DeviceLogin>;
 
-    pub async fn size_per_user(&self, user_id: &str) -> usize {
-        let size: usize =
-            sqlx::query_scalar::<_, u64>("SELECT COUNT(*) FROM device_login WHERE user_id = ?")
-                .bind(user_id)
-                .fetch_one(&self.pool)
-                .await
-                .map(|count| count as usize)
-                .unwrap_or_else(|err| {
-                    log::error!("DeviceLoginDatabase::size_per_user: {err}");
-                    0
-                });
-
-        size
-    }
+    async fn size_per_user(&self, user_id: &str, search: &str) -> usize;
 
-    pub async fn size(&self) -> usize {
-        let size: usize = sqlx::query_scalar::<_, u64>("SELECT COUNT(*) FROM device_login")
-            .fetch_one(&self.pool)
-            .await
-            .map(|count| count as usize)
-            .unwrap_or_else(|err| {
-                log::error!("DeviceLoginDatabase::size: {err}");
-                0
-            });
-
-        size
-    }
+    async fn size(&self, visible_users: Option<&str>, search: &str) -> usize;
+
+    async fn admin_filter_login_status_by_name_and_date(
+        &self,
+        limit_per_page: u64,
+        page_number: u64,
+        filter: &LoginFilter<'_>,
+        sort: LoginSort,
+    ) -> Vec<DeviceLogin>;
+
+    async fn admin_filter_login_status_by_name_and_date_count(
+        &self,
+        filter: &LoginFilter<'_>,
+    ) -> usize;
 
-    pub async fn admin_filter_login_status_by_name_and_date(
+    async fn none_admin_filter_login_status_by_name_and_date(
         &self,
+        user_id: &str,
         limit_per_page: u64,
         page_number: u64,
         name_filter: &str,
         start_rfc3339: &str,
         end_rfc3339: &str,
-    ) -> Vec<DeviceLogin> {
-        let limit = limit_per_page;
-        let offset = limit * (page_number - 1);
-        sqlx::query_as::<_, DeviceLogin>(
-            r#"
-            SELECT user_id, name, email, device_id,
-                   login_status, ip_address, location, isp, created_at
-            FROM device_login
-            WHERE name LIKE ?
-              AND created_at BETWEEN ? AND ?
-            ORDER BY created_at DESC
-            LIMIT ? OFFSET ?;
-            "#,
-        )
-        .bind(format!("%{}%", name_filter))
-        .bind(start_rfc3339)
-        .bind(end_rfc3339)
-        .bind(limit as i64)
-        .bind(offset as i64)
-        .fetch_all(&self.pool)
-        .await
-        .unwrap_or_else(|err| {
-            log::error!("DeviceLoginDatabase::admin_filter_login_status_by_name_and_date: {err}");
-            Vec::new()
-        })
-    }
+    ) -> Vec<DeviceLogin>;
 
-    pub async fn admin_filter_login_status_by_name_and_date_count(
+    async fn none_admin_filter_login_status_by_name_and_date_count(
         &self,
-        name_filter: &str,
+        user_id: &str,
         start_rfc3339: &str,
         end_rfc3339: &str,
-    ) -> usize {
-        sqlx::query_scalar::<_, i64>(
-            r#"
-            SELECT COUNT(*)
-            FROM device_login
-            WHERE name LIKE ?
-              AND created_at BETWEEN ? AND ?;
-            "#,
-        )
-        .bind(format!("%{}%", name_filter))
-        .bind(start_rfc3339)
-        .bind(end_rfc3339)
-        .fetch_one(&self.pool)
-        .await
-        .map(|count| count as usize)
-        .unwrap_or_else(|err| {
-            log::error!(
-                "DeviceLoginDatabase::admin_filter_login_status_by_name_and_date_count: {err}"
-            );
-            0
-        })
-    }
+    ) -> usize;
+
+    async fn last_id(&self) -> i64;
 
-    pub async fn none_admin_filter_login_status_by_name_and_date(
+    async fn device_login_after(&self, last_id: i64, limit: u64) -> Vec<DeviceLogin>;
+
+    async fn device_login_after_per_user(
+        &self,
+        user_id: &str,
+        last_id: i64,
+        limit: u64,
+    ) -> Vec<DeviceLogin>;
+
+    /// As `device_login_after`, leaving out imported history, so jobs that
+    /// react to clock events only see them as they happen.
+    async fn live_device_login_after(&self, last_id: i64, limit: u64) -> Vec<DeviceLogin>;
+
+    /// Latest event per user when it still leaves them on the clock, an `IN`
+    /// or a break, and is older than `before_rfc3339` but not older than
+    /// `since_rfc3339` when given. Imported history is
+    /// not a session anyone is still in. The session started
+    /// at the earliest `IN` not followed by an `OUT` or `AUTO_OUT`, as
+    /// `attendance::sessions` pairs them.
+    async fn open_sessions_before(
+        &self,
+        since_rfc3339: Option<&str>,
+        before_rfc3339: &str,
+    ) -> Vec<OpenSession>;
+
+    /// The user's earliest `IN` in the range by time, so backfilled history
+    /// counts where it happened.
+    async fn first_clock_in_between(
         &self,
         user_id: &str,
-        limit_per_page: u64,
-        page_number: u64,
-        name_filter: &str,
         start_rfc3339: &str,
         end_rfc3339: &str,
-    ) -> Vec<DeviceLogin> {
-        let limit = limit_per_page;
-        let offset = limit * (page_number - 1);
-        sqlx::query_as::<_, DeviceLogin>(
-            r#"
-            SELECT user_id, name, email, device_id,
-                   login_status, ip_address, location, isp, created_at
-            FROM device_login
-            WHERE user_id = ?
-              AND name LIKE ?
-              AND created_at BETWEEN ? AND ?
-            ORDER BY created_at DESC
-            LIMIT ? OFFSET ?;
-            "#,
-        )
-        .bind(user_id)
-        .bind(format!("%{}%", name_filter))
-        .bind(start_rfc3339)
-        .bind(end_rfc3339)
-        .bind(limit as i64)
-        .bind(offset as i64)
-        .fetch_all(&self.pool)
-        .await
-        .unwrap_or_else(|err| {
-            log::error!("DeviceLoginDatabase::admin_filter_login_status_by_name_and_date: {err}");
-            Vec::new()
-        })
-    }
+    ) -> Option<i64>;
+
+    /// Every event in the range, oldest first, for session computations.
+    async fn device_login_between(
+        &self,
+        start_rfc3339: &str,
+        end_rfc3339: &str,
+    ) -> Vec<DeviceLogin>;
 
-    pub async fn none_admin_filter_login_status_by_name_and_date_count(
+    /// Clock-ins in the range per location and ISP, busiest first.
+    /// `visible_users` as for `device_login_history`.
+    async fn clock_ins_by_network(
         &self,
+        start_rfc3339: &str,
+        end_rfc3339: &str,
+        visible_users: Option<&str>,
+        limit: u64,
+    ) -> Vec<NetworkCount>;
+
+    /// Closes the session opened by `login_id` with a system generated
+    /// `AUTO_OUT` on the same session and device.
+    async fn insert_auto_out(&self, login_id: i64, created_at: &str) -> bool;
+
+    /// The user's event right before `before_id` in time, ignoring system
+    /// generated ones. Equal times fall back to the id, so backfilled
+    /// history is compared where it happened rather than where it was
+    /// inserted.
+    async fn previous_login(&self, user_id: &str, before_id: i64) -> Option<DeviceLogin>;
+
+    /// Distinct devices, locations and ISPs the user had before `before_id`
+    /// in time, ordered as for `previous_login`.
+    async fn known_footprint(
+        &self,
+        user_id: &str,
+        before_id: i64,
+    ) -> (Vec<String>, Vec<String>, Vec<String>);
+
+    /// Other users who clocked in on `device_id` within the time range.
+    async fn other_clock_ins_on_device(
+        &self,
+        device_id: &str,
         user_id: &str,
         start_rfc3339: &str,
         end_rfc3339: &str,
-    ) -> usize {
-        sqlx::query_scalar::<_, i64>(
-            r#"
-            SELECT COUNT(*)
-            FROM device_login
-            WHERE user_id = ?
-              AND created_at BETWEEN ? AND ?;
-            "#,
-        )
-        .bind(user_id)
-        .bind(start_rfc3339)
-        .bind(end_rfc3339)
-        .fetch_one(&self.pool)
-        .await
-        .map(|count| count as usize)
-        .unwrap_or_else(|err| {
-            log::error!(
-                "DeviceLoginDatabase::none_admin_filter_login_status_by_name_and_date_count: {err}"
-            );
-            0
-        })
+    ) -> Vec<String>;
+
+    async fn insert(&self, login: &NewDeviceLogin, created_at: &str) -> Result<i64, String>;
+
+    /// Inserts each event unless the user already has one with the same
+    /// status at the same time, all in one transaction. Returns whether
+    /// each was inserted.
+    async fn insert_new(&self, logins: &[(NewDeviceLogin, String)]) -> Result<Vec<bool>, String>;
+
+    async fn device_login_by_ids(&self, ids: &[i64]) -> Vec<DeviceLogin>;
+
+    /// All of the user's events, oldest first.
+    async fn device_login_of_user(&self, user_id: &str) -> Vec<DeviceLogin>;
+
+    /// Every address the user clocked with.
+    async fn emails_of_user(&self, user_id: &str) -> Vec<String>;
+
+    /// Moves the user's events to `pseudonym`, dropping everything that
+    /// identifies them but status and time.
+    async fn pseudonymize_user(&self, user_id: &str, pseudonym: &str) -> Result<u64, String>;
+
+    async fn delete_user(&self, user_id: &str) -> Result<u64, String>;
+
+    /// Events before the cutoff that still hold personal data.
+    async fn count_to_anonymize(&self, cutoff_rfc3339: &str) -> i64;
+
+    /// Ids and IP addresses of the next `limit` events to anonymize.
+    async fn to_anonymize(&self, cutoff_rfc3339: &str, limit: u64) -> Vec<(i64, String)>;
+
+    /// Replaces the IP address of each event, drops its ISP and location
+    /// and marks it anonymized.
+    async fn anonymize(&self, masked: &[(i64, String)]) -> u64;
+
+    async fn count_expired(&self, cutoff_rfc3339: &str) -> i64;
+
+    /// Ids of the next `limit` events before the cutoff.
+    async fn expired_ids(&self, cutoff_rfc3339: &str, limit: u64) -> Vec<i64>;
+
+    async fn delete_by_ids(&self, ids: &[i64]) -> u64;
+
+    /// Events set aside for an unreadable time or status, oldest id first.
+    async fn quarantined(&self) -> Vec<QuarantinedLogin>;
+
+    /// The user's events set aside for an unreadable time or status.
+    async fn quarantined_of_user(&self, user_id: &str) -> Vec<QuarantinedLogin>;
+
+    /// Deletes the user's events set aside as unreadable. Payroll has no use
+    /// for them, so they are not kept under a pseudonym either.
+    async fn delete_quarantined_of_user(&self, user_id: &str) -> Result<u64, String>;
+
+    /// Events set aside before the cutoff that still hold an IP address,
+    /// location or ISP. Their own time may not be readable, so they are aged
+    /// by when they were set aside.
+    async fn count_quarantined_to_anonymize(&self, cutoff_rfc3339: &str) -> i64;
+
+    /// Drops the IP address, location and ISP of events set aside before the
+    /// cutoff.
+    async fn anonymize_quarantined(&self, cutoff_rfc3339: &str) -> u64;
+
+    /// Events set aside before the cutoff.
+    async fn count_quarantined_expired(&self, cutoff_rfc3339: &str) -> i64;
+
+    async fn delete_quarantined_expired(&self, cutoff_rfc3339: &str) -> u64;
+}
+
+/// Whether `url` names a PostgreSQL store rather than the SQLite file.
+pub fn is_postgres(url: &str) -> bool {
+    url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+/// The store named by `url`: PostgreSQL for `postgres://` URLs, otherwise
+/// the SQLite database everything else lives in.
+pub async fn connect(url: &str, sqlite: &Pool<Sqlite>) -> Arc<dyn DeviceLoginStore> {
+    let store: Arc<dyn DeviceLoginStore> = if is_postgres(url) {
+        Arc::new(PgDeviceLoginDatabase::connect(url).await)
+    } else {
+        Arc::new(DeviceLoginDatabase::new(sqlite.clone()).await)
+    };
+
+    let quarantined = store.quarantined().await.len();
+    if quarantined > 0 {
+        log::warn!(
+            "{quarantined} clock events have an unreadable time or status and are kept out of \
+             device_login, list them with the `quarantined` command"
+        );
     }
+    store
 }
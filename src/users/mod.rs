@@ -1 +1,6 @@
 pub mod device_login;
+pub mod postgres;
+pub mod sqlite;
+
+#[cfg(test)]
+mod store_tests;
@@ -0,0 +1,1086 @@
+use async_trait::async_trait;
+use sqlx::{Pool, Postgres, QueryBuilder, postgres::PgPoolOptions};
+
+use super::device_login::{
+    DeviceLogin, DeviceLoginStore, LOGIN_PROVIDER_AUTO_CLOSE, LOGIN_PROVIDER_IMPORT, LoginFilter,
+    LoginSort, LoginStatus, NetworkCount, NewDeviceLogin, OpenSession, QuarantinedLogin,
+    like_pattern,
+};
+
+/// Matches the `ILIKE` pattern bound to `${param}` against the searchable
+/// columns, or every row for an empty search.
+fn search_clause(param: u8) -> String {
+    format!(
+        "(${param} = '%%' OR email ILIKE ${param} ESCAPE '\\' \
+         OR device_id ILIKE ${param} ESCAPE '\\' OR ip_address ILIKE ${param} ESCAPE '\\' \
+         OR location ILIKE ${param} ESCAPE '\\')"
+    )
+}
+
+const INIT_DB: &str = r#"
+CREATE TABLE IF NOT EXISTS device_login (
+                    id BIGSERIAL PRIMARY KEY,
+                    user_id TEXT NOT NULL,
+                    session_id TEXT,
+                    name TEXT,
+                    email TEXT,
+                    device_id TEXT,
+                    login_provider TEXT,
+                    login_status TEXT NOT NULL CONSTRAINT device_login_known_status
+                        CHECK (login_status IN ('IN', 'OUT', 'BREAK_START', 'BREAK_END',
+                                                'AUTO_OUT', 'CORRECTED')),
+                    ip_address TEXT,
+                    location TEXT,
+                    isp TEXT,
+                    created_at TIMESTAMPTZ NOT NULL,
+                    anonymized_at TIMESTAMPTZ
+                );
+CREATE INDEX IF NOT EXISTS device_login_user_time ON device_login (user_id, created_at);
+CREATE OR REPLACE FUNCTION device_login_canonical_status() RETURNS TRIGGER AS $fn$
+BEGIN
+    NEW.login_status := upper(trim(NEW.login_status));
+    RETURN NEW;
+END;
+$fn$ LANGUAGE plpgsql;
+CREATE OR REPLACE TRIGGER device_login_canonical_status BEFORE INSERT OR UPDATE ON device_login
+    FOR EACH ROW EXECUTE FUNCTION device_login_canonical_status();
+CREATE TABLE IF NOT EXISTS device_login_quarantine (
+                    id BIGSERIAL PRIMARY KEY,
+                    login_id BIGINT NOT NULL,
+                    user_id TEXT,
+                    session_id TEXT,
+                    name TEXT,
+                    email TEXT,
+                    device_id TEXT,
+                    login_provider TEXT,
+                    login_status TEXT,
+                    ip_address TEXT,
+                    location TEXT,
+                    isp TEXT,
+                    created_at TEXT,
+                    quarantined_at TEXT NOT NULL
+                );
+"#;
+
+/// Clock events still holding personal data past the cutoff. `anonymize`
+/// stamps the rows it strips, so they drop out of it.
+const PENDING_ANONYMIZATION: &str = r#"
+    created_at < $1::timestamptz
+    AND anonymized_at IS NULL
+    AND (coalesce(isp, '') <> ''
+         OR coalesce(location, '') <> ''
+         OR coalesce(ip_address, '') <> '')
+"#;
+
+/// The columns of `device_login_quarantine` as a `QuarantinedLogin`.
+const QUARANTINED_COLUMNS: &str = r#"
+    login_id, coalesce(user_id, '') AS user_id, coalesce(session_id, '') AS session_id,
+    coalesce(name, '') AS name, coalesce(email, '') AS email,
+    coalesce(device_id, '') AS device_id, coalesce(login_provider, '') AS login_provider,
+    coalesce(login_status, '') AS login_status, coalesce(ip_address, '') AS ip_address,
+    coalesce(location, '') AS location, coalesce(isp, '') AS isp,
+    coalesce(created_at, '') AS created_at, quarantined_at
+"#;
+
+/// Events set aside before the cutoff that still hold network details.
+const QUARANTINED_PENDING_ANONYMIZATION: &str = r#"
+    quarantined_at < $1
+    AND (coalesce(ip_address, '') <> '' OR coalesce(location, '') <> ''
+         OR coalesce(isp, '') <> '')
+"#;
+
+/// Clock events in PostgreSQL, with ids from a sequence where SQLite uses
+/// the rowid.
+#[derive(Clone, Debug)]
+pub struct PgDeviceLoginDatabase {
+    pool: Pool<Postgres>,
+}
+
+impl PgDeviceLoginDatabase {
+    /// Connects and creates the tables if they do not exist yet. Statuses
+    /// are upper cased and trimmed on the way in, as SQLite's trigger does,
+    /// so the main server may keep sending legacy spellings.
+    pub async fn connect(url: &str) -> Self {
+        let pool = PgPoolOptions::new()
+            .connect(url)
+            .await
+            .expect("Failed to connect to database");
+        sqlx::raw_sql(INIT_DB)
+            .execute(&pool)
+            .await
+            .expect("Failed to create table");
+
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl DeviceLoginStore for PgDeviceLoginDatabase {
+    async fn device_login_history(
+        &self,
+        limit_per_page: u64,
+        page_number: u64,
+        visible_users: Option<&str>,
+        search: &str,
+        sort: LoginSort,
+    ) -> Vec<DeviceLogin> {
+        let limit = limit_per_page;
+        let offset = limit * (page_number - 1);
+
+        sqlx::query_as::<_, DeviceLogin>(&format!(
+            r#"
+            SELECT id, user_id, name, email, device_id,
+                   login_status, ip_address, location, isp, created_at
+            FROM device_login
+            WHERE ($1 IS NULL OR user_id IN (SELECT jsonb_array_elements_text($1::jsonb)))
+              AND {search}
+            ORDER BY {order_by}
+            LIMIT $2 OFFSET $3;
+            "#,
+            search = search_clause(4),
+            order_by = sort.order_by(),
+        ))
+        .bind(visible_users)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .bind(like_pattern(search))
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::device_login_history: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn device_login_history_per_user(
+        &self,
+        user_id: &str,
+        limit_per_page: u64,
+        page_number: u64,
+        search: &str,
+        sort: LoginSort,
+    ) -> Vec<DeviceLogin> {
+        let limit = limit_per_page;
+        let offset = limit * (page_number - 1);
+
+        sqlx::query_as::<_, DeviceLogin>(&format!(
+            r#"
+            SELECT id, user_id, name, email, device_id,
+                   login_status, ip_address, location, isp, created_at
+            FROM device_login
+            WHERE user_id = $1
+              AND {search}
+            ORDER BY {order_by}
+            LIMIT $2 OFFSET $3;
+            "#,
+            search = search_clause(4),
+            order_by = sort.order_by(),
+        ))
+        .bind(user_id)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .bind(like_pattern(search))
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::device_login_history_per_user: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn size_per_user(&self, user_id: &str, search: &str) -> usize {
+        let size: usize = sqlx::query_scalar::<_, i64>(&format!(
+            "SELECT COUNT(*) FROM device_login WHERE user_id = $1 AND {}",
+            search_clause(2)
+        ))
+        .bind(user_id)
+        .bind(like_pattern(search))
+        .fetch_one(&self.pool)
+        .await
+        .map(|count| count as usize)
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::size_per_user: {err}");
+            0
+        });
+
+        size
+    }
+
+    async fn size(&self, visible_users: Option<&str>, search: &str) -> usize {
+        let size: usize = sqlx::query_scalar::<_, i64>(&format!(
+            r#"
+            SELECT COUNT(*)
+            FROM device_login
+            WHERE ($1 IS NULL OR user_id IN (SELECT jsonb_array_elements_text($1::jsonb)))
+              AND {}
+            "#,
+            search_clause(2)
+        ))
+        .bind(visible_users)
+        .bind(like_pattern(search))
+        .fetch_one(&self.pool)
+        .await
+        .map(|count| count as usize)
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::size: {err}");
+            0
+        });
+
+        size
+    }
+
+    async fn admin_filter_login_status_by_name_and_date(
+        &self,
+        limit_per_page: u64,
+        page_number: u64,
+        filter: &LoginFilter<'_>,
+        sort: LoginSort,
+    ) -> Vec<DeviceLogin> {
+        let limit = limit_per_page;
+        let offset = limit * (page_number - 1);
+        sqlx::query_as::<_, DeviceLogin>(&format!(
+            r#"
+            SELECT id, user_id, name, email, device_id,
+                   login_status, ip_address, location, isp, created_at
+            FROM device_login
+            WHERE ($1 = '%%'
+                   OR name ILIKE $1 ESCAPE '\'
+                   OR user_id IN (SELECT jsonb_array_elements_text(coalesce($8, '[]')::jsonb)))
+              AND ($2 IS NULL OR user_id IN (SELECT jsonb_array_elements_text($2::jsonb)))
+              AND ($7 IS NULL OR user_id IN (SELECT jsonb_array_elements_text($7::jsonb)))
+              AND created_at BETWEEN $3::timestamptz AND $4::timestamptz
+              AND {search}
+            ORDER BY {order_by}
+            LIMIT $5 OFFSET $6;
+            "#,
+            search = search_clause(9),
+            order_by = sort.order_by(),
+        ))
+        .bind(like_pattern(filter.name))
+        .bind(filter.team_members)
+        .bind(filter.start_rfc3339)
+        .bind(filter.end_rfc3339)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .bind(filter.visible_users)
+        .bind(filter.named_users)
+        .bind(like_pattern(filter.search))
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::admin_filter_login_status_by_name_and_date: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn admin_filter_login_status_by_name_and_date_count(
+        &self,
+        filter: &LoginFilter<'_>,
+    ) -> usize {
+        sqlx::query_scalar::<_, i64>(&format!(
+            r#"
+            SELECT COUNT(*)
+            FROM device_login
+            WHERE ($1 = '%%'
+                   OR name ILIKE $1 ESCAPE '\'
+                   OR user_id IN (SELECT jsonb_array_elements_text(coalesce($6, '[]')::jsonb)))
+              AND ($2 IS NULL OR user_id IN (SELECT jsonb_array_elements_text($2::jsonb)))
+              AND ($5 IS NULL OR user_id IN (SELECT jsonb_array_elements_text($5::jsonb)))
+              AND created_at BETWEEN $3::timestamptz AND $4::timestamptz
+              AND {};
+            "#,
+            search_clause(7)
+        ))
+        .bind(like_pattern(filter.name))
+        .bind(filter.team_members)
+        .bind(filter.start_rfc3339)
+        .bind(filter.end_rfc3339)
+        .bind(filter.visible_users)
+        .bind(filter.named_users)
+        .bind(like_pattern(filter.search))
+        .fetch_one(&self.pool)
+        .await
+        .map(|count| count as usize)
+        .unwrap_or_else(|err| {
+            log::error!(
+                "PgDeviceLoginDatabase::admin_filter_login_status_by_name_and_date_count: {err}"
+            );
+            0
+        })
+    }
+
+    async fn none_admin_filter_login_status_by_name_and_date(
+        &self,
+        user_id: &str,
+        limit_per_page: u64,
+        page_number: u64,
+        name_filter: &str,
+        start_rfc3339: &str,
+        end_rfc3339: &str,
+    ) -> Vec<DeviceLogin> {
+        let limit = limit_per_page;
+        let offset = limit * (page_number - 1);
+        sqlx::query_as::<_, DeviceLogin>(
+            r#"
+            SELECT id, user_id, name, email, device_id,
+                   login_status, ip_address, location, isp, created_at
+            FROM device_login
+            WHERE user_id = $1
+              AND name ILIKE $2 ESCAPE '\'
+              AND created_at BETWEEN $3::timestamptz AND $4::timestamptz
+            ORDER BY created_at DESC
+            LIMIT $5 OFFSET $6;
+            "#,
+        )
+        .bind(user_id)
+        .bind(like_pattern(name_filter))
+        .bind(start_rfc3339)
+        .bind(end_rfc3339)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::admin_filter_login_status_by_name_and_date: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn none_admin_filter_login_status_by_name_and_date_count(
+        &self,
+        user_id: &str,
+        start_rfc3339: &str,
+        end_rfc3339: &str,
+    ) -> usize {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*)
+            FROM device_login
+            WHERE user_id = $1
+              AND created_at BETWEEN $2::timestamptz AND $3::timestamptz;
+            "#,
+        )
+        .bind(user_id)
+        .bind(start_rfc3339)
+        .bind(end_rfc3339)
+        .fetch_one(&self.pool)
+        .await
+        .map(|count| count as usize)
+        .unwrap_or_else(|err| {
+            log::error!(
+                "PgDeviceLoginDatabase::none_admin_filter_login_status_by_name_and_date_count: {err}"
+            );
+            0
+        })
+    }
+
+    async fn last_id(&self) -> i64 {
+        sqlx::query_scalar::<_, i64>("SELECT COALESCE(MAX(id), 0) FROM device_login")
+            .fetch_one(&self.pool)
+            .await
+            .unwrap_or_else(|err| {
+                log::error!("PgDeviceLoginDatabase::last_id: {err}");
+                0
+            })
+    }
+
+    async fn device_login_after(&self, last_id: i64, limit: u64) -> Vec<DeviceLogin> {
+        sqlx::query_as::<_, DeviceLogin>(
+            r#"
+            SELECT id, user_id, name, email, device_id,
+                   login_status, ip_address, location, isp, created_at
+            FROM device_login
+            WHERE id > $1
+            ORDER BY id ASC
+            LIMIT $2;
+            "#,
+        )
+        .bind(last_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::device_login_after: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn device_login_after_per_user(
+        &self,
+        user_id: &str,
+        last_id: i64,
+        limit: u64,
+    ) -> Vec<DeviceLogin> {
+        sqlx::query_as::<_, DeviceLogin>(
+            r#"
+            SELECT id, user_id, name, email, device_id,
+                   login_status, ip_address, location, isp, created_at
+            FROM device_login
+            WHERE user_id = $1
+              AND id > $2
+            ORDER BY id ASC
+            LIMIT $3;
+            "#,
+        )
+        .bind(user_id)
+        .bind(last_id)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::device_login_after_per_user: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn live_device_login_after(&self, last_id: i64, limit: u64) -> Vec<DeviceLogin> {
+        sqlx::query_as::<_, DeviceLogin>(
+            r#"
+            SELECT id, user_id, name, email, device_id,
+                   login_status, ip_address, location, isp, created_at
+            FROM device_login
+            WHERE id > $1
+              AND login_provider IS DISTINCT FROM $3
+            ORDER BY id ASC
+            LIMIT $2;
+            "#,
+        )
+        .bind(last_id)
+        .bind(limit as i64)
+        .bind(LOGIN_PROVIDER_IMPORT)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::live_device_login_after: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn open_sessions_before(
+        &self,
+        since_rfc3339: Option<&str>,
+        before_rfc3339: &str,
+    ) -> Vec<OpenSession> {
+        sqlx::query_as::<_, OpenSession>(
+            r#"
+            SELECT d.id, d.user_id, d.name, d.email, d.device_id,
+                   d.login_status, d.ip_address, d.location, d.isp, d.created_at,
+                   COALESCE((
+                       SELECT MIN(i.created_at) FROM device_login i
+                       WHERE i.user_id = d.user_id
+                         AND i.login_status = 'IN'
+                         AND NOT EXISTS (
+                             SELECT 1 FROM device_login o
+                             WHERE o.user_id = d.user_id
+                               AND o.login_status IN ('OUT', 'AUTO_OUT')
+                               AND o.created_at >= i.created_at
+                         )
+                   ), d.created_at) AS clocked_in_at
+            FROM device_login d
+            WHERE d.login_status IN ('IN', 'BREAK_START', 'BREAK_END')
+              AND d.created_at < $1::timestamptz
+              AND ($3::timestamptz IS NULL OR d.created_at >= $3::timestamptz)
+              AND d.login_provider IS DISTINCT FROM $2
+              AND d.id = (
+                  SELECT id FROM device_login
+                  WHERE user_id = d.user_id
+                  ORDER BY created_at DESC, id DESC
+                  LIMIT 1
+              )
+            ORDER BY d.created_at ASC;
+            "#,
+        )
+        .bind(before_rfc3339)
+        .bind(LOGIN_PROVIDER_IMPORT)
+        .bind(since_rfc3339)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::open_sessions_before: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn first_clock_in_between(
+        &self,
+        user_id: &str,
+        start_rfc3339: &str,
+        end_rfc3339: &str,
+    ) -> Option<i64> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT id
+            FROM device_login
+            WHERE user_id = $1
+              AND login_status = 'IN'
+              AND created_at BETWEEN $2::timestamptz AND $3::timestamptz
+            ORDER BY created_at ASC, id ASC
+            LIMIT 1;
+            "#,
+        )
+        .bind(user_id)
+        .bind(start_rfc3339)
+        .bind(end_rfc3339)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::first_clock_in_between: {err}");
+            None
+        })
+    }
+
+    async fn device_login_between(
+        &self,
+        start_rfc3339: &str,
+        end_rfc3339: &str,
+    ) -> Vec<DeviceLogin> {
+        sqlx::query_as::<_, DeviceLogin>(
+            r#"
+            SELECT id, user_id, name, email, device_id,
+                   login_status, ip_address, location, isp, created_at
+            FROM device_login
+            WHERE created_at BETWEEN $1::timestamptz AND $2::timestamptz
+            ORDER BY user_id ASC, created_at ASC;
+            "#,
+        )
+        .bind(start_rfc3339)
+        .bind(end_rfc3339)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::device_login_between: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn clock_ins_by_network(
+        &self,
+        start_rfc3339: &str,
+        end_rfc3339: &str,
+        visible_users: Option<&str>,
+        limit: u64,
+    ) -> Vec<NetworkCount> {
+        sqlx::query_as::<_, NetworkCount>(
+            r#"
+            SELECT location, isp, COUNT(*) AS clock_ins, COUNT(DISTINCT user_id) AS users
+            FROM device_login
+            WHERE login_status = 'IN'
+              AND created_at BETWEEN $1::timestamptz AND $2::timestamptz
+              AND ($3 IS NULL OR user_id IN (SELECT jsonb_array_elements_text($3::jsonb)))
+            GROUP BY location, isp
+            ORDER BY clock_ins DESC, location ASC, isp ASC
+            LIMIT $4;
+            "#,
+        )
+        .bind(start_rfc3339)
+        .bind(end_rfc3339)
+        .bind(visible_users)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::clock_ins_by_network: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn insert_auto_out(&self, login_id: i64, created_at: &str) -> bool {
+        sqlx::query(
+            r#"
+            INSERT INTO device_login (user_id, session_id, name, email, device_id,
+                                      login_provider, login_status, ip_address,
+                                      location, isp, created_at)
+            SELECT user_id, session_id, name, email, device_id,
+                   $1, $2, '', '', '', $3::timestamptz
+            FROM device_login
+            WHERE id = $4;
+            "#,
+        )
+        .bind(LOGIN_PROVIDER_AUTO_CLOSE)
+        .bind(LoginStatus::AutoOut)
+        .bind(created_at)
+        .bind(login_id)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::insert_auto_out: {err}");
+            false
+        })
+    }
+
+    async fn previous_login(&self, user_id: &str, before_id: i64) -> Option<DeviceLogin> {
+        sqlx::query_as::<_, DeviceLogin>(
+            r#"
+            SELECT id, user_id, name, email, device_id,
+                   login_status, ip_address, location, isp, created_at
+            FROM device_login
+            WHERE user_id = $1
+              AND (created_at, id) < (SELECT created_at, id FROM device_login WHERE id = $2)
+              AND login_provider IS DISTINCT FROM $3
+            ORDER BY created_at DESC, id DESC
+            LIMIT 1;
+            "#,
+        )
+        .bind(user_id)
+        .bind(before_id)
+        .bind(LOGIN_PROVIDER_AUTO_CLOSE)
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::previous_login: {err}");
+            None
+        })
+    }
+
+    async fn known_footprint(
+        &self,
+        user_id: &str,
+        before_id: i64,
+    ) -> (Vec<String>, Vec<String>, Vec<String>) {
+        let rows = sqlx::query_as::<_, (String, String, String)>(
+            r#"
+            SELECT DISTINCT COALESCE(device_id, ''), COALESCE(location, ''), COALESCE(isp, '')
+            FROM device_login
+            WHERE user_id = $1
+              AND (created_at, id) < (SELECT created_at, id FROM device_login WHERE id = $2)
+              AND login_provider IS DISTINCT FROM $3;
+            "#,
+        )
+        .bind(user_id)
+        .bind(before_id)
+        .bind(LOGIN_PROVIDER_AUTO_CLOSE)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::known_footprint: {err}");
+            Vec::new()
+        });
+
+        let mut devices = Vec::new();
+        let mut locations = Vec::new();
+        let mut isps = Vec::new();
+        for (device, location, isp) in rows {
+            devices.push(device);
+            locations.push(location);
+            isps.push(isp);
+        }
+        (devices, locations, isps)
+    }
+
+    async fn other_clock_ins_on_device(
+        &self,
+        device_id: &str,
+        user_id: &str,
+        start_rfc3339: &str,
+        end_rfc3339: &str,
+    ) -> Vec<String> {
+        sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT DISTINCT user_id
+            FROM device_login
+            WHERE device_id = $1
+              AND user_id != $2
+              AND login_status = 'IN'
+              AND created_at BETWEEN $3::timestamptz AND $4::timestamptz;
+            "#,
+        )
+        .bind(device_id)
+        .bind(user_id)
+        .bind(start_rfc3339)
+        .bind(end_rfc3339)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::other_clock_ins_on_device: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn insert(&self, login: &NewDeviceLogin, created_at: &str) -> Result<i64, String> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            INSERT INTO device_login (user_id, session_id, name, email, device_id,
+                                      login_provider, login_status, ip_address,
+                                      location, isp, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11::timestamptz)
+            RETURNING id;
+            "#,
+        )
+        .bind(&login.user_id)
+        .bind(&login.session_id)
+        .bind(&login.name)
+        .bind(&login.email)
+        .bind(&login.device_id)
+        .bind(&login.login_provider)
+        .bind(login.login_status)
+        .bind(&login.ip_address)
+        .bind(&login.location)
+        .bind(&login.isp)
+        .bind(created_at)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|err| {
+            log::error!("PgDeviceLoginDatabase::insert: {err}");
+            err.to_string()
+        })
+    }
+
+    async fn device_login_by_ids(&self, ids: &[i64]) -> Vec<DeviceLogin> {
+        if ids.is_empty() {
+            return Vec::new();
+        }
+
+        let mut query = QueryBuilder::<Postgres>::new(
+            r#"
+            SELECT id, user_id, name, email, device_id,
+                   login_status, ip_address, location, isp, created_at
+            FROM device_login
+            WHERE id IN ("#,
+        );
+        let mut separated = query.separated(", ");
+        for id in ids {
+            separated.push_bind(id);
+        }
+        query.push(") ORDER BY id DESC;");
+
+        query
+            .build_query_as::<DeviceLogin>()
+            .fetch_all(&self.pool)
+            .await
+            .unwrap_or_else(|err| {
+                log::error!("PgDeviceLoginDatabase::device_login_by_ids: {err}");
+                Vec::new()
+            })
+    }
+
+    async fn insert_new(&self, logins: &[(NewDeviceLogin, String)]) -> Result<Vec<bool>, String> {
+        let inserted = async {
+            let mut tx = self.pool.begin().await?;
+            let mut inserted = Vec::with_capacity(logins.len());
+            for (login, created_at) in logins {
+                let result = sqlx::query(
+                    r#"
+                    INSERT INTO device_login (user_id, session_id, name, email, device_id,
+                                              login_provider, login_status, ip_address,
+                                              location, isp, created_at)
+                    SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11::timestamptz
+                    WHERE NOT EXISTS (
+                        SELECT 1 FROM device_login
+                        WHERE user_id = $1
+                          AND created_at = $11::timestamptz
+                          AND login_status = $7
+                    );
+                    "#,
+                )
+                .bind(&login.user_id)
+                .bind(&login.session_id)
+                .bind(&login.name)
+                .bind(&login.email)
+                .bind(&login.device_id)
+                .bind(&login.login_provider)
+                .bind(login.login_status)
+                .bind(&login.ip_address)
+                .bind(&login.location)
+                .bind(&login.isp)
+                .bind(created_at)
+                .execute(&mut *tx)
+                .await?;
+                inserted.push(result.rows_affected() > 0);
+            }
+            tx.commit().await?;
+            Ok::<Vec<bool>, sqlx::Error>(inserted)
+        }
+        .await;
+
+        inserted.map_err(|err| {
+            log::error!("PgDeviceLoginDatabase::insert_new: {err}");
+            err.to_string()
+        })
+    }
+
+    async fn device_login_of_user(&self, user_id: &str) -> Vec<DeviceLogin> {
+        sqlx::query_as::<_, DeviceLogin>(
+            r#"
+            SELECT id, user_id, name, email, device_id,
+                   login_status, ip_address, location, isp, created_at
+            FROM device_login
+            WHERE user_id = $1
+            ORDER BY created_at ASC;
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::device_login_of_user: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn emails_of_user(&self, user_id: &str) -> Vec<String> {
+        sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT DISTINCT email
+            FROM device_login
+            WHERE user_id = $1 AND coalesce(email, '') <> '';
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::emails_of_user: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn pseudonymize_user(&self, user_id: &str, pseudonym: &str) -> Result<u64, String> {
+        sqlx::query(
+            r#"
+            UPDATE device_login
+            SET user_id = $1, session_id = '', name = '', email = '', device_id = '',
+                ip_address = '', location = '', isp = ''
+            WHERE user_id = $2;
+            "#,
+        )
+        .bind(pseudonym)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|err| {
+            log::error!("PgDeviceLoginDatabase::pseudonymize_user: {err}");
+            err.to_string()
+        })
+    }
+
+    async fn delete_user(&self, user_id: &str) -> Result<u64, String> {
+        sqlx::query("DELETE FROM device_login WHERE user_id = $1;")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(|err| {
+                log::error!("PgDeviceLoginDatabase::delete_user: {err}");
+                err.to_string()
+            })
+    }
+
+    async fn count_to_anonymize(&self, cutoff_rfc3339: &str) -> i64 {
+        sqlx::query_scalar::<_, i64>(&format!(
+            "SELECT COUNT(*) FROM device_login WHERE {PENDING_ANONYMIZATION};"
+        ))
+        .bind(cutoff_rfc3339)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::count_to_anonymize: {err}");
+            0
+        })
+    }
+
+    async fn to_anonymize(&self, cutoff_rfc3339: &str, limit: u64) -> Vec<(i64, String)> {
+        sqlx::query_as::<_, (i64, String)>(&format!(
+            r#"
+            SELECT id, coalesce(ip_address, '')
+            FROM device_login
+            WHERE {PENDING_ANONYMIZATION}
+            ORDER BY id
+            LIMIT $2;
+            "#
+        ))
+        .bind(cutoff_rfc3339)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::to_anonymize: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn anonymize(&self, masked: &[(i64, String)]) -> u64 {
+        let anonymized = async {
+            let mut tx = self.pool.begin().await?;
+            let mut anonymized = 0;
+            for (login_id, ip_address) in masked {
+                anonymized += sqlx::query(
+                    r#"
+                    UPDATE device_login
+                    SET ip_address = $1, isp = '', location = '', anonymized_at = now()
+                    WHERE id = $2;
+                    "#,
+                )
+                .bind(ip_address)
+                .bind(login_id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            }
+            tx.commit().await?;
+            Ok::<u64, sqlx::Error>(anonymized)
+        }
+        .await;
+
+        anonymized.unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::anonymize: {err}");
+            0
+        })
+    }
+
+    async fn count_expired(&self, cutoff_rfc3339: &str) -> i64 {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM device_login WHERE created_at < $1::timestamptz;",
+        )
+        .bind(cutoff_rfc3339)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::count_expired: {err}");
+            0
+        })
+    }
+
+    async fn expired_ids(&self, cutoff_rfc3339: &str, limit: u64) -> Vec<i64> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT id FROM device_login
+            WHERE created_at < $1::timestamptz
+            ORDER BY id LIMIT $2;
+            "#,
+        )
+        .bind(cutoff_rfc3339)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::expired_ids: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn delete_by_ids(&self, ids: &[i64]) -> u64 {
+        if ids.is_empty() {
+            return 0;
+        }
+
+        let mut query = QueryBuilder::<Postgres>::new("DELETE FROM device_login WHERE id IN (");
+        let mut separated = query.separated(", ");
+        for id in ids {
+            separated.push_bind(id);
+        }
+        query.push(");");
+
+        query
+            .build()
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected())
+            .unwrap_or_else(|err| {
+                log::error!("PgDeviceLoginDatabase::delete_by_ids: {err}");
+                0
+            })
+    }
+
+    async fn quarantined(&self) -> Vec<QuarantinedLogin> {
+        sqlx::query_as::<_, QuarantinedLogin>(&format!(
+            r#"
+            SELECT {QUARANTINED_COLUMNS}
+            FROM device_login_quarantine
+            ORDER BY login_id;
+            "#
+        ))
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::quarantined: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn quarantined_of_user(&self, user_id: &str) -> Vec<QuarantinedLogin> {
+        sqlx::query_as::<_, QuarantinedLogin>(&format!(
+            r#"
+            SELECT {QUARANTINED_COLUMNS}
+            FROM device_login_quarantine
+            WHERE user_id = $1
+            ORDER BY login_id;
+            "#
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::quarantined_of_user: {err}");
+            Vec::new()
+        })
+    }
+
+    async fn delete_quarantined_of_user(&self, user_id: &str) -> Result<u64, String> {
+        sqlx::query("DELETE FROM device_login_quarantine WHERE user_id = $1;")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(|err| {
+                log::error!("PgDeviceLoginDatabase::delete_quarantined_of_user: {err}");
+                err.to_string()
+            })
+    }
+
+    async fn count_quarantined_to_anonymize(&self, cutoff_rfc3339: &str) -> i64 {
+        sqlx::query_scalar::<_, i64>(&format!(
+            "SELECT COUNT(*) FROM device_login_quarantine WHERE {QUARANTINED_PENDING_ANONYMIZATION};"
+        ))
+        .bind(cutoff_rfc3339)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::count_quarantined_to_anonymize: {err}");
+            0
+        })
+    }
+
+    async fn anonymize_quarantined(&self, cutoff_rfc3339: &str) -> u64 {
+        sqlx::query(&format!(
+            r#"
+            UPDATE device_login_quarantine SET ip_address = '', location = '', isp = ''
+            WHERE {QUARANTINED_PENDING_ANONYMIZATION};
+            "#
+        ))
+        .bind(cutoff_rfc3339)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected())
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::anonymize_quarantined: {err}");
+            0
+        })
+    }
+
+    async fn count_quarantined_expired(&self, cutoff_rfc3339: &str) -> i64 {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM device_login_quarantine WHERE quarantined_at < $1;",
+        )
+        .bind(cutoff_rfc3339)
+        .fetch_one(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("PgDeviceLoginDatabase::count_quarantined_expired: {err}");
+            0
+        })
+    }
+
+    async fn delete_quarantined_expired(&self, cutoff_rfc3339: &str) -> u64 {
+        sqlx::query("DELETE FROM device_login_quarantine WHERE quarantined_at < $1;")
+            .bind(cutoff_rfc3339)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected())
+            .unwrap_or_else(|err| {
+                log::error!("PgDeviceLoginDatabase::delete_quarantined_expired: {err}");
+                0
+            })
+    }
+}
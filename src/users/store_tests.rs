@@ -0,0 +1,859 @@
+//! Runs the same scenarios against every `DeviceLoginStore`, so the SQLite
+//! and PostgreSQL queries are held to the same behaviour. Each scenario gets
+//! a fresh store. The PostgreSQL tests are ignored by default as they need a
+//! scratch database, run them with
+//! `TEST_POSTGRES_URL=postgres://... cargo test -- --include-ignored`.
+
+use chrono::{Duration, Utc};
+use sqlx::{PgPool, SqlitePool, postgres::PgPoolOptions};
+
+use super::{
+    device_login::{
+        DeviceLoginDatabase, DeviceLoginStore, LOGIN_PROVIDER_IMPORT, LoginFilter, LoginSort,
+        LoginStatus, NewDeviceLogin, OpenSession, PgDeviceLoginDatabase, canonical_time,
+    },
+    sqlite,
+};
+use crate::db;
+
+const DAY_1: &str = "2026-01-05T00:00:00.000Z";
+const DAY_2: &str = "2026-01-06T00:00:00.000Z";
+const DAY_3: &str = "2026-01-07T00:00:00.000Z";
+
+/// Declares one test per scenario and backend. PostgreSQL tests each run in
+/// a schema of their own so they can run side by side.
+macro_rules! store_tests {
+    ($($scenario:ident),* $(,)?) => {
+        mod sqlite_store {
+            $(
+                #[tokio::test]
+                async fn $scenario() {
+                    let pool = crate::db::init_db("sqlite::memory:").await;
+                    super::$scenario(&super::DeviceLoginDatabase::new(pool).await).await;
+                }
+            )*
+        }
+
+        mod postgres_store {
+            $(
+                #[tokio::test]
+                #[ignore = "needs a scratch PostgreSQL database in TEST_POSTGRES_URL"]
+                async fn $scenario() {
+                    let scratch = super::Scratch::new(stringify!($scenario)).await;
+                    super::$scenario(&scratch.store().await).await;
+                    scratch.remove().await;
+                }
+            )*
+        }
+    };
+}
+
+store_tests!(
+    listings_and_counts,
+    search_and_sort,
+    admin_filter,
+    incremental_reads,
+    sessions_and_anomaly_inputs,
+    import_skips_existing,
+    retention,
+    erasure,
+    times_in_utc,
+    statuses_read_back,
+    break_keeps_session_open,
+    backfilled_history,
+    imported_history_is_not_live,
+);
+
+/// A schema of its own in the `TEST_POSTGRES_URL` database.
+struct Scratch {
+    pool: PgPool,
+    url: String,
+    schema: String,
+}
+
+impl Scratch {
+    async fn new(name: &str) -> Self {
+        let base = std::env::var("TEST_POSTGRES_URL")
+            .expect("TEST_POSTGRES_URL must point at a scratch PostgreSQL database");
+        let schema = format!("store_test_{name}");
+        let pool = PgPoolOptions::new()
+            .connect(&base)
+            .await
+            .expect("Failed to connect to database");
+        let create = format!("DROP SCHEMA IF EXISTS {schema} CASCADE; CREATE SCHEMA {schema};");
+        sqlx::raw_sql(&create)
+            .execute(&pool)
+            .await
+            .expect("Failed to create schema");
+
+        let separator = if base.contains('?') { '&' } else { '?' };
+        let url = format!("{base}{separator}options=-c%20search_path%3D{schema}");
+        Self { pool, url, schema }
+    }
+
+    async fn store(&self) -> PgDeviceLoginDatabase {
+        PgDeviceLoginDatabase::connect(&self.url).await
+    }
+
+    async fn remove(self) {
+        let drop = format!("DROP SCHEMA {} CASCADE;", self.schema);
+        sqlx::raw_sql(&drop)
+            .execute(&self.pool)
+            .await
+            .expect("Failed to drop schema");
+        self.pool.close().await;
+    }
+}
+
+fn login(user_id: &str, name: &str, status: LoginStatus) -> NewDeviceLogin {
+    NewDeviceLogin {
+        user_id: user_id.to_string(),
+        session_id: format!("{user_id}-session"),
+        name: name.to_string(),
+        email: format!("{user_id}@example.com"),
+        device_id: "front-door".to_string(),
+        login_provider: "google".to_string(),
+        login_status: status,
+        ip_address: "10.1.2.3".to_string(),
+        location: "Manila".to_string(),
+        isp: "PLDT".to_string(),
+    }
+}
+
+/// Ids of the events `seed` inserts.
+struct Seeded {
+    alice_in: i64,
+    alice_out: i64,
+    alice_open: i64,
+}
+
+/// Alice clocks in and out on day 1 and in again on day 2, Bob (listed as
+/// Robert) clocks in on day 1 and never out.
+async fn seed(store: &dyn DeviceLoginStore) -> Seeded {
+    let alice_in = store
+        .insert(
+            &login("alice", "Alice", LoginStatus::In),
+            "2026-01-05T08:00:00.000Z",
+        )
+        .await
+        .unwrap();
+    let alice_out = store
+        .insert(
+            &login("alice", "Alice", LoginStatus::Out),
+            "2026-01-05T17:00:00.000Z",
+        )
+        .await
+        .unwrap();
+    store
+        .insert(
+            &login("bob", "Robert", LoginStatus::In),
+            "2026-01-05T09:00:00.000Z",
+        )
+        .await
+        .unwrap();
+    let alice_open = store
+        .insert(
+            &login("alice", "Alice", LoginStatus::In),
+            "2026-01-06T08:00:00.000Z",
+        )
+        .await
+        .unwrap();
+    assert!(alice_in < alice_out && alice_out < alice_open);
+
+    Seeded {
+        alice_in,
+        alice_out,
+        alice_open,
+    }
+}
+
+async fn listings_and_counts(store: &dyn DeviceLoginStore) {
+    let Seeded {
+        alice_in,
+        alice_out,
+        alice_open,
+    } = seed(store).await;
+
+    assert_eq!(store.last_id().await, alice_open);
+    assert_eq!(store.size(None, "").await, 4);
+    assert_eq!(store.size(Some(r#"["bob"]"#), "").await, 1);
+    assert_eq!(store.size_per_user("alice", "").await, 3);
+    let history = store
+        .device_login_history(10, 1, None, "", LoginSort::default())
+        .await;
+    assert_eq!(history.len(), 4);
+    assert_eq!(history[0].id, alice_open);
+    assert_eq!(
+        store
+            .device_login_history(3, 2, None, "", LoginSort::default())
+            .await
+            .len(),
+        1
+    );
+    assert_eq!(
+        store
+            .device_login_history_per_user("alice", 10, 1, "", LoginSort::default())
+            .await
+            .len(),
+        3
+    );
+    assert_eq!(
+        store
+            .device_login_by_ids(&[alice_in, alice_out])
+            .await
+            .len(),
+        2
+    );
+    let of_alice = store.device_login_of_user("alice").await;
+    assert_eq!(of_alice.first().map(|login| login.id), Some(alice_in));
+    assert_eq!(store.device_login_between(DAY_1, DAY_2).await.len(), 3);
+    let networks = store.clock_ins_by_network(DAY_1, DAY_3, None, 10).await;
+    assert_eq!(networks.len(), 1);
+    assert_eq!((networks[0].clock_ins, networks[0].users), (3, 2));
+    let of_bob = store
+        .clock_ins_by_network(DAY_1, DAY_3, Some(r#"["bob"]"#), 10)
+        .await;
+    assert_eq!(of_bob[0].clock_ins, 1);
+}
+
+/// Search and sort, with the sort parsed as the timekeeping page does.
+async fn search_and_sort(store: &dyn DeviceLoginStore) {
+    let Seeded { alice_open, .. } = seed(store).await;
+
+    assert_eq!(store.size(None, "BOB@").await, 1);
+    assert_eq!(store.size_per_user("alice", "10.1.2").await, 3);
+    assert_eq!(store.size_per_user("alice", "Cebu").await, 0);
+    // Wildcards in the search are matched literally.
+    assert_eq!(store.size(None, "%").await, 0);
+    assert_eq!(store.size_per_user("alice", "1_.").await, 0);
+    assert_eq!(store.size_per_user("alice", "\\").await, 0);
+    let by_name = LoginSort {
+        column: "name".parse().unwrap(),
+        order: "asc".parse().unwrap(),
+    };
+    let sorted = store.device_login_history(10, 1, None, "", by_name).await;
+    assert_eq!(sorted[0].id, alice_open);
+    assert_eq!(sorted[3].user_id, "bob");
+    let searched = store
+        .device_login_history(10, 1, None, "bob@", by_name)
+        .await;
+    assert_eq!(searched.len(), 1);
+}
+
+/// Admin filter, with the user lists resolved by the caller.
+async fn admin_filter(store: &dyn DeviceLoginStore) {
+    seed(store).await;
+
+    let everyone = LoginFilter {
+        start_rfc3339: DAY_1,
+        end_rfc3339: DAY_3,
+        ..LoginFilter::default()
+    };
+    assert_eq!(
+        store
+            .admin_filter_login_status_by_name_and_date_count(&everyone)
+            .await,
+        4
+    );
+    let team = LoginFilter {
+        team_members: Some(r#"["bob"]"#),
+        ..everyone
+    };
+    assert_eq!(
+        store
+            .admin_filter_login_status_by_name_and_date_count(&team)
+            .await,
+        1
+    );
+    let by_name = LoginFilter {
+        name: "ali",
+        ..everyone
+    };
+    assert_eq!(
+        store
+            .admin_filter_login_status_by_name_and_date(10, 1, &by_name, LoginSort::default())
+            .await
+            .len(),
+        3
+    );
+    assert_eq!(
+        store
+            .admin_filter_login_status_by_name_and_date_count(&LoginFilter {
+                name: "a%",
+                ..everyone
+            })
+            .await,
+        0
+    );
+    // Bob is listed as Robert on his events but renamed in the directory.
+    let searched = LoginFilter {
+        search: "front-door",
+        ..team
+    };
+    assert_eq!(
+        store
+            .admin_filter_login_status_by_name_and_date_count(&searched)
+            .await,
+        1
+    );
+    let renamed = LoginFilter {
+        name: "bob",
+        named_users: Some(r#"["bob"]"#),
+        ..everyone
+    };
+    assert_eq!(
+        store
+            .admin_filter_login_status_by_name_and_date_count(&renamed)
+            .await,
+        1
+    );
+    let hidden = LoginFilter {
+        visible_users: Some(r#"["alice"]"#),
+        ..team
+    };
+    assert_eq!(
+        store
+            .admin_filter_login_status_by_name_and_date_count(&hidden)
+            .await,
+        0
+    );
+    assert_eq!(
+        store
+            .none_admin_filter_login_status_by_name_and_date("alice", 10, 1, "", DAY_1, DAY_2)
+            .await
+            .len(),
+        2
+    );
+    assert_eq!(
+        store
+            .none_admin_filter_login_status_by_name_and_date_count("alice", DAY_1, DAY_3)
+            .await,
+        3
+    );
+}
+
+async fn incremental_reads(store: &dyn DeviceLoginStore) {
+    let Seeded {
+        alice_in,
+        alice_out,
+        ..
+    } = seed(store).await;
+
+    assert_eq!(store.device_login_after(alice_in, 10).await.len(), 3);
+    assert_eq!(store.device_login_after(alice_in, 1).await[0].id, alice_out);
+    assert_eq!(
+        store
+            .device_login_after_per_user("alice", alice_in, 10)
+            .await
+            .len(),
+        2
+    );
+}
+
+async fn sessions_and_anomaly_inputs(store: &dyn DeviceLoginStore) {
+    let Seeded {
+        alice_in,
+        alice_out,
+        alice_open,
+    } = seed(store).await;
+
+    assert_eq!(
+        store.first_clock_in_between("alice", DAY_1, DAY_2).await,
+        Some(alice_in)
+    );
+    assert_eq!(store.open_sessions_before(None, DAY_3).await.len(), 2);
+    assert!(
+        store
+            .insert_auto_out(alice_open, "2026-01-06T20:00:00.000Z")
+            .await
+    );
+    let open = store.open_sessions_before(None, DAY_3).await;
+    assert_eq!(open.len(), 1);
+    assert_eq!(open[0].login.user_id, "bob");
+    // Only sessions whose latest event falls in the window.
+    let window = store
+        .open_sessions_before(Some("2026-01-05T08:30:00.000Z"), DAY_3)
+        .await;
+    assert_eq!(window.len(), 1);
+    assert!(
+        store
+            .open_sessions_before(Some("2026-01-05T09:30:00.000Z"), DAY_3)
+            .await
+            .is_empty()
+    );
+    let auto_out = store.last_id().await;
+    let previous = store.previous_login("alice", auto_out).await.unwrap();
+    assert_eq!(previous.id, alice_open);
+    let previous = store.previous_login("alice", alice_open).await.unwrap();
+    assert_eq!(previous.id, alice_out);
+    let (devices, locations, isps) = store.known_footprint("alice", alice_open).await;
+    assert_eq!(devices, ["front-door"]);
+    assert_eq!(locations, ["Manila"]);
+    assert_eq!(isps, ["PLDT"]);
+    assert_eq!(
+        store
+            .other_clock_ins_on_device("front-door", "alice", DAY_1, DAY_2)
+            .await,
+        ["bob"]
+    );
+}
+
+async fn import_skips_existing(store: &dyn DeviceLoginStore) {
+    seed(store).await;
+
+    let imported = store
+        .insert_new(&[
+            (
+                login("alice", "Alice", LoginStatus::In),
+                "2026-01-05T08:00:00.000Z".to_string(),
+            ),
+            (
+                login("carol", "Carol", LoginStatus::In),
+                "2026-01-05T07:30:00.000Z".to_string(),
+            ),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(imported, [false, true]);
+    assert_eq!(store.size(None, "").await, 5);
+    assert_eq!(store.emails_of_user("alice").await, ["alice@example.com"]);
+}
+
+/// Anonymize, then delete what expired.
+async fn retention(store: &dyn DeviceLoginStore) {
+    seed(store).await;
+    // An address that merely looks masked is still personal data.
+    let network_only = NewDeviceLogin {
+        ip_address: "10.1.3.0".to_string(),
+        location: String::new(),
+        isp: String::new(),
+        ..login("carol", "Carol", LoginStatus::In)
+    };
+    store
+        .insert(&network_only, "2026-01-05T10:00:00.000Z")
+        .await
+        .unwrap();
+
+    assert_eq!(store.count_to_anonymize(DAY_2).await, 4);
+    // Whatever the masked address looks like, anonymized rows are done.
+    let masked: Vec<(i64, String)> = store
+        .to_anonymize(DAY_2, 3)
+        .await
+        .into_iter()
+        .map(|(id, _)| (id, "10.1.2.77".to_string()))
+        .collect();
+    assert_eq!(masked.len(), 3);
+    assert_eq!(store.anonymize(&masked).await, 3);
+    assert_eq!(store.count_to_anonymize(DAY_2).await, 1);
+    let rest = store.to_anonymize(DAY_2, 10).await;
+    assert_eq!(rest.len(), 1);
+    assert_eq!(rest[0].1, "10.1.3.0");
+    assert_eq!(store.anonymize(&[(rest[0].0, String::new())]).await, 1);
+    assert_eq!(store.count_to_anonymize(DAY_2).await, 0);
+
+    assert_eq!(store.count_expired(DAY_2).await, 4);
+    let expired = store.expired_ids(DAY_2, 3).await;
+    assert_eq!(expired.len(), 3);
+    assert_eq!(store.delete_by_ids(&expired).await, 3);
+    assert_eq!(store.count_expired(DAY_2).await, 1);
+}
+
+async fn erasure(store: &dyn DeviceLoginStore) {
+    seed(store).await;
+
+    assert_eq!(
+        store
+            .pseudonymize_user("alice", "erased-0123")
+            .await
+            .unwrap(),
+        3
+    );
+    assert_eq!(store.size_per_user("alice", "").await, 0);
+    let pseudonymized = store.device_login_of_user("erased-0123").await;
+    assert!(pseudonymized.iter().all(|login| login.email.is_empty()));
+    assert_eq!(store.delete_user("erased-0123").await.unwrap(), 3);
+    assert_eq!(store.size_per_user("erased-0123", "").await, 0);
+    assert_eq!(store.size(None, "").await, 1);
+}
+
+/// Times are stored in UTC whatever offset they come with, and what is not
+/// a time is refused.
+async fn times_in_utc(store: &dyn DeviceLoginStore) {
+    store
+        .insert(
+            &login("dave", "Dave", LoginStatus::In),
+            "2026-01-06T16:30:00+08:00",
+        )
+        .await
+        .unwrap();
+    let dave = store.device_login_of_user("dave").await;
+    assert_eq!(
+        canonical_time(&dave[0].created_at),
+        "2026-01-06T08:30:00.000Z"
+    );
+    assert_eq!(
+        store
+            .first_clock_in_between("dave", "2026-01-06T16:00:00+08:00", DAY_3)
+            .await,
+        Some(dave[0].id)
+    );
+    assert!(
+        store
+            .insert(&login("dave", "Dave", LoginStatus::Out), "last tuesday")
+            .await
+            .is_err()
+    );
+    assert_eq!(store.size_per_user("dave", "").await, 1);
+}
+
+async fn statuses_read_back(store: &dyn DeviceLoginStore) {
+    for status in [
+        LoginStatus::In,
+        LoginStatus::Out,
+        LoginStatus::BreakStart,
+        LoginStatus::BreakEnd,
+        LoginStatus::AutoOut,
+        LoginStatus::Corrected,
+    ] {
+        let id = store
+            .insert(&login("dave", "Dave", status), "2026-01-06T09:00:00.000Z")
+            .await
+            .unwrap();
+        let read = store.device_login_by_ids(&[id]).await;
+        assert_eq!(read[0].login_status, status);
+    }
+    assert!(store.quarantined().await.is_empty());
+}
+
+/// A break keeps the session open, timed from its clock-in.
+async fn break_keeps_session_open(store: &dyn DeviceLoginStore) {
+    for (status, at) in [
+        (LoginStatus::In, "2026-01-06T08:00:00.000Z"),
+        (LoginStatus::BreakStart, "2026-01-06T12:00:00.000Z"),
+    ] {
+        store
+            .insert(&login("erin", "Erin", status), at)
+            .await
+            .unwrap();
+    }
+    let open_of_erin = |open: Vec<OpenSession>| {
+        open.into_iter()
+            .find(|session| session.login.user_id == "erin")
+            .map(|session| {
+                assert_eq!(
+                    canonical_time(&session.clocked_in_at),
+                    "2026-01-06T08:00:00.000Z"
+                );
+                session.login.login_status
+            })
+    };
+    assert_eq!(
+        open_of_erin(store.open_sessions_before(None, DAY_3).await),
+        Some(LoginStatus::BreakStart)
+    );
+    store
+        .insert(
+            &login("erin", "Erin", LoginStatus::BreakEnd),
+            "2026-01-06T13:00:00.000Z",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        open_of_erin(store.open_sessions_before(None, DAY_3).await),
+        Some(LoginStatus::BreakEnd)
+    );
+}
+
+/// History imported after a live clock-out takes its place in time.
+async fn backfilled_history(store: &dyn DeviceLoginStore) {
+    let frank_out = store
+        .insert(
+            &login("frank", "Frank", LoginStatus::Out),
+            "2026-01-06T17:00:00.000Z",
+        )
+        .await
+        .unwrap();
+    store
+        .insert_new(&[(
+            login("frank", "Frank", LoginStatus::In),
+            "2026-01-06T08:00:00.000Z".to_string(),
+        )])
+        .await
+        .unwrap();
+    let frank_in = store.last_id().await;
+    assert!(frank_in > frank_out);
+    assert!(store.open_sessions_before(None, DAY_3).await.is_empty());
+    assert_eq!(
+        store
+            .previous_login("frank", frank_out)
+            .await
+            .map(|login| login.id),
+        Some(frank_in)
+    );
+    assert!(store.previous_login("frank", frank_in).await.is_none());
+
+    // A clock-in recorded live before the backfill is not the earliest.
+    let late_in = store
+        .insert(
+            &login("frank", "Frank", LoginStatus::In),
+            "2026-01-07T09:00:00.000Z",
+        )
+        .await
+        .unwrap();
+    store
+        .insert_new(&[(
+            login("frank", "Frank", LoginStatus::In),
+            "2026-01-07T08:00:00.000Z".to_string(),
+        )])
+        .await
+        .unwrap();
+    let early_in = store.last_id().await;
+    assert!(early_in > late_in);
+    assert_eq!(
+        store
+            .first_clock_in_between("frank", DAY_3, "2026-01-08T00:00:00.000Z")
+            .await,
+        Some(early_in)
+    );
+}
+
+/// Rows as an older version left them, one per kind of `login_status` and
+/// `created_at` seen.
+const LEGACY_ROWS: [(&str, Option<&str>, Option<&str>); 6] = [
+    ("offset", Some("IN"), Some("2026-01-05T16:00:00+08:00")),
+    ("canonical", Some("IN"), Some("2026-01-05T08:00:00.000Z")),
+    ("lower", Some(" out"), Some("2026-01-05T08:00:00.000Z")),
+    ("garbage", Some("IN"), Some("last tuesday")),
+    ("missing", Some("IN"), None),
+    ("unknown", Some("LUNCH"), Some("2026-01-05T08:00:00.000Z")),
+];
+
+/// After the migration every readable row has a canonical UTC time and an
+/// upper case status, and the rest wait in the quarantine.
+async fn check_legacy_rows(store: &dyn DeviceLoginStore) {
+    for (user_id, status) in [
+        ("offset", LoginStatus::In),
+        ("canonical", LoginStatus::In),
+        ("lower", LoginStatus::Out),
+    ] {
+        let logins = store.device_login_of_user(user_id).await;
+        assert_eq!(logins.len(), 1);
+        assert_eq!(logins[0].login_status, status);
+        assert_eq!(
+            canonical_time(&logins[0].created_at),
+            "2026-01-05T08:00:00.000Z"
+        );
+    }
+    let quarantined = store.quarantined().await;
+    let mut users: Vec<&str> = quarantined
+        .iter()
+        .map(|login| login.user_id.as_str())
+        .collect();
+    users.sort();
+    assert_eq!(users, ["garbage", "missing", "unknown"]);
+    assert!(
+        quarantined
+            .iter()
+            .any(|login| login.created_at == "last tuesday")
+    );
+    assert_eq!(store.size(None, "").await, 3);
+}
+
+/// Rows in the quarantine are handed out and erased with the user's other
+/// events, and aged by when they were set aside for retention.
+async fn check_quarantine(store: &dyn DeviceLoginStore) {
+    let garbage = store.quarantined_of_user("garbage").await;
+    assert_eq!(garbage.len(), 1);
+    assert_eq!(garbage[0].ip_address, "10.0.0.7");
+    assert_eq!(store.delete_quarantined_of_user("garbage").await, Ok(1));
+    assert!(store.quarantined_of_user("garbage").await.is_empty());
+
+    let earlier = canonical_time(&(Utc::now() - Duration::days(1)));
+    let later = canonical_time(&(Utc::now() + Duration::days(1)));
+    assert_eq!(store.count_quarantined_to_anonymize(&earlier).await, 0);
+    assert_eq!(store.count_quarantined_to_anonymize(&later).await, 2);
+    assert_eq!(store.anonymize_quarantined(&later).await, 2);
+    assert_eq!(store.count_quarantined_to_anonymize(&later).await, 0);
+    let missing = store.quarantined_of_user("missing").await;
+    assert_eq!(
+        (missing[0].ip_address.as_str(), missing[0].isp.as_str()),
+        ("", "")
+    );
+
+    assert_eq!(store.count_quarantined_expired(&earlier).await, 0);
+    assert_eq!(store.count_quarantined_expired(&later).await, 2);
+    assert_eq!(store.delete_quarantined_expired(&later).await, 2);
+    assert!(store.quarantined().await.is_empty());
+}
+
+#[tokio::test]
+async fn sqlite_legacy_rows() {
+    let path = std::env::temp_dir().join(format!("store-tests-{}.db", std::process::id()));
+    let url = format!("sqlite://{}?mode=rwc", path.display());
+    let pool = SqlitePool::connect(&url)
+        .await
+        .expect("Failed to connect to database");
+    sqlx::query(LEGACY_SQLITE_TABLE)
+        .execute(&pool)
+        .await
+        .expect("Failed to create table");
+    for (user_id, login_status, created_at) in LEGACY_ROWS {
+        sqlx::query(LEGACY_SQLITE_INSERT)
+            .bind(user_id)
+            .bind(login_status)
+            .bind(created_at)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert");
+    }
+    pool.close().await;
+
+    let pool = db::init_db(&url).await;
+    assert_eq!(sqlite::count_legacy_rows(&pool).await, 5);
+    // Until they are migrated, unreadable rows are left out of listings
+    // rather than emptying them.
+    let unmigrated = DeviceLoginDatabase::new(pool.clone()).await;
+    let listed = unmigrated
+        .device_login_history(10, 1, None, "", LoginSort::default())
+        .await;
+    let mut users: Vec<&str> = listed.iter().map(|login| login.user_id.as_str()).collect();
+    users.sort();
+    assert_eq!(users, ["canonical", "offset"]);
+    let legacy = sqlite::normalize_legacy_rows(&pool).await.unwrap();
+    assert_eq!(legacy.quarantined.len(), 3);
+    assert_eq!((legacy.times_rewritten, legacy.statuses_rewritten), (1, 1));
+    assert_eq!(sqlite::count_legacy_rows(&pool).await, 0);
+    let store = DeviceLoginDatabase::new(pool.clone()).await;
+    check_legacy_rows(&store).await;
+    for (user_id, login_status, created_at) in EXTERNAL_ROWS {
+        let inserted = sqlx::query(LEGACY_SQLITE_INSERT)
+            .bind(user_id)
+            .bind(login_status)
+            .bind(created_at)
+            .execute(&pool)
+            .await;
+        assert_eq!(inserted.is_ok(), user_id == "external", "{login_status:?}");
+    }
+    check_external_rows(&store).await;
+    check_quarantine(&store).await;
+    pool.close().await;
+    let _ = std::fs::remove_file(path);
+}
+
+/// Rows the main server may write once the table is migrated: any case and
+/// padding of a known status is accepted, the rest refused.
+const EXTERNAL_ROWS: [(&str, Option<&str>, &str); 3] = [
+    (
+        "external",
+        Some(" break_start "),
+        "2026-01-05T09:00:00.000Z",
+    ),
+    ("refused", Some("LUNCH"), "2026-01-05T09:00:00.000Z"),
+    ("refused", None, "2026-01-05T09:00:00.000Z"),
+];
+
+async fn check_external_rows(store: &dyn DeviceLoginStore) {
+    let logins = store.device_login_of_user("external").await;
+    assert_eq!(logins.len(), 1);
+    assert_eq!(logins[0].login_status, LoginStatus::BreakStart);
+    assert!(store.device_login_of_user("refused").await.is_empty());
+}
+
+const LEGACY_SQLITE_TABLE: &str = r#"
+CREATE TABLE device_login (
+    user_id TEXT NOT NULL, session_id TEXT, name TEXT, email TEXT, device_id TEXT,
+    login_provider TEXT, login_status TEXT, ip_address TEXT, location TEXT, isp TEXT,
+    created_at TEXT
+);
+"#;
+
+const LEGACY_SQLITE_INSERT: &str = r#"
+INSERT INTO device_login (user_id, session_id, name, email, device_id, login_provider,
+                          login_status, ip_address, location, isp, created_at)
+VALUES (?1, '', ?1, '', '', '', ?2, '10.0.0.7', 'Manila', 'PLDT', ?3);
+"#;
+
+/// As the main server writes the table, with a typed `created_at`.
+const EXTERNAL_POSTGRES_INSERT: &str = r#"
+INSERT INTO device_login (user_id, session_id, name, email, device_id, login_provider,
+                          login_status, ip_address, location, isp, created_at)
+VALUES ($1, '', $1, '', '', '', $2, '', '', '', $3::timestamptz);
+"#;
+
+/// Quarantined rows as SQLite's migration leaves them, which PostgreSQL
+/// tables never need.
+const QUARANTINE_POSTGRES_INSERT: &str = r#"
+INSERT INTO device_login_quarantine (login_id, user_id, session_id, name, email, device_id,
+                                     login_provider, login_status, ip_address, location, isp,
+                                     created_at, quarantined_at)
+VALUES ($1, $2, '', $2, '', '', '', $3, '10.0.0.7', 'Manila', 'PLDT', $4, $5);
+"#;
+
+#[tokio::test]
+#[ignore = "needs a scratch PostgreSQL database in TEST_POSTGRES_URL"]
+async fn postgres_external_rows() {
+    let scratch = Scratch::new("external_rows").await;
+    let store = scratch.store().await;
+    let pool = PgPoolOptions::new()
+        .connect(&scratch.url)
+        .await
+        .expect("Failed to connect to database");
+    for (user_id, login_status, created_at) in EXTERNAL_ROWS {
+        let inserted = sqlx::query(EXTERNAL_POSTGRES_INSERT)
+            .bind(user_id)
+            .bind(login_status)
+            .bind(created_at)
+            .execute(&pool)
+            .await;
+        assert_eq!(inserted.is_ok(), user_id == "external", "{login_status:?}");
+    }
+    check_external_rows(&store).await;
+    let quarantined_at = canonical_time(&Utc::now());
+    for (login_id, (user_id, login_status, created_at)) in
+        (1_i64..).zip(LEGACY_ROWS.into_iter().skip(3))
+    {
+        sqlx::query(QUARANTINE_POSTGRES_INSERT)
+            .bind(login_id)
+            .bind(user_id)
+            .bind(login_status)
+            .bind(created_at)
+            .bind(&quarantined_at)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert");
+    }
+    check_quarantine(&store).await;
+    pool.close().await;
+    scratch.remove().await;
+}
+
+/// Jobs reacting to clock events pass over imported punches, and an
+/// imported `IN` leaves nobody on the clock.
+async fn imported_history_is_not_live(store: &dyn DeviceLoginStore) {
+    let Seeded { alice_open, .. } = seed(store).await;
+    let imported = NewDeviceLogin {
+        login_provider: LOGIN_PROVIDER_IMPORT.to_string(),
+        ..login("gina", "Gina", LoginStatus::In)
+    };
+    store
+        .insert_new(&[(imported, "2025-06-02T08:00:00.000Z".to_string())])
+        .await
+        .unwrap();
+    let carl = store
+        .insert(
+            &login("carl", "Carl", LoginStatus::In),
+            "2026-01-06T09:00:00.000Z",
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(store.device_login_after(alice_open, 10).await.len(), 2);
+    let live = store.live_device_login_after(alice_open, 10).await;
+    assert_eq!(
+        live.iter().map(|login| login.id).collect::<Vec<_>>(),
+        [carl]
+    );
+    let open = store.open_sessions_before(None, DAY_3).await;
+    assert!(open.iter().all(|session| session.login.user_id != "gina"));
+    assert_eq!(open.len(), 3);
+}
@@ -1,9 +1,23 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, net::SocketAddr};
 
-use axum::{body::Body, http::Request};
+use axum::{
+    body::{Body, to_bytes},
+    extract::ConnectInfo,
+    http::{Request, StatusCode},
+};
+use chrono::NaiveDate;
 use serde::de::DeserializeOwned;
 use url::form_urlencoded;
 
+const MAX_JSON_BODY_SIZE: usize = 16 * 1024;
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// A `YYYY-MM-DD` day as a query, a form or the command line gives it.
+pub fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value.trim(), DATE_FORMAT)
+        .map_err(|_| format!("{value} is not a YYYY-MM-DD date."))
+}
+
 pub fn extract_url_params<T>(req: &Request<Body>) -> Result<T, String>
 where
     T: DeserializeOwned,
@@ -28,3 +42,32 @@ where
         e.to_string()
     })
 }
+
+/// The request's body read as JSON, or the 400 to answer with.
+pub async fn json_body<T>(request: Request<Body>) -> Result<T, (StatusCode, String)>
+where
+    T: DeserializeOwned,
+{
+    let body = to_bytes(request.into_body(), MAX_JSON_BODY_SIZE)
+        .await
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+    serde_json::from_slice::<T>(&body).map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))
+}
+
+/// The caller's address. Requests come through the main web server, so the
+/// first `X-Forwarded-For` hop wins over the socket's peer address.
+pub fn client_ip(req: &Request<Body>) -> String {
+    req.headers()
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .or_else(|| {
+            req.extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string())
+        })
+        .unwrap_or_default()
+}
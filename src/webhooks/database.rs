@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Sqlite};
+
+pub const DELIVERY_PENDING: &str = "PENDING";
+pub const DELIVERY_DELIVERED: &str = "DELIVERED";
+pub const DELIVERY_FAILED: &str = "FAILED";
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct WebhookSubscription {
+    pub id: i64,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// Comma separated event names, `*` subscribes to everything.
+    pub events: String,
+    pub active: bool,
+    pub created_at: String,
+}
+
+impl WebhookSubscription {
+    pub fn wants(&self, event: &str) -> bool {
+        self.events
+            .split(',')
+            .map(str::trim)
+            .any(|e| e == "*" || e == event)
+    }
+}
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub subscription_id: i64,
+    pub event: String,
+    pub dedupe_key: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i64,
+    pub next_attempt_at: String,
+    pub created_at: String,
+    pub delivered_at: Option<String>,
+}
+
+#[derive(Debug, FromRow, Serialize, Clone, Deserialize)]
+pub struct WebhookAttempt {
+    pub delivery_id: i64,
+    pub attempt: i64,
+    pub response_code: Option<i64>,
+    pub error: Option<String>,
+    pub attempted_at: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct WebhookDatabase {
+    pool: Pool<Sqlite>,
+}
+
+impl WebhookDatabase {
+    pub async fn new(pool: Pool<Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    pub async fn subscriptions(&self) -> Vec<WebhookSubscription> {
+        sqlx::query_as::<_, WebhookSubscription>(
+            r#"
+            SELECT id, url, secret, events, active, created_at
+            FROM webhook_subscription
+            ORDER BY id ASC;
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("WebhookDatabase::subscriptions: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn add_subscription(
+        &self,
+        url: &str,
+        secret: &str,
+        events: &str,
+        created_at: &str,
+    ) -> Result<i64, String> {
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_subscription (url, secret, events, active, created_at)
+            VALUES (?, ?, ?, 1, ?);
+            "#,
+        )
+        .bind(url)
+        .bind(secret)
+        .bind(events)
+        .bind(created_at)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.last_insert_rowid())
+        .map_err(|err| {
+            log::error!("WebhookDatabase::add_subscription: {err}");
+            err.to_string()
+        })
+    }
+
+    /// Deactivates rather than deletes so the delivery log keeps its owner.
+    pub async fn deactivate_subscription(&self, id: i64) -> bool {
+        sqlx::query("UPDATE webhook_subscription SET active = 0 WHERE id = ?;")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or_else(|err| {
+                log::error!("WebhookDatabase::deactivate_subscription: {err}");
+                false
+            })
+    }
+
+    /// Queues a delivery. The same `(subscription, event, dedupe_key)` is only
+    /// ever queued once, so detectors may report an event repeatedly.
+    pub async fn enqueue(
+        &self,
+        subscription_id: i64,
+        event: &str,
+        dedupe_key: &str,
+        payload: &str,
+        now: &str,
+    ) -> bool {
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO webhook_delivery
+                (subscription_id, event, dedupe_key, payload, status, attempts,
+                 next_attempt_at, created_at)
+            VALUES (?, ?, ?, ?, ?, 0, ?, ?);
+            "#,
+        )
+        .bind(subscription_id)
+        .bind(event)
+        .bind(dedupe_key)
+        .bind(payload)
+        .bind(DELIVERY_PENDING)
+        .bind(now)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .unwrap_or_else(|err| {
+            log::error!("WebhookDatabase::enqueue: {err}");
+            false
+        })
+    }
+
+    pub async fn due_deliveries(&self, now: &str, limit: u64) -> Vec<WebhookDelivery> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            SELECT id, subscription_id, event, dedupe_key, payload, status, attempts,
+                   next_attempt_at, created_at, delivered_at
+            FROM webhook_delivery
+            WHERE status = ?
+              AND next_attempt_at <= ?
+            ORDER BY next_attempt_at ASC
+            LIMIT ?;
+            "#,
+        )
+        .bind(DELIVERY_PENDING)
+        .bind(now)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("WebhookDatabase::due_deliveries: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn deliveries(
+        &self,
+        subscription_id: i64,
+        limit_per_page: u64,
+        page_number: u64,
+    ) -> Vec<WebhookDelivery> {
+        let limit = limit_per_page;
+        let offset = limit * (page_number - 1);
+
+        sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            SELECT id, subscription_id, event, dedupe_key, payload, status, attempts,
+                   next_attempt_at, created_at, delivered_at
+            FROM webhook_delivery
+            WHERE subscription_id = ?
+            ORDER BY id DESC
+            LIMIT ? OFFSET ?;
+            "#,
+        )
+        .bind(subscription_id)
+        .bind(limit as i64)
+        .bind(offset as i64)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("WebhookDatabase::deliveries: {err}");
+            Vec::new()
+        })
+    }
+
+    pub async fn attempts(&self, delivery_id: i64) -> Vec<WebhookAttempt> {
+        sqlx::query_as::<_, WebhookAttempt>(
+            r#"
+            SELECT delivery_id, attempt, response_code, error, attempted_at
+            FROM webhook_attempt
+            WHERE delivery_id = ?
+            ORDER BY attempt ASC;
+            "#,
+        )
+        .bind(delivery_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_else(|err| {
+            log::error!("WebhookDatabase::attempts: {err}");
+            Vec::new()
+        })
+    }
+
+    /// Records one attempt in the delivery log and moves the delivery to its
+    /// next state: delivered, failed for good, or rescheduled. Both happen or
+    /// neither, so the log never disagrees with the attempt count.
+    pub async fn record_attempt(
+        &self,
+        delivery: &WebhookDelivery,
+        response_code: Option<i64>,
+        error: Option<&str>,
+        status: &str,
+        next_attempt_at: &str,
+        now: &str,
+    ) {
+        let attempt = delivery.attempts + 1;
+        let delivered_at = (status == DELIVERY_DELIVERED).then_some(now);
+
+        let recorded = async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query(
+                r#"
+                INSERT INTO webhook_attempt
+                    (delivery_id, attempt, response_code, error, attempted_at)
+                VALUES (?, ?, ?, ?, ?);
+                "#,
+            )
+            .bind(delivery.id)
+            .bind(attempt)
+            .bind(response_code)
+            .bind(error)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                r#"
+                UPDATE webhook_delivery
+                SET status = ?, attempts = ?, next_attempt_at = ?, delivered_at = ?
+                WHERE id = ?;
+                "#,
+            )
+            .bind(status)
+            .bind(attempt)
+            .bind(next_attempt_at)
+            .bind(delivered_at)
+            .bind(delivery.id)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await
+        }
+        .await;
+
+        if let Err(err) = recorded {
+            log::error!("WebhookDatabase::record_attempt: {err}");
+        }
+    }
+}
@@ -0,0 +1,476 @@
+pub mod database;
+
+#[cfg(test)]
+mod tests;
+
+use std::{collections::HashMap, time::Duration};
+
+use async_trait::async_trait;
+use axum::{
+    Extension, Json,
+    body::Body,
+    extract::Path,
+    http::{Request, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{SecondsFormat, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    access::{self, Permission},
+    attendance,
+    audit::{self, AuditAction},
+    config::Config,
+    db::Db,
+    events::LoginEvents,
+    scan::{self, LoginScan},
+    users::device_login::{DeviceLogin, LoginStatus},
+    utils,
+};
+use database::{
+    DELIVERY_DELIVERED, DELIVERY_FAILED, DELIVERY_PENDING, WebhookAttempt, WebhookDelivery,
+    WebhookSubscription,
+};
+
+pub const EVENT_CLOCK_IN: &str = "clock_in";
+pub const EVENT_CLOCK_OUT: &str = "clock_out";
+pub const EVENT_BREAK_START: &str = "break_start";
+pub const EVENT_BREAK_END: &str = "break_end";
+pub const EVENT_MISSING_CLOCK_OUT: &str = "missing_clock_out";
+pub const EVENT_LATE_ARRIVAL: &str = "late_arrival";
+const EVENTS: [&str; 6] = [
+    EVENT_CLOCK_IN,
+    EVENT_CLOCK_OUT,
+    EVENT_BREAK_START,
+    EVENT_BREAK_END,
+    EVENT_MISSING_CLOCK_OUT,
+    EVENT_LATE_ARRIVAL,
+];
+
+const SCAN_CURSOR: &str = "webhooks";
+const DELIVERY_BATCH_SIZE: u64 = 50;
+const DELIVERIES_PER_PAGE: u64 = 20;
+const SIGNATURE_HEADER: &str = "X-Timekeeping-Signature";
+const TIMESTAMP_HEADER: &str = "X-Timekeeping-Timestamp";
+const EVENT_HEADER: &str = "X-Timekeeping-Event";
+const DELIVERY_HEADER: &str = "X-Timekeeping-Delivery";
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct Parameters {
+    page: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NewSubscription {
+    url: String,
+    secret: String,
+    events: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DeliveryLog {
+    #[serde(flatten)]
+    delivery: WebhookDelivery,
+    attempt_log: Vec<WebhookAttempt>,
+}
+
+fn now_rfc3339() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Queues the deliveries of each clock event once, from a cursor kept in
+/// the database, so none are lost to a lagging channel or a restart.
+struct WebhookScan {
+    config: Config,
+}
+
+#[async_trait]
+impl LoginScan for WebhookScan {
+    fn cursor(&self) -> &'static str {
+        SCAN_CURSOR
+    }
+
+    async fn visit(&self, db: &mut Db, login: &DeviceLogin) -> Option<u64> {
+        on_device_login(db, &self.config, login).await;
+        Some(0)
+    }
+}
+
+/// Turns new clock events and detected anomalies into queued deliveries and
+/// sends whatever is due. The queue lives in the database, so pending
+/// deliveries survive a restart. Broadcast events only wake the scan up,
+/// it reads the events themselves from `device_login`.
+pub async fn run(mut db: Db, config: Config, events: LoginEvents) {
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.webhook.request_timeout_secs))
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            log::error!("Webhooks disabled, failed to build HTTP client: {err}");
+            return;
+        }
+    };
+    // Events from before webhooks were first started are not sent.
+    if db.scan().cursor(SCAN_CURSOR).await.is_none() {
+        let last_id = db.device_login().last_id().await;
+        db.scan().set_cursor(SCAN_CURSOR, last_id).await;
+    }
+    let scan = WebhookScan {
+        config: config.clone(),
+    };
+    let mut receiver = events.subscribe();
+    let mut interval = tokio::time::interval(Duration::from_secs(
+        config.webhook.poll_interval_secs.max(1),
+    ));
+
+    loop {
+        tokio::select! {
+            received = receiver.recv() => match received {
+                Ok(_) | Err(RecvError::Lagged(_)) => {
+                    scan::run(&scan, &mut db).await;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = interval.tick() => {
+                // Catches events the main server wrote without a broadcast.
+                scan::run(&scan, &mut db).await;
+                detect_missing_clock_outs(&mut db, &config).await;
+                deliver_due(&mut db, &config, &client).await;
+            }
+        }
+    }
+}
+
+async fn on_device_login(db: &mut Db, config: &Config, login: &DeviceLogin) {
+    let data = json!(login);
+    let dedupe_key = login.id.to_string();
+
+    match login.login_status {
+        LoginStatus::In => {
+            publish(db, EVENT_CLOCK_IN, &dedupe_key, &data).await;
+            detect_late_arrival(db, config, login).await;
+        }
+        LoginStatus::Out => publish(db, EVENT_CLOCK_OUT, &dedupe_key, &data).await,
+        LoginStatus::BreakStart => publish(db, EVENT_BREAK_START, &dedupe_key, &data).await,
+        LoginStatus::BreakEnd => publish(db, EVENT_BREAK_END, &dedupe_key, &data).await,
+        _ => {}
+    }
+}
+
+async fn detect_late_arrival(db: &mut Db, config: &Config, login: &DeviceLogin) {
+    let offset = config.shift.offset();
+    let local_date = login.created_at.with_timezone(&offset).date_naive();
+    let (day_start, day_end) = attendance::local_day_bounds(local_date, &offset);
+
+    let first_in = db
+        .device_login()
+        .first_clock_in_between(login.user_id.as_str(), &day_start, &day_end)
+        .await;
+    if first_in != Some(login.id) {
+        return;
+    }
+
+    let due = local_date.and_time(config.shift.start_time())
+        + chrono::Duration::minutes(config.shift.grace_minutes);
+    let arrived = login.created_at.with_timezone(&offset).naive_local();
+    if arrived > due {
+        let minutes_late = (arrived - due).num_minutes() + config.shift.grace_minutes;
+        let data = json!({ "login": login, "minutes_late": minutes_late });
+        let dedupe_key = format!("{}:{}", login.user_id, local_date);
+        publish(db, EVENT_LATE_ARRIVAL, &dedupe_key, &data).await;
+    }
+}
+
+async fn detect_missing_clock_outs(db: &mut Db, config: &Config) {
+    let (oldest, cutoff) = config.shift.missing_clock_out_window(Utc::now());
+    let open = db
+        .device_login()
+        .open_sessions_before(
+            Some(&oldest.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            &cutoff.to_rfc3339_opts(SecondsFormat::Millis, true),
+        )
+        .await;
+
+    for session in open {
+        let data = json!(session.login);
+        publish(
+            db,
+            EVENT_MISSING_CLOCK_OUT,
+            &session.login.id.to_string(),
+            &data,
+        )
+        .await;
+    }
+}
+
+/// Queues `event` for every active subscription that asked for it.
+pub async fn publish(db: &mut Db, event: &str, dedupe_key: &str, data: &serde_json::Value) {
+    let now = now_rfc3339();
+    let payload = json!({
+        "event": event,
+        "occurred_at": now,
+        "data": data,
+    })
+    .to_string();
+
+    for subscription in db.webhook().subscriptions().await {
+        if subscription.active && subscription.wants(event) {
+            let queued = db
+                .webhook()
+                .enqueue(subscription.id, event, dedupe_key, &payload, &now)
+                .await;
+            if queued {
+                log::info!(
+                    "Queued webhook {event} ({dedupe_key}) for subscription {}",
+                    subscription.id
+                );
+            }
+        }
+    }
+}
+
+async fn deliver_due(db: &mut Db, config: &Config, client: &reqwest::Client) {
+    let due = db
+        .webhook()
+        .due_deliveries(&now_rfc3339(), DELIVERY_BATCH_SIZE)
+        .await;
+    if due.is_empty() {
+        return;
+    }
+
+    let subscriptions: HashMap<i64, WebhookSubscription> = db
+        .webhook()
+        .subscriptions()
+        .await
+        .into_iter()
+        .map(|subscription| (subscription.id, subscription))
+        .collect();
+
+    for delivery in due {
+        let now = now_rfc3339();
+        let (response_code, error) = match subscriptions.get(&delivery.subscription_id) {
+            Some(subscription) if subscription.active => {
+                send(client, subscription, &delivery).await
+            }
+            _ => {
+                db.webhook()
+                    .record_attempt(
+                        &delivery,
+                        None,
+                        Some("subscription is inactive"),
+                        DELIVERY_FAILED,
+                        &now,
+                        &now,
+                    )
+                    .await;
+                continue;
+            }
+        };
+
+        let attempts = delivery.attempts + 1;
+        let (status, next_attempt_at) = if error.is_none() {
+            (DELIVERY_DELIVERED, now.clone())
+        } else if attempts >= config.webhook.max_attempts {
+            (DELIVERY_FAILED, now.clone())
+        } else {
+            let delay = backoff_secs(config, attempts);
+            let next = Utc::now() + chrono::Duration::seconds(delay);
+            (
+                DELIVERY_PENDING,
+                next.to_rfc3339_opts(SecondsFormat::Millis, true),
+            )
+        };
+
+        log::info!(
+            "Webhook delivery {} attempt {attempts}: {status} {response_code:?} {error:?}",
+            delivery.id
+        );
+        db.webhook()
+            .record_attempt(
+                &delivery,
+                response_code,
+                error.as_deref(),
+                status,
+                &next_attempt_at,
+                &now,
+            )
+            .await;
+    }
+}
+
+fn backoff_secs(config: &Config, attempts: i64) -> i64 {
+    let exponent = (attempts - 1).clamp(0, 20) as u32;
+    config
+        .webhook
+        .backoff_base_secs
+        .saturating_mul(2_i64.pow(exponent))
+        .min(config.webhook.backoff_max_secs)
+}
+
+/// The signature is `sha256=` followed by the hex HMAC of
+/// `"{timestamp}.{body}"` keyed with the subscription secret.
+fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+async fn send(
+    client: &reqwest::Client,
+    subscription: &WebhookSubscription,
+    delivery: &WebhookDelivery,
+) -> (Option<i64>, Option<String>) {
+    let timestamp = Utc::now().timestamp();
+
+    let result = client
+        .post(subscription.url.as_str())
+        .header("Content-Type", "application/json")
+        .header(
+            SIGNATURE_HEADER,
+            sign(&subscription.secret, timestamp, &delivery.payload),
+        )
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(EVENT_HEADER, delivery.event.as_str())
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+
+    match result {
+        Ok(response) if response.status().is_success() => {
+            (Some(response.status().as_u16() as i64), None)
+        }
+        Ok(response) => (
+            Some(response.status().as_u16() as i64),
+            Some(format!("Unexpected status {}", response.status())),
+        ),
+        Err(err) => (None, Some(err.to_string())),
+    }
+}
+
+pub async fn list_subscriptions(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    if let Err(rejection) = access::authorize(&mut db, &request, Permission::ManageConfig).await {
+        return rejection.into_response();
+    }
+
+    Json(db.webhook().subscriptions().await).into_response()
+}
+
+pub async fn create_subscription(
+    Extension(mut db): Extension<Db>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageConfig).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let new = match utils::json_body::<NewSubscription>(request).await {
+        Ok(new) => new,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if url::Url::parse(&new.url).is_err() {
+        return (StatusCode::BAD_REQUEST, "Invalid url.").into_response();
+    }
+    if new.secret.is_empty() {
+        return (StatusCode::BAD_REQUEST, "A signing secret is required.").into_response();
+    }
+    if let Some(unknown) = new
+        .events
+        .iter()
+        .find(|event| *event != "*" && !EVENTS.contains(&event.as_str()))
+    {
+        return (StatusCode::BAD_REQUEST, format!("Unknown event {unknown}.")).into_response();
+    }
+
+    match db
+        .webhook()
+        .add_subscription(&new.url, &new.secret, &new.events.join(","), &now_rfc3339())
+        .await
+    {
+        Ok(id) => {
+            log::info!("{} added webhook {id} for {}", principal.user_id, new.url);
+            // The secret stays out of the audit log.
+            audit::record(
+                &mut db,
+                &principal,
+                AuditAction::Create,
+                &format!("webhook {id}"),
+                &json!({ "url": new.url, "events": new.events }),
+                1,
+            )
+            .await;
+            (StatusCode::CREATED, Json(json!({ "id": id }))).into_response()
+        }
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err).into_response(),
+    }
+}
+
+pub async fn delete_subscription(
+    Extension(mut db): Extension<Db>,
+    Path(id): Path<i64>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    let principal = match access::authorize(&mut db, &request, Permission::ManageConfig).await {
+        Ok(principal) => principal,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    if db.webhook().deactivate_subscription(id).await {
+        log::info!("{} deactivated webhook {id}", principal.user_id);
+        audit::record(
+            &mut db,
+            &principal,
+            AuditAction::Delete,
+            &format!("webhook {id}"),
+            &(),
+            1,
+        )
+        .await;
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        (StatusCode::NOT_FOUND, "No such webhook.").into_response()
+    }
+}
+
+pub async fn list_deliveries(
+    Extension(mut db): Extension<Db>,
+    Path(id): Path<i64>,
+    request: Request<Body>,
+) -> axum::response::Response {
+    if let Err(rejection) = access::authorize(&mut db, &request, Permission::ViewReports).await {
+        return rejection.into_response();
+    }
+    let params = utils::extract_url_params::<Parameters>(&request).unwrap_or_default();
+    let page = params
+        .page
+        .and_then(|page| page.parse::<u64>().ok())
+        .unwrap_or(1)
+        .max(1);
+
+    let deliveries = db.webhook().deliveries(id, DELIVERIES_PER_PAGE, page).await;
+    let mut log = Vec::with_capacity(deliveries.len());
+    for delivery in deliveries {
+        let attempt_log = db.webhook().attempts(delivery.id).await;
+        log.push(DeliveryLog {
+            delivery,
+            attempt_log,
+        });
+    }
+
+    Json(log).into_response()
+}
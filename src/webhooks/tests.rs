@@ -0,0 +1,120 @@
+//! Sends deliveries to a local HTTP stub, checking what a subscriber sees:
+//! the signature over the body and a retry queued when it answers 500.
+
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    Router,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+};
+use chrono::{DateTime, Utc};
+use hmac::Mac;
+use tokio::net::TcpListener;
+
+use super::{
+    DELIVERY_PENDING, EVENT_HEADER, HmacSha256, SIGNATURE_HEADER, TIMESTAMP_HEADER, WebhookScan,
+    deliver_due,
+};
+use crate::{
+    config::Config,
+    db::{self, Db},
+    scan,
+    users::device_login::{LoginStatus, NewDeviceLogin, canonical_time},
+};
+
+const SECRET: &str = "stub-secret";
+
+/// Every request the stub received, which it answers with a 500.
+type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+async fn receive(State(received): State<Received>, headers: HeaderMap, body: String) -> StatusCode {
+    received.lock().unwrap().push((headers, body));
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+async fn serve_stub() -> (String, Received) {
+    let received = Received::default();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let app = Router::new()
+        .route("/hook", post(receive))
+        .with_state(received.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    (url, received)
+}
+
+fn clock_in(user_id: &str) -> NewDeviceLogin {
+    NewDeviceLogin {
+        user_id: user_id.to_string(),
+        session_id: format!("{user_id}-session"),
+        name: user_id.to_string(),
+        email: format!("{user_id}@example.com"),
+        device_id: "front-door".to_string(),
+        login_provider: "google".to_string(),
+        login_status: LoginStatus::In,
+        ip_address: "10.1.2.3".to_string(),
+        location: "Manila".to_string(),
+        isp: "PLDT".to_string(),
+    }
+}
+
+async fn open_db(config: &Config) -> Db {
+    let pool = db::init_db("sqlite::memory:").await;
+    db::open(&pool, config).await
+}
+
+#[tokio::test]
+async fn signed_delivery_is_retried_after_a_server_error() {
+    let config = Config::default();
+    let mut db = open_db(&config).await;
+    let client = reqwest::Client::new();
+    let (url, received) = serve_stub().await;
+    let subscription = db
+        .webhook()
+        .add_subscription(&url, SECRET, "clock_in", "2026-01-05T00:00:00.000Z")
+        .await
+        .unwrap();
+
+    // Queued from the stored event by the cursor scan, not a broadcast.
+    let now = Utc::now();
+    db.device_login()
+        .insert(&clock_in("alice"), &canonical_time(&now))
+        .await
+        .unwrap();
+    let scan = WebhookScan {
+        config: config.clone(),
+    };
+    scan::run(&scan, &mut db).await;
+    scan::run(&scan, &mut db).await;
+    let deliveries = db.webhook().deliveries(subscription, 20, 1).await;
+    assert_eq!(deliveries.len(), 1);
+
+    deliver_due(&mut db, &config, &client).await;
+    let requests = received.lock().unwrap().clone();
+    assert_eq!(requests.len(), 1);
+    let (headers, body) = &requests[0];
+    assert_eq!(headers[EVENT_HEADER], "clock_in");
+    let timestamp = headers[TIMESTAMP_HEADER].to_str().unwrap();
+    let mut mac = HmacSha256::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+    assert_eq!(
+        headers[SIGNATURE_HEADER].to_str().unwrap(),
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    );
+    assert!(body.contains("\"user_id\":\"alice\""));
+
+    let delivery = &db.webhook().deliveries(subscription, 20, 1).await[0];
+    assert_eq!(delivery.status, DELIVERY_PENDING);
+    assert_eq!(delivery.attempts, 1);
+    let retry_at = DateTime::parse_from_rfc3339(&delivery.next_attempt_at).unwrap();
+    let backoff = retry_at.with_timezone(&Utc) - now;
+    assert!(backoff.num_seconds() >= config.webhook.backoff_base_secs - 1);
+    let attempts = db.webhook().attempts(delivery.id).await;
+    assert_eq!(attempts[0].response_code, Some(500));
+
+    // Not sent again before the backoff is up.
+    deliver_due(&mut db, &config, &client).await;
+    assert_eq!(received.lock().unwrap().len(), 1);
+}